@@ -0,0 +1,5 @@
+-- Encrypted keys cannot be decrypted in SQL, they have to be entered again
+ALTER TABLE server_config DROP COLUMN api_key_encrypted;
+UPDATE server_config SET api_key_plaintext = '' WHERE api_key_plaintext IS NULL;
+ALTER TABLE server_config ALTER COLUMN api_key_plaintext SET NOT NULL;
+ALTER TABLE server_config RENAME COLUMN api_key_plaintext TO api_key;
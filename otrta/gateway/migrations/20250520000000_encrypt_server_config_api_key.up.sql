@@ -0,0 +1,4 @@
+-- Keep the legacy plaintext column until the gateway has encrypted it on startup
+ALTER TABLE server_config RENAME COLUMN api_key TO api_key_plaintext;
+ALTER TABLE server_config ALTER COLUMN api_key_plaintext DROP NOT NULL;
+ALTER TABLE server_config ADD COLUMN api_key_encrypted TEXT;
@@ -0,0 +1,9 @@
+-- Encrypted tokens cannot be decrypted in SQL and are dropped
+DROP INDEX IF EXISTS transactions_token_fingerprint_idx;
+ALTER TABLE transactions DROP COLUMN keyset_ids;
+ALTER TABLE transactions DROP COLUMN mint_url;
+ALTER TABLE transactions DROP COLUMN token_encrypted;
+ALTER TABLE transactions DROP COLUMN token_fingerprint;
+UPDATE transactions SET token_plaintext = '' WHERE token_plaintext IS NULL;
+ALTER TABLE transactions ALTER COLUMN token_plaintext SET NOT NULL;
+ALTER TABLE transactions RENAME COLUMN token_plaintext TO token;
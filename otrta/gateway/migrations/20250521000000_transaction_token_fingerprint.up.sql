@@ -0,0 +1,9 @@
+-- Replace spendable tokens with a fingerprint, the raw token is only kept encrypted
+ALTER TABLE transactions RENAME COLUMN token TO token_plaintext;
+ALTER TABLE transactions ALTER COLUMN token_plaintext DROP NOT NULL;
+ALTER TABLE transactions ADD COLUMN token_fingerprint TEXT;
+ALTER TABLE transactions ADD COLUMN token_encrypted TEXT;
+ALTER TABLE transactions ADD COLUMN mint_url TEXT;
+ALTER TABLE transactions ADD COLUMN keyset_ids TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX transactions_token_fingerprint_idx ON transactions (token_fingerprint);
@@ -0,0 +1,15 @@
+DROP INDEX IF EXISTS credits_created_at_idx;
+DROP INDEX IF EXISTS transactions_created_at_idx;
+
+ALTER TABLE credits DROP COLUMN mint_url;
+ALTER TABLE credits DROP COLUMN unit;
+ALTER TABLE credits ADD COLUMN amount TEXT;
+UPDATE credits SET amount = (amount_msat / 1000)::TEXT;
+ALTER TABLE credits ALTER COLUMN amount SET NOT NULL;
+ALTER TABLE credits DROP COLUMN amount_msat;
+
+ALTER TABLE transactions DROP COLUMN unit;
+ALTER TABLE transactions ADD COLUMN amount TEXT;
+UPDATE transactions SET amount = (amount_msat / 1000)::TEXT;
+ALTER TABLE transactions ALTER COLUMN amount SET NOT NULL;
+ALTER TABLE transactions DROP COLUMN amount_msat;
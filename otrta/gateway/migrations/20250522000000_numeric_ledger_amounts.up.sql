@@ -0,0 +1,24 @@
+-- Store amounts as integer millisatoshis with an explicit unit and mint per row
+ALTER TABLE transactions ADD COLUMN amount_msat BIGINT;
+UPDATE transactions
+SET amount_msat = CASE
+    WHEN trim(amount) ~ '^-?[0-9]+(\.[0-9]+)?$' THEN round(trim(amount)::NUMERIC * 1000)::BIGINT
+    ELSE 0
+END;
+ALTER TABLE transactions ALTER COLUMN amount_msat SET NOT NULL;
+ALTER TABLE transactions DROP COLUMN amount;
+ALTER TABLE transactions ADD COLUMN unit TEXT NOT NULL DEFAULT 'sat';
+
+ALTER TABLE credits ADD COLUMN amount_msat BIGINT;
+UPDATE credits
+SET amount_msat = CASE
+    WHEN trim(amount) ~ '^-?[0-9]+(\.[0-9]+)?$' THEN round(trim(amount)::NUMERIC * 1000)::BIGINT
+    ELSE 0
+END;
+ALTER TABLE credits ALTER COLUMN amount_msat SET NOT NULL;
+ALTER TABLE credits DROP COLUMN amount;
+ALTER TABLE credits ADD COLUMN unit TEXT NOT NULL DEFAULT 'sat';
+ALTER TABLE credits ADD COLUMN mint_url TEXT;
+
+CREATE INDEX transactions_created_at_idx ON transactions (created_at);
+CREATE INDEX credits_created_at_idx ON credits (created_at);
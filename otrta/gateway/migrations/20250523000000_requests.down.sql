@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS transactions_request_id_idx;
+ALTER TABLE credits DROP COLUMN request_id;
+ALTER TABLE transactions DROP COLUMN request_id;
+DROP TABLE IF EXISTS requests;
@@ -0,0 +1,24 @@
+-- One row per proxied upstream call, payments and change reference it
+CREATE TABLE requests (
+    id UUID PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL,
+    endpoint TEXT NOT NULL,
+    model TEXT,
+    provider TEXT NOT NULL,
+    client_key TEXT,
+    http_status INTEGER,
+    prompt_tokens BIGINT,
+    completion_tokens BIGINT,
+    total_tokens BIGINT,
+    latency_ms BIGINT,
+    cost_msat BIGINT NOT NULL DEFAULT 0,
+    error TEXT
+);
+
+CREATE INDEX requests_created_at_idx ON requests (created_at);
+CREATE INDEX requests_model_idx ON requests (model);
+
+ALTER TABLE transactions ADD COLUMN request_id UUID REFERENCES requests (id) ON DELETE SET NULL;
+ALTER TABLE credits ADD COLUMN request_id UUID REFERENCES requests (id) ON DELETE SET NULL;
+
+CREATE INDEX transactions_request_id_idx ON transactions (request_id);
@@ -0,0 +1,6 @@
+-- Mnemonic of the built-in CDK wallet, encrypted with the application key
+CREATE TABLE wallet_seed (
+    id INTEGER PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    mnemonic_encrypted TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS provider_signing_key;
+DROP TABLE IF EXISTS model_prices;
@@ -0,0 +1,15 @@
+-- Per-model rates of provider mode, taking precedence over the configuration
+CREATE TABLE model_prices (
+    model TEXT PRIMARY KEY,
+    prompt_msat_per_1k BIGINT NOT NULL CHECK (prompt_msat_per_1k >= 0),
+    completion_msat_per_1k BIGINT NOT NULL CHECK (completion_msat_per_1k >= 0),
+    request_msat BIGINT NOT NULL DEFAULT 0 CHECK (request_msat >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Key signing the charge breakdowns of provider mode, encrypted with the application key
+CREATE TABLE provider_signing_key (
+    id INTEGER PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    secret_key_encrypted TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1 @@
+ALTER TABLE server_config DROP COLUMN accepted_mints;
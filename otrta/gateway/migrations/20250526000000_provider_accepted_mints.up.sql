@@ -0,0 +1,2 @@
+-- Mints whose tokens the provider takes; empty means any mint
+ALTER TABLE server_config ADD COLUMN accepted_mints TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+ALTER TABLE credits DROP COLUMN quarantine_reason;
+DROP TABLE IF EXISTS mints;
+DROP TYPE IF EXISTS mint_status;
@@ -0,0 +1,16 @@
+-- Trust rules and NUT-06 info of the mints ecash is taken from
+CREATE TYPE mint_status AS ENUM ('allowed', 'denied');
+
+CREATE TABLE mints (
+    url TEXT PRIMARY KEY,
+    -- NULL when neither allowed nor denied, e.g. a mint only seen in change
+    status mint_status,
+    max_balance_sats BIGINT CHECK (max_balance_sats >= 0),
+    info JSONB,
+    info_fetched_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Change from mints we don't trust is kept aside instead of redeemed
+ALTER TABLE credits ADD COLUMN quarantine_reason TEXT;
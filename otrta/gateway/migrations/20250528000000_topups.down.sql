@@ -0,0 +1,3 @@
+ALTER TABLE transactions DROP COLUMN topup_id;
+DROP TABLE IF EXISTS topups;
+DROP TYPE IF EXISTS topup_status;
@@ -0,0 +1,22 @@
+-- Lightning deposits into the wallet, from the mint quote to the minted ecash
+CREATE TYPE topup_status AS ENUM ('pending', 'paid', 'expired', 'failed');
+
+CREATE TABLE topups (
+    id UUID PRIMARY KEY,
+    amount_sats BIGINT NOT NULL CHECK (amount_sats > 0),
+    -- NULL when minted at the wallet's default mint
+    mint_url TEXT,
+    quote_id TEXT,
+    payment_request TEXT NOT NULL,
+    status topup_status NOT NULL DEFAULT 'pending',
+    error TEXT,
+    expires_at TIMESTAMPTZ NOT NULL,
+    paid_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX topups_pending_idx ON topups (created_at) WHERE status = 'pending';
+
+-- Deposits are booked without a token
+ALTER TABLE transactions ADD COLUMN topup_id UUID REFERENCES topups (id) ON DELETE SET NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE transactions DROP COLUMN fee_msat;
+ALTER TABLE transactions DROP COLUMN withdrawal_id;
+DROP TABLE IF EXISTS withdrawals;
+DROP TYPE IF EXISTS withdrawal_status;
@@ -0,0 +1,27 @@
+-- Lightning payments out of the wallet, from the melt quote to the payment
+CREATE TYPE withdrawal_status AS ENUM ('quoted', 'pending', 'paid', 'failed');
+
+CREATE TABLE withdrawals (
+    id UUID PRIMARY KEY,
+    bolt11 TEXT NOT NULL,
+    mint_url TEXT,
+    quote_id TEXT NOT NULL,
+    amount_sats BIGINT NOT NULL CHECK (amount_sats > 0),
+    fee_reserve_sats BIGINT NOT NULL,
+    fee_paid_sats BIGINT,
+    status withdrawal_status NOT NULL DEFAULT 'quoted',
+    -- The mint's payment id, to follow a pending payment
+    checking_id TEXT,
+    preimage TEXT,
+    error TEXT,
+    expires_at TIMESTAMPTZ NOT NULL,
+    paid_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX withdrawals_pending_idx ON withdrawals (created_at) WHERE status = 'pending';
+
+-- Withdrawals are booked without a token, with the Lightning fee they cost
+ALTER TABLE transactions ADD COLUMN withdrawal_id UUID REFERENCES withdrawals (id) ON DELETE SET NULL;
+ALTER TABLE transactions ADD COLUMN fee_msat BIGINT;
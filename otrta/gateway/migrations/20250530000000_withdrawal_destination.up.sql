@@ -0,0 +1,2 @@
+-- The Lightning Address or LNURL a withdrawal's invoice was requested from
+ALTER TABLE withdrawals ADD COLUMN destination TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE transactions DROP COLUMN transfer_id;
+DROP TABLE IF EXISTS transfers;
+DROP TYPE IF EXISTS transfer_status;
@@ -0,0 +1,24 @@
+-- Ecash sent out of the wallet as a token, until it is claimed or reclaimed
+CREATE TYPE transfer_status AS ENUM ('pending', 'claimed', 'reclaimed');
+
+CREATE TABLE transfers (
+    id UUID PRIMARY KEY,
+    amount_sats BIGINT NOT NULL CHECK (amount_sats > 0),
+    mint_url TEXT,
+    token_fingerprint TEXT NOT NULL,
+    -- Kept to check and reclaim the token, never returned after the send
+    token_encrypted TEXT NOT NULL,
+    -- P2PK key the token is locked to
+    lock_pubkey TEXT,
+    -- Nostr recipient the token was sent to as a DM
+    npub TEXT,
+    status transfer_status NOT NULL DEFAULT 'pending',
+    settled_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX transfers_pending_idx ON transfers (created_at) WHERE status = 'pending';
+
+-- A send is booked as outgoing and a reclaim as incoming, by the token's fingerprint
+ALTER TABLE transactions ADD COLUMN transfer_id UUID REFERENCES transfers (id) ON DELETE SET NULL;
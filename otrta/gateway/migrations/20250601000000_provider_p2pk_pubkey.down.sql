@@ -0,0 +1 @@
+ALTER TABLE server_config DROP COLUMN IF EXISTS p2pk_pubkey;
@@ -0,0 +1,2 @@
+-- Key the provider advertises for payments to be locked to (NUT-11)
+ALTER TABLE server_config ADD COLUMN p2pk_pubkey TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE transactions DROP COLUMN dleq_status;
+DROP TYPE IF EXISTS dleq_status;
@@ -0,0 +1,5 @@
+-- Outcome of verifying the DLEQ proofs (NUT-12) of incoming tokens, NULL when
+-- the wallet could not check them
+CREATE TYPE dleq_status AS ENUM ('valid', 'missing', 'invalid');
+
+ALTER TABLE transactions ADD COLUMN dleq_status dleq_status;
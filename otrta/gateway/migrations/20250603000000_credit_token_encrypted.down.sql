@@ -0,0 +1,6 @@
+-- Encrypted tokens cannot be decrypted in SQL and are dropped
+ALTER TABLE credits DROP COLUMN token_encrypted;
+ALTER TABLE credits DROP COLUMN token_fingerprint;
+UPDATE credits SET token_plaintext = '' WHERE token_plaintext IS NULL;
+ALTER TABLE credits ALTER COLUMN token_plaintext SET NOT NULL;
+ALTER TABLE credits RENAME COLUMN token_plaintext TO token;
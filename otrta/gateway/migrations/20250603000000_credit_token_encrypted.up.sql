@@ -0,0 +1,5 @@
+-- Credits keep their token encrypted like transactions do
+ALTER TABLE credits RENAME COLUMN token TO token_plaintext;
+ALTER TABLE credits ALTER COLUMN token_plaintext DROP NOT NULL;
+ALTER TABLE credits ADD COLUMN token_fingerprint TEXT;
+ALTER TABLE credits ADD COLUMN token_encrypted TEXT;
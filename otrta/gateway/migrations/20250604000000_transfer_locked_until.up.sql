@@ -0,0 +1,2 @@
+-- Locked transfers can be taken back with the wallet's own key from then on
+ALTER TABLE transfers ADD COLUMN locked_until TIMESTAMPTZ;
@@ -0,0 +1,162 @@
+use crate::{export, forward, handlers, health, metrics, models::AppState, provider};
+use axum::{
+    Router, middleware,
+    routing::{get, post, put},
+};
+use std::sync::Arc;
+use tower_http::{
+    cors::{Any, CorsLayer},
+    trace::TraceLayer,
+};
+use wallet::api::CashuWalletApi;
+
+/// All routes of the gateway in client mode, paying upstream with whatever
+/// wallet `state` holds.
+pub fn router<W: CashuWalletApi>(state: Arc<AppState<W>>) -> Router {
+    let routes = Router::new()
+        .route("/api/openai-models", get(handlers::list_openai_models::<W>))
+        .route(
+            "/v1/chat/completions",
+            post(forward::forward_chat_completions::<W>),
+        )
+        .route(
+            "/chat/completions",
+            post(forward::forward_chat_completions::<W>),
+        )
+        .route("/models", get(forward::forward_list_models::<W>))
+        .route("/models/{model_id}", get(forward::get_specific_model::<W>))
+        .route("/embeddings", post(forward::forward_embeddings::<W>))
+        .route(
+            "/images/generations",
+            post(forward::forward_image_generations::<W>),
+        )
+        .route("/v1/models", get(forward::forward_list_models::<W>))
+        .route(
+            "/v1/models/{model_id}",
+            get(forward::get_specific_model::<W>),
+        )
+        .route("/v1/embeddings", post(forward::forward_embeddings::<W>))
+        .route(
+            "/v1/images/generations",
+            post(forward::forward_image_generations::<W>),
+        );
+
+    finish(routes, state)
+}
+
+/// All routes of the gateway in provider mode: the OpenAI endpoints are
+/// served to clients paying with ecash, on top of the same admin API.
+pub fn provider_router<W: CashuWalletApi>(state: Arc<AppState<W>>) -> Router {
+    let routes = Router::new()
+        .route(
+            "/v1/chat/completions",
+            post(provider::provide_chat_completions::<W>),
+        )
+        .route("/v1/embeddings", post(provider::provide_embeddings::<W>))
+        .route(
+            "/v1/images/generations",
+            post(provider::provide_image_generations::<W>),
+        )
+        .route("/v1/models", get(provider::provide_models::<W>))
+        .route("/v1/models/{model_id}", get(provider::provide_model::<W>))
+        .route("/api/pricing", get(handlers::get_pricing::<W>))
+        .route(
+            "/api/pricing/{model}",
+            put(handlers::update_model_price::<W>).delete(handlers::reset_model_price::<W>),
+        );
+
+    finish(routes, state)
+}
+
+/// Adds the wallet, ledger and operational routes shared by both modes.
+fn finish<W: CashuWalletApi>(routes: Router<Arc<AppState<W>>>, state: Arc<AppState<W>>) -> Router {
+    routes
+        .route("/api/wallet/redeem", post(handlers::redeem_token::<W>))
+        .route("/api/wallet/balance", get(handlers::get_balance::<W>))
+        .route("/api/wallet/restore", post(handlers::restore_wallet::<W>))
+        .route("/api/wallet/topup", post(handlers::create_topup::<W>))
+        .route(
+            "/api/wallet/topup/{id}",
+            get(handlers::get_topup_status::<W>),
+        )
+        .route(
+            "/api/wallet/withdraw",
+            post(handlers::quote_withdrawal::<W>),
+        )
+        .route(
+            "/api/wallet/withdraw/{id}",
+            get(handlers::get_withdrawal_status::<W>),
+        )
+        .route(
+            "/api/wallet/withdraw/{id}/confirm",
+            post(handlers::confirm_withdrawal::<W>),
+        )
+        .route("/api/wallet/send", post(handlers::send_ecash::<W>))
+        .route(
+            "/api/wallet/send/{id}",
+            get(handlers::get_transfer_status::<W>),
+        )
+        .route(
+            "/api/wallet/send/{id}/reclaim",
+            post(handlers::reclaim_transfer::<W>),
+        )
+        .route(
+            "/api/server-config",
+            get(handlers::get_current_server_config::<W>),
+        )
+        .route(
+            "/api/server-config",
+            post(handlers::update_server_config::<W>),
+        )
+        .route("/api/credits", get(handlers::get_all_credits::<W>))
+        .route(
+            "/api/transactions",
+            get(handlers::get_all_transactions::<W>),
+        )
+        .route(
+            "/api/transactions/export",
+            get(export::export_transactions::<W>),
+        )
+        .route("/api/credits/export", get(export::export_credits::<W>))
+        .route("/api/credits/{id}", get(handlers::get_credit_by_id::<W>))
+        .route(
+            "/api/credits/{id}/redeem",
+            post(handlers::redeem_credit::<W>),
+        )
+        .route("/api/mints", get(handlers::list_mints::<W>))
+        .route(
+            "/api/mints/{url}",
+            put(handlers::update_mint::<W>).delete(handlers::reset_mint::<W>),
+        )
+        .route(
+            "/api/mints/{url}/info",
+            post(handlers::refresh_mint_info::<W>),
+        )
+        .route(
+            "/api/transactions/{id}",
+            get(handlers::get_transaction_by_id::<W>),
+        )
+        .route("/api/requests", get(handlers::get_all_requests::<W>))
+        .route(
+            "/api/analytics/spend",
+            get(handlers::get_spend_analytics::<W>),
+        )
+        .route(
+            "/api/analytics/summary",
+            get(handlers::get_summary_analytics::<W>),
+        )
+        .route("/metrics", get(metrics::metrics_handler::<W>))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz::<W>))
+        .route_layer(middleware::from_fn(metrics::track_http_metrics))
+        .with_state(state)
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods(Any)
+                .allow_headers(Any)
+                .expose_headers(Any)
+                .allow_private_network(true),
+        )
+        .layer(TraceLayer::new_for_http())
+}
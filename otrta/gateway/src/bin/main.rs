@@ -1,34 +1,54 @@
-use axum::{
-    Router,
-    routing::{get, post},
-};
+use cdk::nuts::SecretKey;
 use gateway::{
-    connection::{DatabaseSettings, get_configuration},
-    forward, handlers,
+    app,
+    connection::{
+        ApplicationSettings, DatabaseSettings, GatewayMode, ProviderSettings, WalletBackendKind,
+        WalletSettings, get_configuration,
+    },
+    crypto::SecretCipher,
+    db::{
+        credit::seal_plaintext_credits,
+        pricing::{get_signing_key, store_signing_key},
+        server_config::encrypt_plaintext_api_keys,
+        transaction::seal_plaintext_tokens,
+        wallet_seed::{get_mnemonic, store_mnemonic},
+    },
+    metrics,
+    mint_policy::MintPolicy,
     models::AppState,
+    pricing::PricingEngine,
+    rebalance::{Rebalancer, spawn_rebalancer},
+    telemetry,
+    topup::resume_topups,
+    withdraw::resume_withdrawals,
 };
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
-use tower_http::{
-    cors::{Any, CorsLayer},
-    trace::TraceLayer,
+use wallet::{
+    api::{CashuWalletApi, CashuWalletClient, WalletBackend},
+    wallet::{cdk_wallet, generate_mnemonic},
 };
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use wallet::api::CashuWalletClient;
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG")
-                .unwrap_or_else(|_| "wallet_gateway=debug,tower_http=debug".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
     let configuration = get_configuration().expect("Failed to read configuration.");
+    // Refused here rather than halfway through a payment.
+    if configuration.wallet.backend == WalletBackendKind::Nutshell
+        && configuration.rebalance.swaps()
+        && !configuration.rebalance.uncapped_fees
+    {
+        panic!(
+            "The nutshell wallet can't hold swap fees to rebalance.max_fee_*: turn off \
+             rebalance.auto_swap and rebalance.targets, or set rebalance.uncapped_fees."
+        );
+    }
+    let tracer_provider = telemetry::init_tracing(&configuration.telemetry);
+
+    let metrics_handle = metrics::install_recorder();
+
     let connection_pool = get_connection_pool(&configuration.database)
         .await
         .expect("Failed to connect to Postgres.");
@@ -36,7 +56,41 @@ async fn main() {
         .run(&connection_pool)
         .await
         .unwrap();
-    let wallet = CashuWalletClient::new(&configuration.application.wallet_url);
+    let cipher = SecretCipher::new(&configuration.application.encryption_key)
+        .expect("Invalid application.encryption_key.");
+    let migrated = encrypt_plaintext_api_keys(&connection_pool, &cipher)
+        .await
+        .expect("Failed to encrypt stored API keys.");
+    if migrated > 0 {
+        tracing::info!("Encrypted {} plaintext API key(s)", migrated);
+    }
+    let migrated = seal_plaintext_tokens(&connection_pool, &cipher)
+        .await
+        .expect("Failed to seal stored transaction tokens.");
+    if migrated > 0 {
+        tracing::info!("Sealed {} plaintext transaction token(s)", migrated);
+    }
+    let migrated = seal_plaintext_credits(&connection_pool, &cipher)
+        .await
+        .expect("Failed to seal stored credit tokens.");
+    if migrated > 0 {
+        tracing::info!("Sealed {} plaintext credit token(s)", migrated);
+    }
+    let wallet = open_wallet(
+        &configuration.application,
+        &configuration.wallet,
+        &connection_pool,
+        &cipher,
+    )
+    .await;
+
+    let signer = match configuration.mode {
+        GatewayMode::Client => None,
+        GatewayMode::Provider => {
+            Some(load_signing_key(&configuration.provider, &connection_pool, &cipher).await)
+        }
+    };
+    let pricing = PricingEngine::new(configuration.provider.clone(), signer);
 
     let app_state = Arc::new(AppState {
         db: connection_pool.clone(),
@@ -47,48 +101,38 @@ async fn main() {
         providers: RwLock::new(HashMap::new()),
         credits: RwLock::new(HashMap::new()),
         wallet,
+        cipher,
+        payments: configuration.payments.clone(),
+        metrics: metrics_handle,
+        pricing,
+        rebalancer: Rebalancer::new(configuration.rebalance.clone()),
+        mint_policy: MintPolicy::new(
+            configuration.mint_policy.clone(),
+            &configuration.wallet.mint_urls,
+        ),
+        topup: configuration.topup.clone(),
+        withdraw: configuration.withdraw.clone(),
+        transfer: configuration.transfer.clone(),
     });
+    spawn_rebalancer(app_state.clone());
+    match resume_topups(app_state.clone()).await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Watching {} pending top-up(s)", count),
+        Err(e) => tracing::error!("Failed to resume pending top-ups: {}", e),
+    }
+    match resume_withdrawals(app_state.clone()).await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Watching {} pending withdrawal(s)", count),
+        Err(e) => tracing::error!("Failed to resume pending withdrawals: {}", e),
+    }
 
-    let app = Router::new()
-        .route("/api/openai-models", get(handlers::list_openai_models))
-        .route("/api/wallet/redeem", post(handlers::redeem_token))
-        .route("/api/wallet/balance", get(handlers::get_balance))
-        .route(
-            "/v1/chat/completions",
-            post(forward::forward_chat_completions),
-        )
-        .route("/chat/completions", post(forward::forward_chat_completions))
-        .route("/models", get(forward::forward_list_models))
-        .route("/models/{model_id}", get(forward::get_specific_model))
-        .route("/embeddings", post(forward::forward_embeddings))
-        .route(
-            "/images/generations",
-            post(forward::forward_image_generations),
-        )
-        .route("/v1/models", get(forward::forward_list_models))
-        .route("/v1/models/{model_id}", get(forward::get_specific_model))
-        .route("/v1/embeddings", post(forward::forward_embeddings))
-        .route(
-            "/v1/images/generations",
-            post(forward::forward_image_generations),
-        )
-        .route(
-            "/api/server-config",
-            get(handlers::get_current_server_config),
-        )
-        .route("/api/server-config", post(handlers::update_server_config))
-        .route("/api/credits", get(handlers::get_all_credits))
-        .route("/api/transactions", get(handlers::get_all_transactions))
-        .with_state(app_state)
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any)
-                .expose_headers(Any)
-                .allow_private_network(true),
-        )
-        .layer(TraceLayer::new_for_http());
+    let app = match configuration.mode {
+        GatewayMode::Client => app::router(app_state),
+        GatewayMode::Provider => {
+            tracing::info!("Running as a paid provider");
+            app::provider_router(app_state)
+        }
+    };
     println!(
         "Server starting on http://{}:{}",
         configuration.application.host, configuration.application.port
@@ -99,7 +143,148 @@ async fn main() {
     ))
     .await
     .unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    telemetry::shutdown_tracing(tracer_provider);
+}
+
+/// Ctrl-C, or SIGTERM as sent by `docker stop`.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for shutdown signal.");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM.")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+async fn open_wallet(
+    application: &ApplicationSettings,
+    settings: &WalletSettings,
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> WalletBackend {
+    if settings.backend == WalletBackendKind::Nutshell {
+        return WalletBackend::Nutshell(CashuWalletClient::new(&application.wallet_url));
+    }
+
+    let fresh_store = !settings.store_path.exists();
+    let (mnemonic, generated) = match &settings.mnemonic {
+        Some(mnemonic) => (mnemonic.clone(), false),
+        None => load_or_create_mnemonic(pool, cipher).await,
+    };
+    let wallet = cdk_wallet(
+        &settings.mint_urls,
+        mnemonic.expose_secret(),
+        &settings.store_path,
+    )
+    .await
+    .expect("Failed to open the CDK wallet.");
+
+    // A known seed with an empty store means the proofs were lost or this is
+    // a new machine; recover them from the mints.
+    if fresh_store && !generated {
+        let wallet = wallet.clone();
+        tokio::spawn(async move {
+            match wallet.restore(0).await {
+                Ok(restored) => tracing::info!("Restored wallet, balance {} sat", restored.balance),
+                Err(e) => tracing::error!("Failed to restore wallet from mnemonic: {}", e),
+            }
+        });
+    }
+
+    WalletBackend::Cdk(wallet)
+}
+
+/// The configured charge signing key, else the stored one, else a new one.
+async fn load_signing_key(
+    settings: &ProviderSettings,
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> SecretKey {
+    let secret_key = match &settings.signing_key {
+        Some(hex) => {
+            SecretKey::from_hex(hex.expose_secret().trim()).expect("Invalid provider.signing_key.")
+        }
+        None => match get_signing_key(pool, cipher)
+            .await
+            .expect("Failed to read the charge signing key.")
+        {
+            Some(hex) => SecretKey::from_hex(hex.expose_secret())
+                .expect("Stored charge signing key is invalid."),
+            None => {
+                let generated = SecretKey::generate();
+                let stored =
+                    store_signing_key(pool, cipher, &SecretString::from(generated.to_secret_hex()))
+                        .await
+                        .expect("Failed to store the charge signing key.");
+                if stored {
+                    generated
+                } else {
+                    // Another instance got there first.
+                    let hex = get_signing_key(pool, cipher)
+                        .await
+                        .expect("Failed to read the charge signing key.")
+                        .expect("Charge signing key vanished.");
+                    SecretKey::from_hex(hex.expose_secret())
+                        .expect("Stored charge signing key is invalid.")
+                }
+            }
+        },
+    };
+
+    tracing::info!(
+        "Signing charges with key {}",
+        secret_key.public_key().to_hex()
+    );
+    secret_key
+}
+
+/// Returns the stored mnemonic, or generates one and prints it once. The
+/// flag is set when the mnemonic was generated by this call.
+async fn load_or_create_mnemonic(pool: &PgPool, cipher: &SecretCipher) -> (SecretString, bool) {
+    if let Some(mnemonic) = get_mnemonic(pool, cipher)
+        .await
+        .expect("Failed to read the wallet mnemonic.")
+    {
+        return (mnemonic, false);
+    }
+
+    let mnemonic = SecretString::from(generate_mnemonic().expect("Failed to generate a mnemonic."));
+    let stored = store_mnemonic(pool, cipher, &mnemonic)
+        .await
+        .expect("Failed to store the wallet mnemonic.");
+    if !stored {
+        // Another instance got there first.
+        let mnemonic = get_mnemonic(pool, cipher)
+            .await
+            .expect("Failed to read the wallet mnemonic.")
+            .expect("Wallet mnemonic vanished.");
+        return (mnemonic, false);
+    }
+
+    println!(
+        "\nGenerated a new wallet mnemonic. Write it down, it is the only way to recover\nthe wallet's funds and will not be shown again:\n\n    {}\n",
+        mnemonic.expose_secret()
+    );
+    (mnemonic, true)
 }
 
 pub async fn get_connection_pool(configuration: &DatabaseSettings) -> Result<PgPool, sqlx::Error> {
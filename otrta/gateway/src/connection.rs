@@ -1,12 +1,86 @@
 use secrecy::{ExposeSecret, SecretString};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::path::PathBuf;
+use wallet::models::ModelPricing;
 
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub wallet: WalletSettings,
+    #[serde(default)]
+    pub mode: GatewayMode,
+    #[serde(default)]
+    pub payments: PaymentSettings,
+    #[serde(default)]
+    pub provider: ProviderSettings,
+    #[serde(default)]
+    pub rebalance: RebalanceSettings,
+    #[serde(default)]
+    pub mint_policy: MintPolicySettings,
+    #[serde(default)]
+    pub topup: TopupSettings,
+    #[serde(default)]
+    pub withdraw: WithdrawSettings,
+    #[serde(default)]
+    pub transfer: TransferSettings,
+}
+
+#[derive(Debug, Default, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GatewayMode {
+    /// Pay the configured provider with ecash from our wallet.
+    #[default]
+    Client,
+    /// Be the paid provider: take ecash in `X-PAYMENT-SATS`, call the
+    /// configured upstream with its API key and return change.
+    Provider,
+}
+
+/// Paying the provider in client mode.
+#[derive(Debug, Default, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct PaymentSettings {
+    /// Pay providers that advertise no P2PK key with bearer tokens, which
+    /// anything on the way to the provider could spend.
+    pub allow_unlocked: bool,
+}
+
+/// Pricing of provider mode. A model's rate comes from the `model_prices`
+/// table, then `models`, then `default_price`.
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ProviderSettings {
+    /// Payments below this are refused before being redeemed.
+    pub min_payment_sats: i64,
+    pub rounding: SubSatRounding,
+    pub default_price: ModelPricing,
+    pub models: HashMap<String, ModelPricing>,
+    /// Hex secp256k1 key signing the charge breakdowns. When unset, one is
+    /// generated on first run and kept encrypted in the database.
+    pub signing_key: Option<SecretString>,
+}
+
+impl Default for ProviderSettings {
+    fn default() -> Self {
+        Self {
+            min_payment_sats: 10,
+            rounding: SubSatRounding::default(),
+            default_price: ModelPricing {
+                prompt_msat_per_1k: 2000,
+                completion_msat_per_1k: 8000,
+                request_msat: 0,
+            },
+            models: HashMap::new(),
+            signing_key: None,
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
@@ -17,6 +91,211 @@ pub struct ApplicationSettings {
     pub worker: usize,
     pub connections: usize,
     pub wallet_url: String,
+    pub encryption_key: SecretString,
+}
+
+/// How a charge that isn't a whole number of sats is settled, since change
+/// can only be handed back in sats.
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubSatRounding {
+    /// In the provider's favour.
+    #[default]
+    Up,
+    /// In the client's favour.
+    Down,
+    Nearest,
+}
+
+#[derive(Debug, Default, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WalletBackendKind {
+    /// External nutshell wallet at `application.wallet_url`.
+    #[default]
+    Nutshell,
+    /// In-process CDK wallet holding ecash from `mint_urls`.
+    Cdk,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct WalletSettings {
+    #[serde(default)]
+    pub backend: WalletBackendKind,
+    #[serde(default)]
+    pub mint_urls: Vec<String>,
+    /// BIP39 mnemonic of the CDK wallet. When unset, one is generated on
+    /// first run and kept encrypted in the database.
+    pub mnemonic: Option<SecretString>,
+    /// Where the CDK wallet keeps its proofs.
+    #[serde(default = "default_store_path")]
+    pub store_path: PathBuf,
+}
+
+impl Default for WalletSettings {
+    fn default() -> Self {
+        Self {
+            backend: WalletBackendKind::default(),
+            mint_urls: Vec::new(),
+            mnemonic: None,
+            store_path: default_store_path(),
+        }
+    }
+}
+
+fn default_store_path() -> PathBuf {
+    PathBuf::from("data/wallet.redb")
+}
+
+/// Which mints ecash is taken from. Rules stored through `/api/mints` win
+/// over these, and denied mints are never trusted.
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct MintPolicySettings {
+    /// Trusted on top of the CDK wallet's `mint_urls`.
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    /// Trust mints that are neither allowed nor denied.
+    pub trust_unlisted: bool,
+    /// Most sats to hold at any one mint, unless set for the mint.
+    pub max_balance_sats: Option<i64>,
+    /// Most mints without a rule to record from incoming tokens.
+    pub max_unreviewed_mints: i64,
+}
+
+impl Default for MintPolicySettings {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            trust_unlisted: false,
+            max_balance_sats: None,
+            max_unreviewed_mints: 100,
+        }
+    }
+}
+
+/// Funding the wallet over Lightning through `/api/wallet/topup`.
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct TopupSettings {
+    /// Largest top-up, in sats.
+    pub max_sats: i64,
+    /// How often the mint is asked whether an invoice was paid.
+    pub poll_interval_ms: u64,
+    /// Seconds after which an unpaid invoice is given up.
+    pub expiry_secs: i64,
+}
+
+impl Default for TopupSettings {
+    fn default() -> Self {
+        Self {
+            max_sats: 1_000_000,
+            poll_interval_ms: 2000,
+            expiry_secs: 3600,
+        }
+    }
+}
+
+/// Paying Lightning invoices out of the wallet through `/api/wallet/withdraw`.
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct WithdrawSettings {
+    /// How often the mint is asked about a payment still in flight.
+    pub poll_interval_ms: u64,
+    /// Reach Lightning Addresses and LNURL services over plain http. Only
+    /// meant for local testing.
+    pub allow_insecure_lnurl: bool,
+}
+
+impl Default for WithdrawSettings {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 2000,
+            allow_insecure_lnurl: false,
+        }
+    }
+}
+
+/// Sending ecash out of the wallet through `/api/wallet/send`.
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct TransferSettings {
+    /// How long a locked token is for its recipient alone, before the
+    /// wallet can take it back.
+    pub lock_secs: i64,
+}
+
+impl Default for TransferSettings {
+    fn default() -> Self {
+        Self {
+            lock_secs: 7 * 24 * 3600,
+        }
+    }
+}
+
+/// Moving ecash between mints over Lightning, when a payment can't be made
+/// from a mint the provider accepts and in the background to keep `targets`.
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct RebalanceSettings {
+    /// Swap into an accepted mint when none of them can cover a payment.
+    pub auto_swap: bool,
+    /// Sats every mint keeps, whatever is swapped out of it.
+    pub min_reserve_sats: i64,
+    /// Fees a swap may cost: this many sats plus `max_fee_percent` of the
+    /// amount. The source mint has to hold them on top of the amount.
+    pub max_fee_base_sats: i64,
+    pub max_fee_percent: f64,
+    /// Let swaps cost whatever the mint asks, for wallets that can't hold
+    /// them to the limit above (nutshell).
+    pub uncapped_fees: bool,
+    /// Balance to keep at each of these mints, by mint URL.
+    pub targets: HashMap<String, i64>,
+    /// Seconds between two rebalancing runs, when there are `targets`.
+    pub interval_secs: u64,
+}
+
+impl Default for RebalanceSettings {
+    fn default() -> Self {
+        Self {
+            auto_swap: false,
+            min_reserve_sats: 0,
+            max_fee_base_sats: 2,
+            max_fee_percent: 1.0,
+            uncapped_fees: false,
+            targets: HashMap::new(),
+            interval_secs: 300,
+        }
+    }
+}
+
+/// Traces are only exported when `otlp_endpoint` is set, e.g.
+/// `http://localhost:4318/v1/traces`.
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct TelemetrySettings {
+    pub otlp_endpoint: Option<String>,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+impl RebalanceSettings {
+    /// Whether swaps may run at all, on demand or in the background.
+    pub fn swaps(&self) -> bool {
+        self.auto_swap || !self.targets.is_empty()
+    }
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_service_name(),
+        }
+    }
+}
+
+fn default_service_name() -> String {
+    "otrta-gateway".to_string()
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
@@ -42,7 +321,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -76,7 +355,11 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
-                .separator("__"),
+                .separator("__")
+                // `APP_WALLET__MINT_URLS=https://a,https://b`
+                .list_separator(",")
+                .with_list_parse_key("wallet.mint_urls")
+                .try_parsing(true),
         )
         .build()?;
 
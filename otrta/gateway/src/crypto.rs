@@ -0,0 +1,95 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("encryption key must be 32 bytes encoded as base64")]
+    InvalidKey,
+    #[error("malformed ciphertext")]
+    MalformedCiphertext,
+    #[error("failed to encrypt secret")]
+    Encrypt,
+    #[error("failed to decrypt secret, is the encryption key correct?")]
+    Decrypt,
+}
+
+/// Symmetric cipher used to keep secrets such as upstream API keys encrypted
+/// at rest. Ciphertexts are stored as base64 of `nonce || ciphertext`.
+#[derive(Clone)]
+pub struct SecretCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl SecretCipher {
+    pub fn new(key: &SecretString) -> Result<Self, CryptoError> {
+        let bytes = STANDARD
+            .decode(key.expose_secret().trim())
+            .map_err(|_| CryptoError::InvalidKey)?;
+        if bytes.len() != 32 {
+            return Err(CryptoError::InvalidKey);
+        }
+
+        Ok(Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&bytes)),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, CryptoError> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| CryptoError::Encrypt)?;
+
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&ciphertext);
+        Ok(STANDARD.encode(out))
+    }
+
+    pub fn decrypt(&self, encoded: &str) -> Result<SecretString, CryptoError> {
+        let bytes = STANDARD
+            .decode(encoded)
+            .map_err(|_| CryptoError::MalformedCiphertext)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(CryptoError::MalformedCiphertext);
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::Decrypt)?;
+        let plaintext = String::from_utf8(plaintext).map_err(|_| CryptoError::Decrypt)?;
+
+        Ok(SecretString::from(plaintext))
+    }
+}
+
+/// Masks a secret for display, keeping only the last four characters when the
+/// secret is long enough for that not to give it away.
+pub fn mask_secret(secret: &str) -> String {
+    if secret.is_empty() {
+        return String::new();
+    }
+
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 12 {
+        return "********".to_string();
+    }
+
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("********{}", tail)
+}
+
+/// Short, stable identifier for a secret that can be stored and grouped on
+/// without revealing the secret itself.
+pub fn fingerprint_secret(secret: &str) -> String {
+    hex::encode(&Sha256::digest(secret.as_bytes())[..8])
+}
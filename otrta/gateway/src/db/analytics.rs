@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl Bucket {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bucket::Hour => "hour",
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+            Bucket::Month => "month",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Model,
+    Provider,
+    ApiKey,
+}
+
+impl GroupBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupBy::Model => "model",
+            GroupBy::Provider => "provider",
+            GroupBy::ApiKey => "api_key",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpendPoint {
+    pub bucket: DateTime<Utc>,
+    pub group: Option<String>,
+    pub requests: i64,
+    pub spend_msat: i64,
+    pub avg_cost_msat: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpendSummary {
+    pub group: Option<String>,
+    pub requests: i64,
+    pub failed_requests: i64,
+    pub spend_msat: i64,
+    pub avg_cost_msat: i64,
+    pub avg_latency_ms: Option<i64>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
+/// Spend per time bucket between `from` and `to`, optionally split by
+/// `group_by`. Buckets without requests are omitted.
+///
+/// Spend is what the ledger booked for the requests: payments and their
+/// fees, less the change and refunds that came back.
+pub async fn get_spend_series(
+    pool: &PgPool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket: Bucket,
+    group_by: Option<GroupBy>,
+) -> Result<Vec<SpendPoint>, sqlx::Error> {
+    sqlx::query_as!(
+        SpendPoint,
+        r#"
+        WITH ledger AS (
+            SELECT
+                request_id,
+                SUM(CASE direction
+                    WHEN 'Outgoing' THEN amount_msat + COALESCE(fee_msat, 0)
+                    ELSE -amount_msat
+                END) as spend_msat
+            FROM transactions
+            WHERE request_id IS NOT NULL
+            GROUP BY request_id
+        )
+        SELECT
+            date_trunc($3, r.created_at) as "bucket!",
+            CASE $4::TEXT
+                WHEN 'model' THEN r.model
+                WHEN 'provider' THEN r.provider
+                WHEN 'api_key' THEN r.client_key
+            END as "group",
+            COUNT(*) as "requests!",
+            COALESCE(SUM(l.spend_msat), 0)::BIGINT as "spend_msat!",
+            COALESCE(AVG(COALESCE(l.spend_msat, 0)), 0)::BIGINT as "avg_cost_msat!",
+            COALESCE(SUM(r.prompt_tokens), 0)::BIGINT as "prompt_tokens!",
+            COALESCE(SUM(r.completion_tokens), 0)::BIGINT as "completion_tokens!",
+            COALESCE(SUM(r.total_tokens), 0)::BIGINT as "total_tokens!"
+        FROM requests r
+        LEFT JOIN ledger l ON l.request_id = r.id
+        WHERE r.created_at >= $1 AND r.created_at < $2
+        GROUP BY 1, 2
+        ORDER BY 1, 2
+        "#,
+        from,
+        to,
+        bucket.as_str(),
+        group_by.map(|g| g.as_str())
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Totals between `from` and `to`, optionally split by `group_by`, most
+/// expensive group first.
+pub async fn get_spend_summary(
+    pool: &PgPool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    group_by: Option<GroupBy>,
+) -> Result<Vec<SpendSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SpendSummary,
+        r#"
+        WITH ledger AS (
+            SELECT
+                request_id,
+                SUM(CASE direction
+                    WHEN 'Outgoing' THEN amount_msat + COALESCE(fee_msat, 0)
+                    ELSE -amount_msat
+                END) as spend_msat
+            FROM transactions
+            WHERE request_id IS NOT NULL
+            GROUP BY request_id
+        )
+        SELECT
+            CASE $3::TEXT
+                WHEN 'model' THEN r.model
+                WHEN 'provider' THEN r.provider
+                WHEN 'api_key' THEN r.client_key
+            END as "group",
+            COUNT(*) as "requests!",
+            COUNT(*) FILTER (WHERE r.http_status IS NULL OR r.http_status >= 400) as "failed_requests!",
+            COALESCE(SUM(l.spend_msat), 0)::BIGINT as "spend_msat!",
+            COALESCE(AVG(COALESCE(l.spend_msat, 0)), 0)::BIGINT as "avg_cost_msat!",
+            AVG(r.latency_ms)::BIGINT as "avg_latency_ms",
+            COALESCE(SUM(r.prompt_tokens), 0)::BIGINT as "prompt_tokens!",
+            COALESCE(SUM(r.completion_tokens), 0)::BIGINT as "completion_tokens!",
+            COALESCE(SUM(r.total_tokens), 0)::BIGINT as "total_tokens!"
+        FROM requests r
+        LEFT JOIN ledger l ON l.request_id = r.id
+        WHERE r.created_at >= $1 AND r.created_at < $2
+        GROUP BY 1
+        ORDER BY 4 DESC
+        "#,
+        from,
+        to,
+        group_by.map(|g| g.as_str())
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -1,15 +1,48 @@
+use crate::{
+    crypto::SecretCipher,
+    db::listing::{Cursor, ListOptions},
+    token::summarize_token,
+};
 use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Credit {
-    pub id: String,
+    pub id: Uuid,
     pub created_at: DateTime<Utc>,
-    pub token: String,
-    pub amount: String,
+    pub token_fingerprint: String,
+    pub amount_msat: i64,
+    pub unit: String,
+    pub mint_url: Option<String>,
     pub redeemed: bool,
+    pub request_id: Option<Uuid>,
+    /// Why the token was kept aside rather than redeemed, for change from
+    /// mints we don't trust.
+    pub quarantine_reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreditExportRow {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub amount_msat: i64,
+    pub unit: String,
+    pub mint_url: Option<String>,
+    pub redeemed: bool,
+    pub request_id: Option<Uuid>,
+    pub model: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CreditExportFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub model: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -23,21 +56,46 @@ pub struct PaginationInfo {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CreditListResponse {
     pub data: Vec<Credit>,
-    pub pagination: PaginationInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PaginationInfo>,
+    pub next_cursor: Option<String>,
 }
 
-pub async fn add_credit(pool: &PgPool, token: &str, amount: &str) -> Result<Uuid, sqlx::Error> {
+/// Records change kept as a credit. As for transactions, only a fingerprint of
+/// `token` is stored in the clear and the API never returns the token. Amounts
+/// are always sats, like those of transactions.
+#[tracing::instrument(skip(pool, cipher, token), fields(db.system = "postgresql"), err)]
+pub async fn add_credit(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+    token: &str,
+    amount_msat: i64,
+    request_id: Option<Uuid>,
+    quarantine_reason: Option<&str>,
+) -> Result<Uuid, sqlx::Error> {
+    let summary = summarize_token(token);
+    let token_encrypted = cipher
+        .encrypt(token)
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
     let rec = sqlx::query!(
         r#"
-        INSERT INTO credits (id, created_at, token, amount, redeemed)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO credits
+            (id, created_at, token_fingerprint, token_encrypted, amount_msat, unit, mint_url,
+             redeemed, request_id, quarantine_reason)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
         RETURNING id
         "#,
         Uuid::new_v4(),
         Utc::now(),
-        token,
-        amount,
-        false
+        summary.fingerprint,
+        token_encrypted,
+        amount_msat,
+        "sat",
+        summary.mint_url,
+        false,
+        request_id,
+        quarantine_reason
     )
     .fetch_one(pool)
     .await?;
@@ -45,54 +103,230 @@ pub async fn add_credit(pool: &PgPool, token: &str, amount: &str) -> Result<Uuid
     Ok(rec.id)
 }
 
-pub async fn get_credits(
+/// The token of a credit, for redeeming it.
+#[tracing::instrument(skip(pool, cipher), fields(db.system = "postgresql"), err)]
+pub async fn get_credit_token(
     pool: &PgPool,
-    page: Option<i64>,
-    page_size: Option<i64>,
-) -> Result<CreditListResponse, sqlx::Error> {
-    // Default values if not provided
-    let page = page.unwrap_or(1);
-    let page_size = page_size.unwrap_or(10);
-
-    // Calculate offset
-    let offset = (page - 1) * page_size;
-
-    // Get total count
-    let total = sqlx::query_scalar(r#"SELECT COUNT(*) FROM credits"#)
-        .fetch_one(pool)
-        .await
-        .unwrap_or(0);
+    cipher: &SecretCipher,
+    id: Uuid,
+) -> Result<Option<SecretString>, sqlx::Error> {
+    let Some(rec) = sqlx::query!(
+        r#"SELECT token_encrypted as "token_encrypted!" FROM credits WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
 
-    // Calculate total pages
-    let total_pages = (total + page_size - 1) / page_size;
+    cipher
+        .decrypt(&rec.token_encrypted)
+        .map(Some)
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
 
-    // Get paginated credits
-    let credits = sqlx::query_as!(
-        Credit,
+/// Fingerprints and encrypts credit tokens stored in plaintext by earlier
+/// versions. Returns the number of rows that were migrated.
+#[tracing::instrument(skip_all, fields(db.system = "postgresql"), err)]
+pub async fn seal_plaintext_credits(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> Result<u64, sqlx::Error> {
+    let rows = sqlx::query!(
         r#"
-        SELECT 
-            id,
-            created_at,
-            token,
-            amount,
-            redeemed
+        SELECT id, token_plaintext as "token_plaintext!"
         FROM credits
-        ORDER BY created_at
-        LIMIT $1 OFFSET $2
-        "#,
-        page_size,
-        offset
+        WHERE token_plaintext IS NOT NULL
+        "#
     )
     .fetch_all(pool)
     .await?;
 
-    Ok(CreditListResponse {
-        data: credits,
-        pagination: PaginationInfo {
+    let mut migrated = 0;
+    for row in rows {
+        let token_encrypted = cipher
+            .encrypt(&row.token_plaintext)
+            .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+        migrated += sqlx::query!(
+            r#"
+            UPDATE credits
+            SET token_fingerprint = $1,
+                token_encrypted = $2,
+                token_plaintext = NULL
+            WHERE id = $3
+            "#,
+            summarize_token(&row.token_plaintext).fingerprint,
+            token_encrypted,
+            row.id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(migrated)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CreditFilter {
+    pub redeemed: Option<bool>,
+    pub quarantined: Option<bool>,
+    pub min_amount_msat: Option<i64>,
+    pub max_amount_msat: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub search: Option<String>,
+}
+
+const CREDIT_COLUMNS: &str = r#"
+    SELECT
+        id,
+        created_at,
+        COALESCE(token_fingerprint, '') as token_fingerprint,
+        amount_msat,
+        unit,
+        mint_url,
+        redeemed,
+        request_id,
+        quarantine_reason
+    FROM credits
+"#;
+
+fn push_credit_filter(qb: &mut QueryBuilder<'_, Postgres>, filter: &CreditFilter) {
+    qb.push(" WHERE TRUE");
+    if let Some(redeemed) = filter.redeemed {
+        qb.push(" AND redeemed = ").push_bind(redeemed);
+    }
+    if let Some(quarantined) = filter.quarantined {
+        qb.push(" AND (quarantine_reason IS NOT NULL) = ")
+            .push_bind(quarantined);
+    }
+    if let Some(min) = filter.min_amount_msat {
+        qb.push(" AND amount_msat >= ").push_bind(min);
+    }
+    if let Some(max) = filter.max_amount_msat {
+        qb.push(" AND amount_msat <= ").push_bind(max);
+    }
+    if let Some(from) = filter.from {
+        qb.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        qb.push(" AND created_at < ").push_bind(to);
+    }
+    if let Some(search) = filter.search.as_deref().filter(|s| !s.is_empty()) {
+        let pattern = format!("%{}%", search.replace('%', "\\%").replace('_', "\\_"));
+        qb.push(" AND (id::TEXT ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR mint_url ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+}
+
+#[tracing::instrument(skip_all, fields(db.system = "postgresql"), err)]
+pub async fn get_credits(
+    pool: &PgPool,
+    filter: &CreditFilter,
+    options: &ListOptions,
+) -> Result<CreditListResponse, sqlx::Error> {
+    let page_size = options.page_size();
+
+    let pagination = if options.cursor.is_none() {
+        let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM credits");
+        push_credit_filter(&mut qb, filter);
+        let total: i64 = qb.build_query_scalar().fetch_one(pool).await?;
+
+        Some(PaginationInfo {
             total,
-            page,
+            page: options.page(),
             page_size,
-            total_pages,
-        },
+            total_pages: (total + page_size - 1) / page_size,
+        })
+    } else {
+        None
+    };
+
+    let mut qb = QueryBuilder::new(CREDIT_COLUMNS);
+    push_credit_filter(&mut qb, filter);
+    options.push_keyset(&mut qb);
+    options.push_order_and_limit(&mut qb);
+    let credits: Vec<Credit> = qb.build_query_as().fetch_all(pool).await?;
+
+    let next_cursor = match credits.last() {
+        Some(last) if credits.len() as i64 == page_size => Some(
+            Cursor {
+                created_at: last.created_at,
+                amount_msat: last.amount_msat,
+                id: last.id,
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    Ok(CreditListResponse {
+        data: credits,
+        pagination,
+        next_cursor,
     })
 }
+
+#[tracing::instrument(skip(pool), fields(db.system = "postgresql"), err)]
+pub async fn get_credit(pool: &PgPool, id: Uuid) -> Result<Option<Credit>, sqlx::Error> {
+    let mut qb = QueryBuilder::new(CREDIT_COLUMNS);
+    qb.push(" WHERE id = ").push_bind(id);
+    qb.build_query_as().fetch_optional(pool).await
+}
+
+/// Marks a credit as redeemed into the wallet. Returns false when it was
+/// redeemed already.
+#[tracing::instrument(skip(pool), fields(db.system = "postgresql"), err)]
+pub async fn mark_credit_redeemed(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE credits
+        SET redeemed = TRUE
+        WHERE id = $1 AND NOT redeemed
+        "#,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Streams every credit matching `filter` in chronological order. The tokens
+/// themselves are left out of exports.
+pub fn stream_credits<'a>(
+    pool: &'a PgPool,
+    filter: &'a CreditExportFilter,
+) -> BoxStream<'a, Result<CreditExportRow, sqlx::Error>> {
+    sqlx::query_as!(
+        CreditExportRow,
+        r#"
+        SELECT
+            c.id,
+            c.created_at,
+            c.amount_msat,
+            c.unit,
+            c.mint_url,
+            c.redeemed,
+            c.request_id,
+            r.model as "model?",
+            r.endpoint as "endpoint?"
+        FROM credits c
+        LEFT JOIN requests r ON r.id = c.request_id
+        WHERE ($1::TIMESTAMPTZ IS NULL OR c.created_at >= $1)
+          AND ($2::TIMESTAMPTZ IS NULL OR c.created_at < $2)
+          AND ($3::TEXT IS NULL OR r.model = $3)
+        ORDER BY c.created_at, c.id
+        "#,
+        filter.from,
+        filter.to,
+        filter.model
+    )
+    .fetch(pool)
+}
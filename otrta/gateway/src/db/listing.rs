@@ -0,0 +1,121 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 10;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    CreatedAt,
+    Amount,
+}
+
+impl SortField {
+    fn column(&self) -> &'static str {
+        match self {
+            SortField::CreatedAt => "created_at",
+            SortField::Amount => "amount_msat",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    fn keyword(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Position of the last row of a page, handed to clients as an opaque string
+/// so the next page can be fetched with a keyset query instead of an offset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub amount_msat: i64,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(encoded).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ListOptions {
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+    pub cursor: Option<Cursor>,
+}
+
+impl ListOptions {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn page_size(&self) -> i64 {
+        self.page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    /// Offset pagination is only used when no cursor was given.
+    pub fn offset(&self) -> i64 {
+        if self.cursor.is_some() {
+            0
+        } else {
+            (self.page() - 1) * self.page_size()
+        }
+    }
+
+    /// Appends the keyset condition for the cursor. Expects a `WHERE` clause
+    /// to have been started already.
+    pub fn push_keyset(&self, qb: &mut QueryBuilder<'_, Postgres>) {
+        let Some(cursor) = &self.cursor else {
+            return;
+        };
+        let op = match self.order {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        };
+
+        qb.push(format!(" AND ({}, id) {} (", self.sort.column(), op));
+        match self.sort {
+            SortField::CreatedAt => qb.push_bind(cursor.created_at),
+            SortField::Amount => qb.push_bind(cursor.amount_msat),
+        };
+        qb.push(", ").push_bind(cursor.id).push(")");
+    }
+
+    pub fn push_order_and_limit(&self, qb: &mut QueryBuilder<'_, Postgres>) {
+        qb.push(format!(
+            " ORDER BY {col} {dir}, id {dir} LIMIT ",
+            col = self.sort.column(),
+            dir = self.order.keyword()
+        ));
+        qb.push_bind(self.page_size());
+        qb.push(" OFFSET ").push_bind(self.offset());
+    }
+}
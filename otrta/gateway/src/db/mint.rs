@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "mint_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MintStatus {
+    Allowed,
+    Denied,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MintRecord {
+    pub url: String,
+    pub status: Option<MintStatus>,
+    pub max_balance_sats: Option<i64>,
+    /// The mint's NUT-06 info as last fetched.
+    pub info: Option<serde_json::Value>,
+    pub info_fetched_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub async fn get_mints(pool: &PgPool) -> Result<Vec<MintRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        MintRecord,
+        r#"
+        SELECT url, status as "status: MintStatus", max_balance_sats, info, info_fetched_at,
+               created_at, updated_at
+        FROM mints
+        ORDER BY url
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_mint(pool: &PgPool, url: &str) -> Result<Option<MintRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        MintRecord,
+        r#"
+        SELECT url, status as "status: MintStatus", max_balance_sats, info, info_fetched_at,
+               created_at, updated_at
+        FROM mints
+        WHERE url = $1
+        "#,
+        url
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Sets the trust rule of a mint, keeping its info.
+pub async fn upsert_mint_rule(
+    pool: &PgPool,
+    url: &str,
+    status: Option<MintStatus>,
+    max_balance_sats: Option<i64>,
+) -> Result<MintRecord, sqlx::Error> {
+    sqlx::query_as!(
+        MintRecord,
+        r#"
+        INSERT INTO mints (url, status, max_balance_sats)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (url) DO UPDATE
+        SET status = EXCLUDED.status,
+            max_balance_sats = EXCLUDED.max_balance_sats,
+            updated_at = NOW()
+        RETURNING url, status as "status: MintStatus", max_balance_sats, info, info_fetched_at,
+                  created_at, updated_at
+        "#,
+        url,
+        status as Option<MintStatus>,
+        max_balance_sats
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Records a mint seen in a token, without any rule, unless `max_unreviewed`
+/// mints without a rule are recorded already.
+pub async fn record_mint(
+    pool: &PgPool,
+    url: &str,
+    max_unreviewed: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO mints (url)
+        SELECT $1
+        WHERE (SELECT COUNT(*) FROM mints WHERE status IS NULL) < $2
+        ON CONFLICT (url) DO NOTHING
+        "#,
+        url,
+        max_unreviewed
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn store_mint_info(
+    pool: &PgPool,
+    url: &str,
+    info: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO mints (url, info, info_fetched_at)
+        VALUES ($1, $2, NOW())
+        ON CONFLICT (url) DO UPDATE
+        SET info = EXCLUDED.info, info_fetched_at = EXCLUDED.info_fetched_at
+        "#,
+        url,
+        info
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_mint(pool: &PgPool, url: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM mints
+        WHERE url = $1
+        "#,
+        url
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
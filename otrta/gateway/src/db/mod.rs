@@ -1,7 +1,18 @@
+pub mod analytics;
 pub mod credit;
 pub mod helpers;
+pub mod listing;
+pub mod mint;
+pub mod pricing;
+pub mod request;
 pub mod server_config;
+pub mod topup;
 pub mod transaction;
+pub mod transfer;
+pub mod wallet_seed;
+pub mod withdrawal;
 
 pub use helpers::*;
 pub type Pool = sqlx::PgPool;
+
+pub const MSAT_PER_SAT: i64 = 1000;
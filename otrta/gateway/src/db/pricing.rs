@@ -0,0 +1,157 @@
+use crate::crypto::SecretCipher;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use wallet::models::ModelPricing;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelPriceRecord {
+    pub model: String,
+    #[serde(flatten)]
+    pub pricing: ModelPricing,
+    pub updated_at: DateTime<Utc>,
+}
+
+struct ModelPriceRow {
+    model: String,
+    prompt_msat_per_1k: i64,
+    completion_msat_per_1k: i64,
+    request_msat: i64,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<ModelPriceRow> for ModelPriceRecord {
+    fn from(row: ModelPriceRow) -> Self {
+        Self {
+            model: row.model,
+            pricing: ModelPricing {
+                prompt_msat_per_1k: row.prompt_msat_per_1k,
+                completion_msat_per_1k: row.completion_msat_per_1k,
+                request_msat: row.request_msat,
+            },
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+pub async fn get_model_prices(pool: &PgPool) -> Result<Vec<ModelPriceRecord>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        ModelPriceRow,
+        r#"
+        SELECT model, prompt_msat_per_1k, completion_msat_per_1k, request_msat, updated_at
+        FROM model_prices
+        ORDER BY model
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(ModelPriceRecord::from).collect())
+}
+
+pub async fn get_model_price(
+    pool: &PgPool,
+    model: &str,
+) -> Result<Option<ModelPriceRecord>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        ModelPriceRow,
+        r#"
+        SELECT model, prompt_msat_per_1k, completion_msat_per_1k, request_msat, updated_at
+        FROM model_prices
+        WHERE model = $1
+        "#,
+        model
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(ModelPriceRecord::from))
+}
+
+pub async fn upsert_model_price(
+    pool: &PgPool,
+    model: &str,
+    pricing: &ModelPricing,
+) -> Result<ModelPriceRecord, sqlx::Error> {
+    let row = sqlx::query_as!(
+        ModelPriceRow,
+        r#"
+        INSERT INTO model_prices (model, prompt_msat_per_1k, completion_msat_per_1k, request_msat)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (model) DO UPDATE SET
+            prompt_msat_per_1k = EXCLUDED.prompt_msat_per_1k,
+            completion_msat_per_1k = EXCLUDED.completion_msat_per_1k,
+            request_msat = EXCLUDED.request_msat,
+            updated_at = NOW()
+        RETURNING model, prompt_msat_per_1k, completion_msat_per_1k, request_msat, updated_at
+        "#,
+        model,
+        pricing.prompt_msat_per_1k,
+        pricing.completion_msat_per_1k,
+        pricing.request_msat
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.into())
+}
+
+pub async fn delete_model_price(pool: &PgPool, model: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM model_prices WHERE model = $1
+        "#,
+        model
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// The charge signing key generated on first run, if any.
+pub async fn get_signing_key(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> Result<Option<SecretString>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT secret_key_encrypted FROM provider_signing_key WHERE id = 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| {
+        cipher
+            .decrypt(&row.secret_key_encrypted)
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    })
+    .transpose()
+}
+
+/// Stores the signing key unless one exists already. Returns whether it was
+/// stored.
+pub async fn store_signing_key(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+    secret_key: &SecretString,
+) -> Result<bool, sqlx::Error> {
+    let encrypted = cipher
+        .encrypt(secret_key.expose_secret())
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO provider_signing_key (id, secret_key_encrypted)
+        VALUES (1, $1)
+        ON CONFLICT (id) DO NOTHING
+        "#,
+        encrypted
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
@@ -0,0 +1,208 @@
+use crate::{db::transaction::PaginationInfo, usage::Usage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub provider: String,
+    pub client_key: Option<String>,
+    pub http_status: Option<i32>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub cost_msat: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewRequest {
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub provider: String,
+    pub client_key: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RequestFilter {
+    pub model: Option<String>,
+    pub endpoint: Option<String>,
+    pub provider: Option<String>,
+    pub client_key: Option<String>,
+    pub status: Option<i32>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RequestListResponse {
+    pub data: Vec<Request>,
+    pub pagination: PaginationInfo,
+    pub total_cost_msat: i64,
+}
+
+pub async fn create_request(pool: &PgPool, request: &NewRequest) -> Result<Uuid, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        INSERT INTO requests (id, created_at, endpoint, model, provider, client_key)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+        request.endpoint,
+        request.model,
+        request.provider,
+        request.client_key
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rec.id)
+}
+
+pub async fn set_request_cost(pool: &PgPool, id: Uuid, cost_msat: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE requests SET cost_msat = $1 WHERE id = $2
+        "#,
+        cost_msat,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn complete_request(
+    pool: &PgPool,
+    id: Uuid,
+    http_status: Option<i32>,
+    usage: Option<&Usage>,
+    latency_ms: i64,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let usage = usage.cloned().unwrap_or_default();
+
+    sqlx::query!(
+        r#"
+        UPDATE requests
+        SET http_status = $1,
+            prompt_tokens = $2,
+            completion_tokens = $3,
+            total_tokens = $4,
+            latency_ms = $5,
+            error = $6
+        WHERE id = $7
+        "#,
+        http_status,
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.total_tokens,
+        latency_ms,
+        error,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_requests(
+    pool: &PgPool,
+    filter: &RequestFilter,
+    page: Option<i64>,
+    page_size: Option<i64>,
+) -> Result<RequestListResponse, sqlx::Error> {
+    let page = page.unwrap_or(1);
+    let page_size = page_size.unwrap_or(10);
+
+    let offset = (page - 1) * page_size;
+
+    let summary = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) as "total!",
+            COALESCE(SUM(cost_msat), 0)::BIGINT as "total_cost_msat!"
+        FROM requests
+        WHERE ($1::TEXT IS NULL OR model = $1)
+          AND ($2::TEXT IS NULL OR endpoint = $2)
+          AND ($3::TEXT IS NULL OR provider = $3)
+          AND ($4::TEXT IS NULL OR client_key = $4)
+          AND ($5::INTEGER IS NULL OR http_status = $5)
+          AND ($6::TIMESTAMPTZ IS NULL OR created_at >= $6)
+          AND ($7::TIMESTAMPTZ IS NULL OR created_at < $7)
+        "#,
+        filter.model,
+        filter.endpoint,
+        filter.provider,
+        filter.client_key,
+        filter.status,
+        filter.from,
+        filter.to
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let total_pages = (summary.total + page_size - 1) / page_size;
+
+    let requests = sqlx::query_as!(
+        Request,
+        r#"
+        SELECT
+            id,
+            created_at,
+            endpoint,
+            model,
+            provider,
+            client_key,
+            http_status,
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            latency_ms,
+            cost_msat,
+            error
+        FROM requests
+        WHERE ($1::TEXT IS NULL OR model = $1)
+          AND ($2::TEXT IS NULL OR endpoint = $2)
+          AND ($3::TEXT IS NULL OR provider = $3)
+          AND ($4::TEXT IS NULL OR client_key = $4)
+          AND ($5::INTEGER IS NULL OR http_status = $5)
+          AND ($6::TIMESTAMPTZ IS NULL OR created_at >= $6)
+          AND ($7::TIMESTAMPTZ IS NULL OR created_at < $7)
+        ORDER BY created_at DESC
+        LIMIT $8 OFFSET $9
+        "#,
+        filter.model,
+        filter.endpoint,
+        filter.provider,
+        filter.client_key,
+        filter.status,
+        filter.from,
+        filter.to,
+        page_size,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(RequestListResponse {
+        data: requests,
+        pagination: PaginationInfo {
+            total: summary.total,
+            page,
+            page_size,
+            total_pages,
+        },
+        total_cost_msat: summary.total_cost_msat,
+    })
+}
@@ -1,44 +1,85 @@
+use crate::crypto::{SecretCipher, mask_secret};
 use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
 use wallet::models::ServerConfig;
 
 pub struct ServerConfigRecord {
     pub id: String,
     pub endpoint: String,
-    pub api_key: String,
+    pub api_key: SecretString,
+    pub accepted_mints: Vec<String>,
+    pub p2pk_pubkey: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-pub async fn get_all_configs(pool: &PgPool) -> Result<Vec<ServerConfigRecord>, sqlx::Error> {
-    let configs = sqlx::query!(
+struct ServerConfigRow {
+    id: String,
+    endpoint: String,
+    api_key_encrypted: Option<String>,
+    accepted_mints: Vec<String>,
+    p2pk_pubkey: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl ServerConfigRow {
+    fn decrypt(self, cipher: &SecretCipher) -> Result<ServerConfigRecord, sqlx::Error> {
+        let api_key = match self.api_key_encrypted {
+            Some(encrypted) => cipher
+                .decrypt(&encrypted)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            None => SecretString::from(String::new()),
+        };
+
+        Ok(ServerConfigRecord {
+            id: self.id,
+            endpoint: self.endpoint,
+            api_key,
+            accepted_mints: self.accepted_mints,
+            p2pk_pubkey: self.p2pk_pubkey,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        })
+    }
+}
+
+fn encrypt_api_key(cipher: &SecretCipher, api_key: &str) -> Result<String, sqlx::Error> {
+    cipher
+        .encrypt(api_key)
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))
+}
+
+pub async fn get_all_configs(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> Result<Vec<ServerConfigRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ServerConfigRow,
         r#"
-        SELECT id, endpoint, api_key, created_at, updated_at
+        SELECT id, endpoint, api_key_encrypted, accepted_mints, p2pk_pubkey, created_at,
+               updated_at
         FROM server_config
         "#
     )
     .fetch_all(pool)
     .await?
     .into_iter()
-    .map(|record| ServerConfigRecord {
-        id: record.id,
-        endpoint: record.endpoint,
-        api_key: record.api_key,
-        created_at: record.created_at,
-        updated_at: record.updated_at,
-    })
-    .collect();
-
-    Ok(configs)
+    .map(|row| row.decrypt(cipher))
+    .collect()
 }
 
 pub async fn get_config_by_id(
     pool: &PgPool,
+    cipher: &SecretCipher,
     id: &str,
 ) -> Result<Option<ServerConfigRecord>, sqlx::Error> {
-    let record = sqlx::query!(
+    let row = sqlx::query_as!(
+        ServerConfigRow,
         r#"
-        SELECT id, endpoint, api_key, created_at, updated_at
+        SELECT id, endpoint, api_key_encrypted, accepted_mints, p2pk_pubkey, created_at,
+               updated_at
         FROM server_config
         WHERE id = $1
         "#,
@@ -47,22 +88,18 @@ pub async fn get_config_by_id(
     .fetch_optional(pool)
     .await?;
 
-    match record {
-        Some(r) => Ok(Some(ServerConfigRecord {
-            id: r.id,
-            endpoint: r.endpoint,
-            api_key: r.api_key,
-            created_at: r.created_at,
-            updated_at: r.updated_at,
-        })),
-        None => Ok(None),
-    }
+    row.map(|r| r.decrypt(cipher)).transpose()
 }
 
-pub async fn get_default_config(pool: &PgPool) -> Result<Option<ServerConfigRecord>, sqlx::Error> {
-    let record = sqlx::query!(
+pub async fn get_default_config(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> Result<Option<ServerConfigRecord>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        ServerConfigRow,
         r#"
-        SELECT id, endpoint, api_key, created_at, updated_at
+        SELECT id, endpoint, api_key_encrypted, accepted_mints, p2pk_pubkey, created_at,
+               updated_at
         FROM server_config
         ORDER BY created_at ASC
         LIMIT 1
@@ -71,75 +108,109 @@ pub async fn get_default_config(pool: &PgPool) -> Result<Option<ServerConfigReco
     .fetch_optional(pool)
     .await?;
 
-    match record {
-        Some(r) => Ok(Some(ServerConfigRecord {
-            id: r.id,
-            endpoint: r.endpoint,
-            api_key: r.api_key,
-            created_at: r.created_at,
-            updated_at: r.updated_at,
-        })),
-        None => Ok(None),
-    }
+    row.map(|r| r.decrypt(cipher)).transpose()
 }
 
 pub async fn create_config(
     pool: &PgPool,
+    cipher: &SecretCipher,
     config: &ServerConfig,
 ) -> Result<ServerConfigRecord, sqlx::Error> {
     let id = format!(
         "config_{}",
         uuid::Uuid::new_v4().to_string().replace("-", "")
     );
+    let api_key_encrypted = encrypt_api_key(cipher, &config.api_key)?;
 
-    let record = sqlx::query!(
+    let row = sqlx::query_as!(
+        ServerConfigRow,
         r#"
-        INSERT INTO server_config (id, endpoint, api_key, created_at)
-        VALUES ($1, $2, $3, NOW())
-        RETURNING id, endpoint, api_key, created_at, updated_at
+        INSERT INTO server_config
+            (id, endpoint, api_key_encrypted, accepted_mints, p2pk_pubkey, created_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        RETURNING id, endpoint, api_key_encrypted, accepted_mints, p2pk_pubkey, created_at,
+                  updated_at
         "#,
         id,
         config.endpoint,
-        config.api_key
+        api_key_encrypted,
+        &config.accepted_mints,
+        config.p2pk_pubkey
     )
     .fetch_one(pool)
     .await?;
 
-    Ok(ServerConfigRecord {
-        id: record.id,
-        endpoint: record.endpoint,
-        api_key: record.api_key,
-        created_at: record.created_at,
-        updated_at: record.updated_at,
-    })
+    row.decrypt(cipher)
 }
 
 pub async fn update_config(
     pool: &PgPool,
+    cipher: &SecretCipher,
     id: String,
     config: &ServerConfig,
 ) -> Result<ServerConfigRecord, sqlx::Error> {
-    let record = sqlx::query!(
+    let api_key_encrypted = encrypt_api_key(cipher, &config.api_key)?;
+
+    let row = sqlx::query_as!(
+        ServerConfigRow,
         r#"
         UPDATE server_config
-        SET endpoint = $1, api_key = $2, updated_at = NOW()
-        WHERE id = $3
-        RETURNING id, endpoint, api_key, created_at, updated_at
+        SET endpoint = $1, api_key_encrypted = $2, accepted_mints = $3, p2pk_pubkey = $4,
+            api_key_plaintext = NULL, updated_at = NOW()
+        WHERE id = $5
+        RETURNING id, endpoint, api_key_encrypted, accepted_mints, p2pk_pubkey, created_at,
+                  updated_at
         "#,
         config.endpoint,
-        config.api_key,
+        api_key_encrypted,
+        &config.accepted_mints,
+        config.p2pk_pubkey,
         id
     )
     .fetch_one(pool)
     .await?;
 
-    Ok(ServerConfigRecord {
-        id: record.id,
-        endpoint: record.endpoint,
-        api_key: record.api_key,
-        created_at: record.created_at,
-        updated_at: record.updated_at,
-    })
+    row.decrypt(cipher)
+}
+
+/// Encrypts API keys left in plaintext by installations that predate
+/// encryption at rest, all of them or none. Returns the number of rows that
+/// were migrated.
+pub async fn encrypt_plaintext_api_keys(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, api_key_plaintext as "api_key_plaintext!"
+        FROM server_config
+        WHERE api_key_plaintext IS NOT NULL
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut migrated = 0;
+    for row in rows {
+        let api_key_encrypted = encrypt_api_key(cipher, &row.api_key_plaintext)?;
+        migrated += sqlx::query!(
+            r#"
+            UPDATE server_config
+            SET api_key_encrypted = $1, api_key_plaintext = NULL
+            WHERE id = $2
+            "#,
+            api_key_encrypted,
+            row.id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(migrated)
 }
 
 pub async fn delete_config(pool: &PgPool, id: &str) -> Result<bool, sqlx::Error> {
@@ -182,10 +253,13 @@ pub async fn count_configs(pool: &PgPool) -> Result<i64, sqlx::Error> {
 }
 
 impl ServerConfigRecord {
+    /// API representation of the record. The API key is always masked.
     pub fn to_model(&self) -> ServerConfig {
         ServerConfig {
             endpoint: self.endpoint.clone(),
-            api_key: self.api_key.clone(),
+            api_key: mask_secret(self.api_key.expose_secret()),
+            accepted_mints: self.accepted_mints.clone(),
+            p2pk_pubkey: self.p2pk_pubkey.clone(),
         }
     }
 }
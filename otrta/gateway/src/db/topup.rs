@@ -0,0 +1,156 @@
+use crate::db::MSAT_PER_SAT;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "topup_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TopupStatus {
+    Pending,
+    Paid,
+    Expired,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Topup {
+    pub id: Uuid,
+    pub amount_sats: i64,
+    pub mint_url: Option<String>,
+    pub quote_id: Option<String>,
+    /// The bolt11 invoice to pay.
+    pub payment_request: String,
+    pub status: TopupStatus,
+    pub error: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub async fn add_topup(
+    pool: &PgPool,
+    amount_sats: i64,
+    mint_url: Option<&str>,
+    quote_id: Option<&str>,
+    payment_request: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<Topup, sqlx::Error> {
+    sqlx::query_as!(
+        Topup,
+        r#"
+        INSERT INTO topups (id, amount_sats, mint_url, quote_id, payment_request, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, amount_sats, mint_url, quote_id, payment_request,
+                  status as "status: TopupStatus", error, expires_at, paid_at,
+                  created_at, updated_at
+        "#,
+        Uuid::new_v4(),
+        amount_sats,
+        mint_url,
+        quote_id,
+        payment_request,
+        expires_at
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_topup(pool: &PgPool, id: Uuid) -> Result<Option<Topup>, sqlx::Error> {
+    sqlx::query_as!(
+        Topup,
+        r#"
+        SELECT id, amount_sats, mint_url, quote_id, payment_request,
+               status as "status: TopupStatus", error, expires_at, paid_at,
+               created_at, updated_at
+        FROM topups
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_pending_topups(pool: &PgPool) -> Result<Vec<Topup>, sqlx::Error> {
+    sqlx::query_as!(
+        Topup,
+        r#"
+        SELECT id, amount_sats, mint_url, quote_id, payment_request,
+               status as "status: TopupStatus", error, expires_at, paid_at,
+               created_at, updated_at
+        FROM topups
+        WHERE status = 'pending'
+        ORDER BY created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Marks a pending top-up paid and books the deposit, in one transaction.
+/// Returns the deposit's transaction id, `None` when the top-up was no
+/// longer pending.
+#[tracing::instrument(skip(pool), fields(db.system = "postgresql"), err)]
+pub async fn complete_topup(pool: &PgPool, id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let Some(topup) = sqlx::query!(
+        r#"
+        UPDATE topups
+        SET status = 'paid', paid_at = NOW(), updated_at = NOW()
+        WHERE id = $1 AND status = 'pending'
+        RETURNING amount_sats, mint_url
+        "#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let rec = sqlx::query!(
+        r#"
+        INSERT INTO transactions
+            (id, created_at, mint_url, amount_msat, unit, direction, topup_id)
+        VALUES ($1, $2, $3, $4, 'sat', 'Incoming', $5)
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+        topup.mint_url,
+        topup.amount_sats * MSAT_PER_SAT,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(rec.id))
+}
+
+/// Ends a pending top-up that will never be paid out.
+pub async fn close_topup(
+    pool: &PgPool,
+    id: Uuid,
+    status: TopupStatus,
+    error: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE topups
+        SET status = $2, error = $3, updated_at = NOW()
+        WHERE id = $1 AND status = 'pending'
+        "#,
+        id,
+        status as TopupStatus,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
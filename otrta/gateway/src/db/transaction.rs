@@ -1,6 +1,12 @@
+use crate::{
+    crypto::SecretCipher,
+    db::listing::{Cursor, ListOptions},
+    token::summarize_token,
+};
 use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, sqlx::Type, Serialize, Deserialize)]
@@ -20,13 +26,70 @@ impl From<String> for TransactionDirection {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Outcome of verifying the DLEQ proofs (NUT-12) of an incoming token.
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "dleq_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DleqStatus {
+    Valid,
+    /// Some proofs carried none, from a mint not known to support NUT-12.
+    Missing,
+    Invalid,
+}
+
+impl From<wallet::api::models::DleqStatus> for DleqStatus {
+    fn from(status: wallet::api::models::DleqStatus) -> Self {
+        match status {
+            wallet::api::models::DleqStatus::Valid => DleqStatus::Valid,
+            wallet::api::models::DleqStatus::Missing => DleqStatus::Missing,
+            wallet::api::models::DleqStatus::Invalid => DleqStatus::Invalid,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Transaction {
-    pub id: String,
+    pub id: Uuid,
     pub created_at: DateTime<Utc>,
-    pub token: String,
-    pub amount: String,
+    pub token_fingerprint: String,
+    pub mint_url: Option<String>,
+    pub keyset_ids: Vec<String>,
+    pub amount_msat: i64,
+    pub unit: String,
     pub direction: TransactionDirection,
+    pub request_id: Option<Uuid>,
+    /// Set for Lightning deposits, which have no token.
+    pub topup_id: Option<Uuid>,
+    /// Set for Lightning withdrawals, along with the fee they cost.
+    pub withdrawal_id: Option<Uuid>,
+    pub fee_msat: Option<i64>,
+    /// Set for tokens sent out of the wallet and for their reclaim.
+    pub transfer_id: Option<Uuid>,
+    /// Set for incoming tokens whose DLEQ proofs the wallet could check.
+    pub dleq_status: Option<DleqStatus>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransactionExportRow {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub direction: TransactionDirection,
+    pub amount_msat: i64,
+    pub unit: String,
+    pub mint_url: Option<String>,
+    pub token_fingerprint: Option<String>,
+    pub keyset_ids: Vec<String>,
+    pub request_id: Option<Uuid>,
+    pub model: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TransactionExportFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub direction: Option<TransactionDirection>,
+    pub model: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -40,26 +103,48 @@ pub struct PaginationInfo {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionListResponse {
     pub data: Vec<Transaction>,
-    pub pagination: PaginationInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PaginationInfo>,
+    pub next_cursor: Option<String>,
 }
 
+/// Records a transaction. Only a fingerprint of `token` is stored in the clear,
+/// the token itself is kept encrypted for recovery and never returned by the API.
+/// Amounts are always sats; the mint policy refuses tokens in other units.
+#[tracing::instrument(skip(pool, cipher, token), fields(db.system = "postgresql"), err)]
 pub async fn add_transaction(
     pool: &PgPool,
+    cipher: &SecretCipher,
     token: &str,
-    amount: &str,
+    amount_msat: i64,
     direction: TransactionDirection,
+    request_id: Option<Uuid>,
+    dleq_status: Option<DleqStatus>,
 ) -> Result<Uuid, sqlx::Error> {
+    let summary = summarize_token(token);
+    let token_encrypted = cipher
+        .encrypt(token)
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
     let rec = sqlx::query!(
         r#"
-        INSERT INTO transactions (id, created_at, token, amount, direction)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO transactions
+            (id, created_at, token_fingerprint, token_encrypted, mint_url, keyset_ids,
+             amount_msat, unit, direction, request_id, dleq_status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         RETURNING id
         "#,
         Uuid::new_v4(),
         Utc::now(),
-        token,
-        amount,
-        direction as TransactionDirection
+        summary.fingerprint,
+        token_encrypted,
+        summary.mint_url,
+        &summary.keyset_ids,
+        amount_msat,
+        "sat",
+        direction as TransactionDirection,
+        request_id,
+        dleq_status as Option<DleqStatus>
     )
     .fetch_one(pool)
     .await?;
@@ -67,49 +152,203 @@ pub async fn add_transaction(
     Ok(rec.id)
 }
 
-pub async fn get_transactions(
+/// Fingerprints and encrypts tokens stored in plaintext by earlier versions.
+/// Returns the number of rows that were migrated.
+#[tracing::instrument(skip_all, fields(db.system = "postgresql"), err)]
+pub async fn seal_plaintext_tokens(
     pool: &PgPool,
-    page: Option<i64>,
-    page_size: Option<i64>,
-) -> Result<TransactionListResponse, sqlx::Error> {
-    let page = page.unwrap_or(1);
-    let page_size = page_size.unwrap_or(10);
-
-    let offset = (page - 1) * page_size;
-
-    let total = sqlx::query_scalar(r#"SELECT COUNT(*) FROM transactions"#)
-        .fetch_one(pool)
-        .await
-        .unwrap_or(0);
-
-    let total_pages = (total + page_size - 1) / page_size;
-
-    let credits = sqlx::query_as!(
-        Transaction,
+    cipher: &SecretCipher,
+) -> Result<u64, sqlx::Error> {
+    let rows = sqlx::query!(
         r#"
-        SELECT 
-            id,
-            created_at,
-            token,
-            amount,
-            direction as "direction: TransactionDirection"
+        SELECT id, token_plaintext as "token_plaintext!"
         FROM transactions
-        ORDER BY created_at DESC
-        LIMIT $1 OFFSET $2
-        "#,
-        page_size,
-        offset
+        WHERE token_plaintext IS NOT NULL
+        "#
     )
     .fetch_all(pool)
     .await?;
 
-    Ok(TransactionListResponse {
-        data: credits,
-        pagination: PaginationInfo {
+    let mut migrated = 0;
+    for row in rows {
+        let summary = summarize_token(&row.token_plaintext);
+        let token_encrypted = cipher
+            .encrypt(&row.token_plaintext)
+            .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+        migrated += sqlx::query!(
+            r#"
+            UPDATE transactions
+            SET token_fingerprint = $1,
+                token_encrypted = $2,
+                mint_url = $3,
+                keyset_ids = $4,
+                token_plaintext = NULL
+            WHERE id = $5
+            "#,
+            summary.fingerprint,
+            token_encrypted,
+            summary.mint_url,
+            &summary.keyset_ids,
+            row.id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(migrated)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TransactionFilter {
+    pub direction: Option<TransactionDirection>,
+    pub min_amount_msat: Option<i64>,
+    pub max_amount_msat: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub search: Option<String>,
+}
+
+const TRANSACTION_COLUMNS: &str = r#"
+    SELECT
+        id,
+        created_at,
+        COALESCE(token_fingerprint, '') as token_fingerprint,
+        mint_url,
+        keyset_ids,
+        amount_msat,
+        unit,
+        direction,
+        request_id,
+        topup_id,
+        withdrawal_id,
+        fee_msat,
+        transfer_id,
+        dleq_status
+    FROM transactions
+"#;
+
+fn push_transaction_filter(qb: &mut QueryBuilder<'_, Postgres>, filter: &TransactionFilter) {
+    qb.push(" WHERE TRUE");
+    if let Some(direction) = &filter.direction {
+        qb.push(" AND direction = ").push_bind(direction.clone());
+    }
+    if let Some(min) = filter.min_amount_msat {
+        qb.push(" AND amount_msat >= ").push_bind(min);
+    }
+    if let Some(max) = filter.max_amount_msat {
+        qb.push(" AND amount_msat <= ").push_bind(max);
+    }
+    if let Some(from) = filter.from {
+        qb.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        qb.push(" AND created_at < ").push_bind(to);
+    }
+    if let Some(search) = filter.search.as_deref().filter(|s| !s.is_empty()) {
+        let pattern = format!("%{}%", search.replace('%', "\\%").replace('_', "\\_"));
+        qb.push(" AND (id::TEXT ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR token_fingerprint ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR mint_url ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR array_to_string(keyset_ids, ' ') ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+}
+
+#[tracing::instrument(skip_all, fields(db.system = "postgresql"), err)]
+pub async fn get_transactions(
+    pool: &PgPool,
+    filter: &TransactionFilter,
+    options: &ListOptions,
+) -> Result<TransactionListResponse, sqlx::Error> {
+    let page_size = options.page_size();
+
+    // Counting is skipped in cursor mode, it defeats the point on large tables.
+    let pagination = if options.cursor.is_none() {
+        let mut qb = QueryBuilder::new("SELECT COUNT(*) FROM transactions");
+        push_transaction_filter(&mut qb, filter);
+        let total: i64 = qb.build_query_scalar().fetch_one(pool).await?;
+
+        Some(PaginationInfo {
             total,
-            page,
+            page: options.page(),
             page_size,
-            total_pages,
-        },
+            total_pages: (total + page_size - 1) / page_size,
+        })
+    } else {
+        None
+    };
+
+    let mut qb = QueryBuilder::new(TRANSACTION_COLUMNS);
+    push_transaction_filter(&mut qb, filter);
+    options.push_keyset(&mut qb);
+    options.push_order_and_limit(&mut qb);
+    let transactions: Vec<Transaction> = qb.build_query_as().fetch_all(pool).await?;
+
+    let next_cursor = match transactions.last() {
+        Some(last) if transactions.len() as i64 == page_size => Some(
+            Cursor {
+                created_at: last.created_at,
+                amount_msat: last.amount_msat,
+                id: last.id,
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    Ok(TransactionListResponse {
+        data: transactions,
+        pagination,
+        next_cursor,
     })
 }
+
+#[tracing::instrument(skip(pool), fields(db.system = "postgresql"), err)]
+pub async fn get_transaction(pool: &PgPool, id: Uuid) -> Result<Option<Transaction>, sqlx::Error> {
+    let mut qb = QueryBuilder::new(TRANSACTION_COLUMNS);
+    qb.push(" WHERE id = ").push_bind(id);
+    qb.build_query_as().fetch_optional(pool).await
+}
+
+/// Streams every transaction matching `filter` in chronological order, joined
+/// with the request it belongs to.
+pub fn stream_transactions<'a>(
+    pool: &'a PgPool,
+    filter: &'a TransactionExportFilter,
+) -> BoxStream<'a, Result<TransactionExportRow, sqlx::Error>> {
+    sqlx::query_as!(
+        TransactionExportRow,
+        r#"
+        SELECT
+            t.id,
+            t.created_at,
+            t.direction as "direction: TransactionDirection",
+            t.amount_msat,
+            t.unit,
+            t.mint_url,
+            t.token_fingerprint,
+            t.keyset_ids,
+            t.request_id,
+            r.model as "model?",
+            r.endpoint as "endpoint?"
+        FROM transactions t
+        LEFT JOIN requests r ON r.id = t.request_id
+        WHERE ($1::TIMESTAMPTZ IS NULL OR t.created_at >= $1)
+          AND ($2::TIMESTAMPTZ IS NULL OR t.created_at < $2)
+          AND ($3::transaction_direction IS NULL OR t.direction = $3)
+          AND ($4::TEXT IS NULL OR r.model = $4)
+        ORDER BY t.created_at, t.id
+        "#,
+        filter.from,
+        filter.to,
+        filter.direction.clone() as Option<TransactionDirection>,
+        filter.model
+    )
+    .fetch(pool)
+}
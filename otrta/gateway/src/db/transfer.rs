@@ -0,0 +1,187 @@
+use crate::{crypto::SecretCipher, db::MSAT_PER_SAT, token::summarize_token};
+use chrono::{DateTime, Utc};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "transfer_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStatus {
+    /// Sent, not claimed yet.
+    Pending,
+    Claimed,
+    /// Taken back into the wallet.
+    Reclaimed,
+}
+
+/// A token sent out of the wallet. The token itself is only handed out when
+/// it is sent.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transfer {
+    pub id: Uuid,
+    pub amount_sats: i64,
+    pub mint_url: Option<String>,
+    pub token_fingerprint: String,
+    pub lock_pubkey: Option<String>,
+    /// When a locked token can be taken back.
+    pub locked_until: Option<DateTime<Utc>>,
+    pub npub: Option<String>,
+    pub status: TransferStatus,
+    /// When it was claimed or reclaimed.
+    pub settled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewTransfer {
+    pub token: String,
+    pub amount_sats: i64,
+    pub lock_pubkey: Option<String>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub npub: Option<String>,
+}
+
+/// Records a sent token and books it as outgoing, in one transaction.
+#[tracing::instrument(skip_all, fields(db.system = "postgresql"), err)]
+pub async fn add_transfer(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+    transfer: &NewTransfer,
+) -> Result<Transfer, sqlx::Error> {
+    let summary = summarize_token(&transfer.token);
+    let token_encrypted = cipher
+        .encrypt(&transfer.token)
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+    let mut tx = pool.begin().await?;
+
+    let created = sqlx::query_as!(
+        Transfer,
+        r#"
+        INSERT INTO transfers
+            (id, amount_sats, mint_url, token_fingerprint, token_encrypted, lock_pubkey,
+             locked_until, npub)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id, amount_sats, mint_url, token_fingerprint, lock_pubkey, locked_until,
+                  npub, status as "status: TransferStatus", settled_at, created_at, updated_at
+        "#,
+        Uuid::new_v4(),
+        transfer.amount_sats,
+        summary.mint_url,
+        summary.fingerprint,
+        token_encrypted,
+        transfer.lock_pubkey,
+        transfer.locked_until,
+        transfer.npub
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO transactions
+            (id, created_at, token_fingerprint, mint_url, keyset_ids, amount_msat, unit,
+             direction, transfer_id)
+        VALUES ($1, $2, $3, $4, $5, $6, 'sat', 'Outgoing', $7)
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+        created.token_fingerprint,
+        created.mint_url,
+        &summary.keyset_ids,
+        created.amount_sats * MSAT_PER_SAT,
+        created.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(created)
+}
+
+pub async fn get_transfer(pool: &PgPool, id: Uuid) -> Result<Option<Transfer>, sqlx::Error> {
+    sqlx::query_as!(
+        Transfer,
+        r#"
+        SELECT id, amount_sats, mint_url, token_fingerprint, lock_pubkey, locked_until,
+               npub, status as "status: TransferStatus", settled_at, created_at, updated_at
+        FROM transfers
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool, cipher), fields(db.system = "postgresql"), err)]
+pub async fn get_transfer_token(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+    id: Uuid,
+) -> Result<Option<SecretString>, sqlx::Error> {
+    let Some(rec) = sqlx::query!("SELECT token_encrypted FROM transfers WHERE id = $1", id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    cipher
+        .decrypt(&rec.token_encrypted)
+        .map(Some)
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// Moves a transfer from `from` to `to`. `None` when it wasn't in `from`.
+pub async fn set_transfer_status(
+    pool: &PgPool,
+    id: Uuid,
+    from: TransferStatus,
+    to: TransferStatus,
+) -> Result<Option<Transfer>, sqlx::Error> {
+    sqlx::query_as!(
+        Transfer,
+        r#"
+        UPDATE transfers
+        SET status = $3,
+            settled_at = CASE WHEN $3 = 'pending'::transfer_status THEN NULL ELSE NOW() END,
+            updated_at = NOW()
+        WHERE id = $1 AND status = $2
+        RETURNING id, amount_sats, mint_url, token_fingerprint, lock_pubkey, locked_until,
+                  npub, status as "status: TransferStatus", settled_at, created_at, updated_at
+        "#,
+        id,
+        from as TransferStatus,
+        to as TransferStatus
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Books the ecash of a reclaimed transfer as incoming again.
+#[tracing::instrument(skip(pool), fields(db.system = "postgresql"), err)]
+pub async fn book_reclaimed_transfer(pool: &PgPool, id: Uuid) -> Result<Uuid, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        INSERT INTO transactions
+            (id, created_at, token_fingerprint, mint_url, amount_msat, unit, direction,
+             transfer_id)
+        SELECT $1, $2, token_fingerprint, mint_url, amount_sats * $3, 'sat', 'Incoming', id
+        FROM transfers
+        WHERE id = $4 AND status = 'reclaimed'
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+        MSAT_PER_SAT,
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rec.id)
+}
@@ -0,0 +1,49 @@
+use crate::crypto::SecretCipher;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+
+/// The mnemonic generated on first run, if any.
+pub async fn get_mnemonic(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+) -> Result<Option<SecretString>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT mnemonic_encrypted FROM wallet_seed WHERE id = 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| {
+        cipher
+            .decrypt(&row.mnemonic_encrypted)
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    })
+    .transpose()
+}
+
+/// Stores the mnemonic unless one exists already. Returns whether it was
+/// stored, so two instances racing on first run agree on a single seed.
+pub async fn store_mnemonic(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+    mnemonic: &SecretString,
+) -> Result<bool, sqlx::Error> {
+    let encrypted = cipher
+        .encrypt(mnemonic.expose_secret())
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO wallet_seed (id, mnemonic_encrypted)
+        VALUES (1, $1)
+        ON CONFLICT (id) DO NOTHING
+        "#,
+        encrypted
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
@@ -0,0 +1,216 @@
+use crate::db::MSAT_PER_SAT;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "withdrawal_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum WithdrawalStatus {
+    /// Waiting to be confirmed.
+    Quoted,
+    Pending,
+    Paid,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub id: Uuid,
+    pub bolt11: String,
+    /// Lightning Address or LNURL the invoice came from.
+    pub destination: Option<String>,
+    pub mint_url: Option<String>,
+    pub quote_id: String,
+    pub amount_sats: i64,
+    /// Most the mint may take in Lightning fees, held on top of the amount.
+    pub fee_reserve_sats: i64,
+    pub fee_paid_sats: Option<i64>,
+    pub status: WithdrawalStatus,
+    pub checking_id: Option<String>,
+    pub preimage: Option<String>,
+    pub error: Option<String>,
+    /// When the quote runs out, past which it can't be confirmed.
+    pub expires_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewWithdrawal {
+    pub bolt11: String,
+    pub destination: Option<String>,
+    pub mint_url: Option<String>,
+    pub quote_id: String,
+    pub amount_sats: i64,
+    pub fee_reserve_sats: i64,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub async fn add_withdrawal(
+    pool: &PgPool,
+    withdrawal: &NewWithdrawal,
+) -> Result<Withdrawal, sqlx::Error> {
+    sqlx::query_as!(
+        Withdrawal,
+        r#"
+        INSERT INTO withdrawals
+            (id, bolt11, destination, mint_url, quote_id, amount_sats, fee_reserve_sats,
+             expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id, bolt11, destination, mint_url, quote_id, amount_sats, fee_reserve_sats,
+                  fee_paid_sats, status as "status: WithdrawalStatus", checking_id, preimage, error,
+                  expires_at, paid_at, created_at, updated_at
+        "#,
+        Uuid::new_v4(),
+        withdrawal.bolt11,
+        withdrawal.destination,
+        withdrawal.mint_url,
+        withdrawal.quote_id,
+        withdrawal.amount_sats,
+        withdrawal.fee_reserve_sats,
+        withdrawal.expires_at
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_withdrawal(pool: &PgPool, id: Uuid) -> Result<Option<Withdrawal>, sqlx::Error> {
+    sqlx::query_as!(
+        Withdrawal,
+        r#"
+        SELECT id, bolt11, destination, mint_url, quote_id, amount_sats, fee_reserve_sats,
+               fee_paid_sats, status as "status: WithdrawalStatus", checking_id, preimage, error,
+               expires_at, paid_at, created_at, updated_at
+        FROM withdrawals
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_pending_withdrawals(pool: &PgPool) -> Result<Vec<Withdrawal>, sqlx::Error> {
+    sqlx::query_as!(
+        Withdrawal,
+        r#"
+        SELECT id, bolt11, destination, mint_url, quote_id, amount_sats, fee_reserve_sats,
+               fee_paid_sats, status as "status: WithdrawalStatus", checking_id, preimage, error,
+               expires_at, paid_at, created_at, updated_at
+        FROM withdrawals
+        WHERE status = 'pending'
+        ORDER BY created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Moves a quoted withdrawal whose quote is still valid to pending, so that
+/// it is paid once. `None` when it wasn't in that state.
+pub async fn begin_withdrawal(pool: &PgPool, id: Uuid) -> Result<Option<Withdrawal>, sqlx::Error> {
+    sqlx::query_as!(
+        Withdrawal,
+        r#"
+        UPDATE withdrawals
+        SET status = 'pending', updated_at = NOW()
+        WHERE id = $1 AND status = 'quoted' AND expires_at > NOW()
+        RETURNING id, bolt11, destination, mint_url, quote_id, amount_sats, fee_reserve_sats,
+                  fee_paid_sats, status as "status: WithdrawalStatus", checking_id, preimage, error,
+                  expires_at, paid_at, created_at, updated_at
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn set_withdrawal_checking_id(
+    pool: &PgPool,
+    id: Uuid,
+    checking_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE withdrawals
+        SET checking_id = $2, updated_at = NOW()
+        WHERE id = $1
+        "#,
+        id,
+        checking_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks a pending withdrawal paid and books it with the fee it cost, in
+/// one transaction. Returns the transaction id, `None` when the withdrawal
+/// was no longer pending.
+#[tracing::instrument(skip(pool, preimage), fields(db.system = "postgresql"), err)]
+pub async fn complete_withdrawal(
+    pool: &PgPool,
+    id: Uuid,
+    fee_paid_sats: Option<i64>,
+    preimage: Option<&str>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let Some(withdrawal) = sqlx::query!(
+        r#"
+        UPDATE withdrawals
+        SET status = 'paid', fee_paid_sats = $2, preimage = $3, paid_at = NOW(),
+            updated_at = NOW()
+        WHERE id = $1 AND status = 'pending'
+        RETURNING amount_sats, mint_url
+        "#,
+        id,
+        fee_paid_sats,
+        preimage
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let rec = sqlx::query!(
+        r#"
+        INSERT INTO transactions
+            (id, created_at, mint_url, amount_msat, fee_msat, unit, direction, withdrawal_id)
+        VALUES ($1, $2, $3, $4, $5, 'sat', 'Outgoing', $6)
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+        withdrawal.mint_url,
+        withdrawal.amount_sats * MSAT_PER_SAT,
+        fee_paid_sats.map(|fee| fee * MSAT_PER_SAT),
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(rec.id))
+}
+
+pub async fn fail_withdrawal(pool: &PgPool, id: Uuid, error: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE withdrawals
+        SET status = 'failed', error = $2, updated_at = NOW()
+        WHERE id = $1 AND status = 'pending'
+        "#,
+        id,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
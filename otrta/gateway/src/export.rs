@@ -0,0 +1,247 @@
+use crate::{
+    db::{
+        credit::{CreditExportFilter, CreditExportRow, stream_credits},
+        transaction::{
+            TransactionDirection, TransactionExportFilter, TransactionExportRow,
+            stream_transactions,
+        },
+    },
+    models::AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use wallet::api::CashuWalletApi;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    format: Option<ExportFormat>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    direction: Option<TransactionDirection>,
+    model: Option<String>,
+}
+
+trait ExportRow: Serialize {
+    const HEADER: &'static [&'static str];
+
+    fn csv_fields(&self) -> Vec<String>;
+}
+
+impl ExportRow for TransactionExportRow {
+    const HEADER: &'static [&'static str] = &[
+        "id",
+        "created_at",
+        "direction",
+        "amount_msat",
+        "unit",
+        "mint_url",
+        "token_fingerprint",
+        "keyset_ids",
+        "request_id",
+        "model",
+        "endpoint",
+    ];
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.created_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            format!("{:?}", self.direction),
+            self.amount_msat.to_string(),
+            self.unit.clone(),
+            self.mint_url.clone().unwrap_or_default(),
+            self.token_fingerprint.clone().unwrap_or_default(),
+            self.keyset_ids.join(";"),
+            self.request_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.model.clone().unwrap_or_default(),
+            self.endpoint.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl ExportRow for CreditExportRow {
+    const HEADER: &'static [&'static str] = &[
+        "id",
+        "created_at",
+        "amount_msat",
+        "unit",
+        "mint_url",
+        "redeemed",
+        "request_id",
+        "model",
+        "endpoint",
+    ];
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.created_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.amount_msat.to_string(),
+            self.unit.clone(),
+            self.mint_url.clone().unwrap_or_default(),
+            self.redeemed.to_string(),
+            self.request_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.model.clone().unwrap_or_default(),
+            self.endpoint.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+pub async fn export_transactions<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<ExportParams>,
+) -> Response {
+    let format = params.format.unwrap_or_default();
+    let filter = TransactionExportFilter {
+        from: params.from,
+        to: params.to,
+        direction: params.direction,
+        model: params.model,
+    };
+
+    let (tx, rx) = mpsc::channel::<Result<Vec<u8>, io::Error>>(100);
+    let db = state.db.clone();
+
+    tokio::spawn(async move {
+        let rows = stream_transactions(&db, &filter);
+        write_rows(rows, format, tx).await;
+    });
+
+    export_response(rx, format, "transactions")
+}
+
+pub async fn export_credits<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<ExportParams>,
+) -> Response {
+    let format = params.format.unwrap_or_default();
+    let filter = CreditExportFilter {
+        from: params.from,
+        to: params.to,
+        model: params.model,
+    };
+
+    let (tx, rx) = mpsc::channel::<Result<Vec<u8>, io::Error>>(100);
+    let db = state.db.clone();
+
+    tokio::spawn(async move {
+        let rows = stream_credits(&db, &filter);
+        write_rows(rows, format, tx).await;
+    });
+
+    export_response(rx, format, "credits")
+}
+
+async fn write_rows<R: ExportRow>(
+    mut rows: impl Stream<Item = Result<R, sqlx::Error>> + Unpin,
+    format: ExportFormat,
+    tx: mpsc::Sender<Result<Vec<u8>, io::Error>>,
+) {
+    if let ExportFormat::Csv = format {
+        let header: Vec<String> = R::HEADER.iter().map(|h| h.to_string()).collect();
+        if tx.send(Ok(csv_line(&header))).await.is_err() {
+            return;
+        }
+    }
+
+    while let Some(row) = rows.next().await {
+        let line = match row {
+            Ok(row) => match format {
+                ExportFormat::Csv => csv_line(&row.csv_fields()),
+                ExportFormat::Ndjson => {
+                    let mut line = serde_json::to_vec(&row).unwrap_or_default();
+                    line.push(b'\n');
+                    line
+                }
+            },
+            Err(e) => {
+                tracing::error!("Ledger export failed: {}", e);
+                let _ = tx
+                    .send(Err(io::Error::other(format!("Export failed: {}", e))))
+                    .await;
+                return;
+            }
+        };
+
+        if tx.send(Ok(line)).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn export_response(
+    rx: mpsc::Receiver<Result<Vec<u8>, io::Error>>,
+    format: ExportFormat,
+    name: &str,
+) -> Response {
+    let filename = format!(
+        "{}-{}.{}",
+        name,
+        Utc::now().format("%Y%m%d%H%M%S"),
+        format.extension()
+    );
+    let body = Body::from_stream(ReceiverStream::new(rx));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(body)
+        .unwrap()
+}
+
+fn csv_line(fields: &[String]) -> Vec<u8> {
+    let mut line = fields
+        .iter()
+        .map(|field| csv_escape(field))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line.into_bytes()
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,11 +1,20 @@
 use crate::{
+    crypto::{SecretCipher, fingerprint_secret},
     db::{
-        Pool,
+        MSAT_PER_SAT, Pool,
         credit::add_credit,
+        request::{NewRequest, complete_request, create_request, set_request_cost},
         transaction::{TransactionDirection, add_transaction},
     },
     handlers::get_server_config,
+    metrics::{self, observe_wallet_call},
+    mint_policy::PolicyViolation,
+    mints::{MintSelectionError, mint_balances, select_mint},
     models::*,
+    provider::{ChangeEventFilter, change_from_stream},
+    telemetry::inject_trace_context,
+    token::summarize_token,
+    usage::{Usage, UsageCollector},
 };
 use axum::{
     Json,
@@ -16,18 +25,26 @@ use axum::{
 };
 use futures_util::StreamExt;
 use reqwest::Client;
+use secrecy::ExposeSecret;
 use serde_json::json;
 use std::io;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, Span, field};
+use uuid::Uuid;
 use wallet::{
-    api::{CashuWalletApi, CashuWalletClient},
+    api::CashuWalletApi,
     models::{ChatCompletionRequest, EmbeddingRequest, ImageGenerationRequest},
 };
 
-pub async fn forward_chat_completions(
-    State(state): State<Arc<AppState>>,
+/// Response headers carrying change, which the gateway redeems itself and
+/// never relays.
+const CHANGE_HEADERS: [&str; 3] = ["x-change-sats", "x-change-token", "x-change-amount"];
+
+pub async fn forward_chat_completions<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
     headers: HeaderMap,
     Json(request): Json<ChatCompletionRequest>,
 ) -> Response {
@@ -38,8 +55,7 @@ pub async fn forward_chat_completions(
 
     let response = forward_request_with_payment_with_body(
         headers,
-        &state.db,
-        &state.wallet,
+        &state,
         endpoint_fn,
         Some(request),
         is_streaming,
@@ -49,80 +65,70 @@ pub async fn forward_chat_completions(
     response.into_response()
 }
 
-pub async fn forward_list_models(
-    State(state): State<Arc<AppState>>,
+pub async fn forward_list_models<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
     headers: HeaderMap,
 ) -> Response {
     let endpoint_fn = |base_endpoint: &str| -> String { format!("{}/v1/models", base_endpoint) };
 
-    let response = forward_request(headers, &state.db, endpoint_fn).await;
+    let response = forward_request(headers, &state.db, &state.cipher, endpoint_fn).await;
 
     response.into_response()
 }
 
-pub async fn forward_embeddings(
-    State(state): State<Arc<AppState>>,
+pub async fn forward_embeddings<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
     headers: HeaderMap,
     Json(request): Json<EmbeddingRequest>,
 ) -> Response {
     let endpoint_fn =
         |base_endpoint: &str| -> String { format!("{}/v1/embeddings", base_endpoint) };
 
-    let response = forward_request_with_payment_with_body(
-        headers,
-        &state.db,
-        &state.wallet,
-        endpoint_fn,
-        Some(request),
-        false,
-    )
-    .await;
+    let response =
+        forward_request_with_payment_with_body(headers, &state, endpoint_fn, Some(request), false)
+            .await;
 
     response.into_response()
 }
 
-pub async fn forward_image_generations(
-    State(state): State<Arc<AppState>>,
+pub async fn forward_image_generations<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
     headers: HeaderMap,
     Json(request): Json<ImageGenerationRequest>,
 ) -> Response {
     let endpoint_fn =
         |base_endpoint: &str| -> String { format!("{}/v1/images/generations", base_endpoint) };
 
-    let response = forward_request_with_payment_with_body(
-        headers,
-        &state.db,
-        &state.wallet,
-        endpoint_fn,
-        Some(request),
-        false,
-    )
-    .await;
+    let response =
+        forward_request_with_payment_with_body(headers, &state, endpoint_fn, Some(request), false)
+            .await;
 
     response.into_response()
 }
 
-pub async fn get_specific_model(
+pub async fn get_specific_model<W: CashuWalletApi>(
     Path(model_id): Path<String>,
-    State(state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState<W>>>,
     headers: HeaderMap,
 ) -> Response {
     let model_endpoint =
         move |endpoint: &str| -> String { format!("{}/v1/models/{}", endpoint, model_id) };
 
-    let response = forward_request(headers, &state.db, model_endpoint).await;
+    let response = forward_request(headers, &state.db, &state.cipher, model_endpoint).await;
     response.into_response()
 }
 
-pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
+#[tracing::instrument(name = "paid_request", skip_all, fields(streaming = is_streaming))]
+pub async fn forward_request_with_payment_with_body<T: serde::Serialize, W: CashuWalletApi>(
     original_headers: HeaderMap,
-    db: &Pool,
-    wallet: &CashuWalletClient,
+    state: &Arc<AppState<W>>,
     endpoint_fn: impl Fn(&str) -> String,
     body: Option<T>,
     is_streaming: bool,
 ) -> Response<Body> {
-    let server_config = if let Some(config) = get_server_config(&db).await {
+    let (db, cipher, wallet) = (&state.db, &state.cipher, &state.wallet);
+    let started = Instant::now();
+    let server_config = if let Some(config) = get_server_config(db, cipher).await {
         config
     } else {
         return (
@@ -138,6 +144,30 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
         ).into_response();
     };
 
+    let endpoint_path = endpoint_fn("");
+    let model = body
+        .as_ref()
+        .and_then(|b| serde_json::to_value(b).ok())
+        .and_then(|v| v.get("model")?.as_str().map(String::from));
+
+    let request_id = match create_request(
+        db,
+        &NewRequest {
+            endpoint: endpoint_path.clone(),
+            model: model.clone(),
+            provider: server_config.endpoint.clone(),
+            client_key: client_key(&original_headers),
+        },
+    )
+    .await
+    {
+        Ok(id) => Some(id),
+        Err(e) => {
+            tracing::error!("Failed to record request: {}", e);
+            None
+        }
+    };
+
     let mut client_builder = Client::builder();
 
     if is_streaming {
@@ -151,6 +181,7 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
     let client = client_builder.build().unwrap();
     let endpoint_url = endpoint_fn(&server_config.endpoint);
 
+    let method = if body.is_some() { "POST" } else { "GET" };
     let mut req_builder = if body.is_some() {
         client.post(endpoint_url)
     } else {
@@ -161,17 +192,48 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
         req_builder = req_builder.json(&body_data);
     }
 
+    // Bearer tokens can be taken by anything between us and the provider.
+    let lock = server_config.p2pk_pubkey.as_deref();
+    if lock.is_none() && !state.payments.allow_unlocked {
+        metrics::record_payment_failure("unlocked_payment");
+        let message = "The provider advertises no P2PK key and unlocked payments are not allowed";
+        finish_request(db, request_id, None, None, started, Some(message)).await;
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": {
+                    "message": message,
+                    "type": "payment_error",
+                    "code": "unlocked_payment_refused"
+                }
+            })),
+        )
+            .into_response();
+    }
+
     let sats = 30;
 
-    let token_result = wallet.send(sats, None, None, None, None).await;
+    let token_result = match paying_mint(state, &server_config.accepted_mints, sats).await {
+        Ok(mint) => {
+            observe_wallet_call(
+                "send",
+                wallet.send(sats, None, lock, None, mint.as_deref(), None),
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
     let token = match token_result {
         Ok(token) => token.token,
         Err(e) => {
+            metrics::record_payment_failure("token_creation");
+            let message = format!("Failed to generate payment token: {}", e);
+            finish_request(db, request_id, None, None, started, Some(&message)).await;
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
                     "error": {
-                        "message": format!("Failed to generate payment token: {}", e),
+                        "message": message,
                         "type": "payment_error",
                     }
                 })),
@@ -182,7 +244,7 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
 
     req_builder = req_builder.header(
         header::AUTHORIZATION,
-        format!("Bearer {}", server_config.api_key),
+        format!("Bearer {}", server_config.api_key.expose_secret()),
     );
     req_builder = req_builder.header(header::CONTENT_TYPE, "application/json");
     req_builder = req_builder.header("X-PAYMENT-SATS", &token);
@@ -191,10 +253,45 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
         req_builder = req_builder.header(header::ACCEPT, accept);
     }
 
-    match req_builder.send().await {
+    let span = upstream_span(method, &endpoint_path);
+    req_builder = req_builder.headers(trace_headers(&span));
+
+    let upstream_started = Instant::now();
+    let upstream = req_builder.send().instrument(span.clone()).await;
+    if let Ok(resp) = &upstream {
+        span.record("http.response.status_code", resp.status().as_u16());
+    }
+
+    // The token was handed to the provider, so it is spent whatever the
+    // outcome. The response is relayed even when that can't be booked.
+    if let Err(e) = add_transaction(
+        db,
+        cipher,
+        &token,
+        sats * MSAT_PER_SAT,
+        TransactionDirection::Outgoing,
+        request_id,
+        None,
+    )
+    .await
+    {
+        metrics::record_payment_failure("payment_booking");
+        tracing::error!("Failed to book a payment of {} sat: {}", sats, e);
+    }
+    metrics::record_sats_spent(sats);
+    let mut cost_msat = sats * MSAT_PER_SAT;
+
+    match upstream {
         Ok(resp) => {
             let status = resp.status();
             let headers = resp.headers().clone();
+            metrics::record_upstream_response(
+                &endpoint_path,
+                model.as_deref(),
+                &server_config.endpoint,
+                status.as_u16(),
+                upstream_started,
+            );
 
             let mut response = Response::builder().status(status);
 
@@ -203,28 +300,16 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
             }
 
             if let Some(change_sats) = headers.get("X-CHANGE-SATS") {
-                let in_token = change_sats.to_str().unwrap();
-                if let Ok(res) = wallet
-                    .receive(Some(change_sats.to_str().unwrap()), None, None)
-                    .await
-                {
-                    add_transaction(
-                        &db,
-                        &token,
-                        &sats.to_string(),
-                        TransactionDirection::Outgoing,
-                    )
-                    .await
-                    .unwrap();
-
-                    add_transaction(
-                        &db,
-                        in_token,
-                        &(res.balance - res.initial_balance).to_string(),
-                        TransactionDirection::Incoming,
-                    )
-                    .await
-                    .unwrap();
+                match change_sats.to_str() {
+                    Ok(token) => {
+                        if let Some(change_msat) = receive_change(state, token, request_id).await {
+                            cost_msat -= change_msat;
+                        }
+                    }
+                    Err(_) => {
+                        metrics::record_payment_failure("invalid_change");
+                        tracing::warn!("Ignoring change token with invalid X-CHANGE-SATS");
+                    }
                 }
             }
 
@@ -232,45 +317,130 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
                 headers.get("X-CHANGE-TOKEN"),
                 headers.get("X-CHANGE-AMOUNT"),
             ) {
-                let _ = add_credit(
-                    &db,
-                    change_token.to_str().unwrap(),
-                    change_amount.to_str().unwrap(),
-                )
-                .await
-                .unwrap();
+                let change_amount = change_amount
+                    .to_str()
+                    .ok()
+                    .and_then(|amount| amount.trim().parse::<i64>().ok());
+                match (change_token.to_str(), change_amount) {
+                    (Ok(change_token), Some(change_sats)) => {
+                        let violation = state
+                            .mint_policy
+                            .check_token(db, wallet, change_token)
+                            .await
+                            .err()
+                            .filter(|violation| {
+                                // Credits aren't held in the wallet.
+                                !matches!(violation, PolicyViolation::OverLimit { .. })
+                            });
+                        match violation {
+                            Some(violation @ PolicyViolation::UnsupportedUnit(_)) => {
+                                metrics::record_payment_failure("invalid_change");
+                                tracing::warn!("Ignoring change token: {}", violation);
+                            }
+                            violation => {
+                                let reason = violation.map(|violation| violation.to_string());
+                                match add_credit(
+                                    db,
+                                    cipher,
+                                    change_token,
+                                    change_sats * MSAT_PER_SAT,
+                                    request_id,
+                                    reason.as_deref(),
+                                )
+                                .await
+                                {
+                                    Ok(_) if reason.is_none() => {
+                                        cost_msat -= change_sats * MSAT_PER_SAT;
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        metrics::record_payment_failure("change_booking");
+                                        tracing::error!("Failed to keep change as a credit: {}", e);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        metrics::record_payment_failure("invalid_change");
+                        tracing::warn!("Ignoring change token with invalid X-CHANGE-* headers");
+                    }
+                }
             }
 
+            record_request_cost(db, request_id, cost_msat).await;
+
             let response_headers = response.headers_mut().unwrap();
             for (name, value) in headers.iter() {
-                if name != "connection" && name != "transfer-encoding" {
+                if name != "connection"
+                    && name != "transfer-encoding"
+                    && !CHANGE_HEADERS.contains(&name.as_str())
+                {
                     response_headers.insert(name, value.clone());
                 }
             }
 
             let (tx, rx) = mpsc::channel::<Result<Vec<u8>, io::Error>>(100);
             let mut stream = resp.bytes_stream();
-
-            tokio::spawn(async move {
-                while let Some(item) = stream.next().await {
-                    match item {
-                        Ok(chunk) => {
-                            if tx.send(Ok(chunk.to_vec())).await.is_err() {
+            let state = state.clone();
+
+            tokio::spawn(
+                async move {
+                    let mut usage = UsageCollector::default();
+                    let mut change_filter = is_streaming.then(ChangeEventFilter::default);
+                    let mut error = None;
+                    while let Some(item) = stream.next().await {
+                        match item {
+                            Ok(chunk) => {
+                                usage.push(&chunk);
+                                let relayed = match change_filter.as_mut() {
+                                    Some(filter) => filter.push(&chunk),
+                                    None => chunk.to_vec(),
+                                };
+                                if !relayed.is_empty() && tx.send(Ok(relayed)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                let message = format!("Error reading from upstream: {}", e);
+                                let _ = tx.send(Err(io::Error::other(message.clone()))).await;
+                                error = Some(message);
                                 break;
                             }
                         }
-                        Err(e) => {
-                            let _ = tx
-                                .send(Err(io::Error::new(
-                                    io::ErrorKind::Other,
-                                    format!("Error reading from upstream: {}", e),
-                                )))
-                                .await;
-                            break;
+                    }
+                    if error.is_none()
+                        && let Some(filter) = change_filter
+                    {
+                        let rest = filter.finish();
+                        if !rest.is_empty() {
+                            let _ = tx.send(Ok(rest)).await;
                         }
                     }
+
+                    // A provider running in provider mode sends the change of a
+                    // streamed response after its last event. It is redeemed
+                    // while the response is still open, so the client never
+                    // gets to see it.
+                    let stream_change = usage.body().and_then(change_from_stream);
+                    if let Some(token) = stream_change
+                        && let Some(change_msat) = receive_change(&state, &token, request_id).await
+                    {
+                        record_request_cost(&state.db, request_id, cost_msat - change_msat).await;
+                    }
+
+                    finish_request(
+                        &state.db,
+                        request_id,
+                        Some(status.as_u16() as i32),
+                        usage.finish(is_streaming).as_ref(),
+                        started,
+                        error.as_deref(),
+                    )
+                    .await;
                 }
-            });
+                .in_current_span(),
+            );
 
             let stream = ReceiverStream::new(rx);
 
@@ -283,18 +453,23 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
 
             let body = Body::from_stream(mapped_stream);
 
-            return response.body(body).unwrap_or_else(|e| {
+            response.body(body).unwrap_or_else(|e| {
                 eprintln!("Error creating streaming response: {}", e);
                 Response::builder()
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
                     .body(Body::from("Error creating streaming response"))
                     .unwrap()
-            });
+            })
         }
         Err(error) => {
+            metrics::record_upstream_error(&server_config.endpoint, "connection");
+            let message = format!("Error forwarding request: {}", error);
+            record_request_cost(db, request_id, cost_msat).await;
+            finish_request(db, request_id, None, None, started, Some(&message)).await;
+
             let error_json = Json(json!({
                 "error": {
-                    "message": format!("Error forwarding request: {}", error),
+                    "message": message,
                     "type": "gateway_error"
                 }
             }));
@@ -304,12 +479,166 @@ pub async fn forward_request_with_payment_with_body<T: serde::Serialize>(
     }
 }
 
+/// The mint to pay `sats` from, swapping into an accepted one when none of
+/// them can. `None` leaves the choice to the wallet, for wallets that don't
+/// report their balance per mint.
+async fn paying_mint<W: CashuWalletApi>(
+    state: &AppState<W>,
+    accepted: &[String],
+    sats: i64,
+) -> anyhow::Result<Option<String>> {
+    let balance = observe_wallet_call("balance", state.wallet.balance()).await?;
+    let balances = mint_balances(&balance);
+    if balances.is_empty() {
+        return Ok(accepted.first().cloned());
+    }
+
+    match select_mint(&balances, accepted, sats) {
+        Ok(mint) => Ok(Some(mint)),
+        Err(MintSelectionError::NoAcceptedMint(_)) if state.rebalancer.settings().auto_swap => {
+            let mint = state
+                .rebalancer
+                .cover(&state.wallet, &state.mint_policy, &state.db, accepted, sats)
+                .await?;
+            Ok(Some(mint))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Redeems change handed back by the provider into our wallet and books it.
+/// Returns the amount received.
+async fn receive_change<W: CashuWalletApi>(
+    state: &AppState<W>,
+    token: &str,
+    request_id: Option<Uuid>,
+) -> Option<i64> {
+    let dleq = match state
+        .mint_policy
+        .check_token(&state.db, &state.wallet, token)
+        .await
+    {
+        Ok(checked) => checked.dleq,
+        // Not a token we could ever redeem, there is nothing to keep.
+        Err(PolicyViolation::UnknownMint | PolicyViolation::UnsupportedUnit(_)) => {
+            metrics::record_payment_failure("change_receive");
+            return None;
+        }
+        Err(violation) => {
+            quarantine_change(state, token, request_id, &violation).await;
+            return None;
+        }
+    };
+
+    let received = observe_wallet_call("receive", state.wallet.receive(Some(token), None, None))
+        .await
+        .inspect_err(|_| metrics::record_payment_failure("change_receive"))
+        .ok()?;
+
+    metrics::record_sats_received(received.amount);
+    let change_msat = received.amount * MSAT_PER_SAT;
+    // The change is in the wallet already, so it still counts against the
+    // cost of the request when it can't be booked.
+    if let Err(e) = add_transaction(
+        &state.db,
+        &state.cipher,
+        token,
+        change_msat,
+        TransactionDirection::Incoming,
+        request_id,
+        dleq,
+    )
+    .await
+    {
+        metrics::record_payment_failure("change_booking");
+        tracing::error!("Failed to book {} sat of change: {}", received.amount, e);
+    }
+    Some(change_msat)
+}
+
+/// Keeps change we won't redeem as a credit, out of the wallet's balance.
+async fn quarantine_change<W: CashuWalletApi>(
+    state: &AppState<W>,
+    token: &str,
+    request_id: Option<Uuid>,
+    violation: &PolicyViolation,
+) {
+    metrics::record_payment_failure("change_quarantined");
+    tracing::warn!("Quarantining change: {}", violation);
+
+    let amount_msat = summarize_token(token).amount.unwrap_or(0) as i64 * MSAT_PER_SAT;
+    if let Err(e) = add_credit(
+        &state.db,
+        &state.cipher,
+        token,
+        amount_msat,
+        request_id,
+        Some(&violation.to_string()),
+    )
+    .await
+    {
+        tracing::error!("Failed to quarantine change: {}", e);
+    }
+}
+
+pub(crate) fn upstream_span(method: &str, path: &str) -> Span {
+    tracing::info_span!(
+        "upstream_request",
+        otel.kind = "client",
+        http.request.method = method,
+        url.path = path,
+        http.response.status_code = field::Empty,
+    )
+}
+
+pub(crate) fn trace_headers(span: &Span) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    inject_trace_context(span, &mut headers);
+    headers
+}
+
+pub(crate) fn client_key(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let key = value.strip_prefix("Bearer ").unwrap_or(value).trim();
+    if key.is_empty() {
+        return None;
+    }
+    Some(fingerprint_secret(key))
+}
+
+pub(crate) async fn record_request_cost(db: &Pool, request_id: Option<Uuid>, cost_msat: i64) {
+    let Some(id) = request_id else {
+        return;
+    };
+    if let Err(e) = set_request_cost(db, id, cost_msat).await {
+        tracing::error!("Failed to record cost of request {}: {}", id, e);
+    }
+}
+
+pub(crate) async fn finish_request(
+    db: &Pool,
+    request_id: Option<Uuid>,
+    http_status: Option<i32>,
+    usage: Option<&Usage>,
+    started: Instant,
+    error: Option<&str>,
+) {
+    let Some(id) = request_id else {
+        return;
+    };
+    let latency_ms = started.elapsed().as_millis() as i64;
+    if let Err(e) = complete_request(db, id, http_status, usage, latency_ms, error).await {
+        tracing::error!("Failed to complete request {}: {}", id, e);
+    }
+}
+
 pub async fn forward_request(
     original_headers: HeaderMap,
     db: &Pool,
+    cipher: &SecretCipher,
     endpoint_fn: impl Fn(&str) -> String,
 ) -> Response<Body> {
-    let server_config = if let Some(config) = get_server_config(&db).await {
+    let server_config = if let Some(config) = get_server_config(db, cipher).await {
         config
     } else {
         return (
@@ -338,10 +667,26 @@ pub async fn forward_request(
         req_builder = req_builder.header(header::ACCEPT, accept);
     }
 
-    match req_builder.send().await {
+    let endpoint_path = endpoint_fn("");
+    let span = upstream_span("GET", &endpoint_path);
+    req_builder = req_builder.headers(trace_headers(&span));
+
+    let upstream_started = Instant::now();
+    let upstream = req_builder.send().instrument(span.clone()).await;
+    if let Ok(resp) = &upstream {
+        span.record("http.response.status_code", resp.status().as_u16());
+    }
+    match upstream {
         Ok(resp) => {
             let status = resp.status();
             let headers = resp.headers().clone();
+            metrics::record_upstream_response(
+                &endpoint_path,
+                None,
+                &server_config.endpoint,
+                status.as_u16(),
+                upstream_started,
+            );
 
             let mut response = Response::builder().status(status);
 
@@ -365,10 +710,10 @@ pub async fn forward_request(
                         }
                         Err(e) => {
                             let _ = tx
-                                .send(Err(io::Error::new(
-                                    io::ErrorKind::Other,
-                                    format!("Error reading from upstream: {}", e),
-                                )))
+                                .send(Err(io::Error::other(format!(
+                                    "Error reading from upstream: {}",
+                                    e
+                                ))))
                                 .await;
                             break;
                         }
@@ -387,15 +732,16 @@ pub async fn forward_request(
 
             let body = Body::from_stream(mapped_stream);
 
-            return response.body(body).unwrap_or_else(|e| {
+            response.body(body).unwrap_or_else(|e| {
                 eprintln!("Error creating streaming response: {}", e);
                 Response::builder()
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
                     .body(Body::from("Error creating streaming response"))
                     .unwrap()
-            });
+            })
         }
         Err(error) => {
+            metrics::record_upstream_error(&server_config.endpoint, "connection");
             let error_json = Json(json!({
                 "error": {
                     "message": format!("Error forwarding request: {}", error),
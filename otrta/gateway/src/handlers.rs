@@ -1,35 +1,101 @@
 use crate::{
+    connection::SubSatRounding,
+    crypto::{SecretCipher, mask_secret},
     db::{
-        Pool,
-        credit::{CreditListResponse, get_credits},
+        MSAT_PER_SAT, Pool,
+        analytics::{
+            Bucket, GroupBy, SpendPoint, SpendSummary, get_spend_series, get_spend_summary,
+        },
+        credit::{
+            Credit, CreditFilter, CreditListResponse, get_credit, get_credit_token, get_credits,
+            mark_credit_redeemed,
+        },
+        listing::{Cursor, ListOptions, SortField, SortOrder},
+        mint::{MintRecord, MintStatus, delete_mint, get_mint, get_mints, upsert_mint_rule},
+        pricing::{ModelPriceRecord, delete_model_price, upsert_model_price},
+        request::{RequestFilter, RequestListResponse, get_requests},
         server_config::{ServerConfigRecord, create_config, get_default_config, update_config},
-        transaction::{TransactionListResponse, get_transactions},
+        topup::{Topup, get_topup},
+        transaction::{
+            Transaction, TransactionDirection, TransactionFilter, TransactionListResponse,
+            add_transaction, get_transaction, get_transactions,
+        },
+        transfer::Transfer,
+        withdrawal::{Withdrawal, get_withdrawal},
     },
+    error::AppError,
+    metrics::{observe_wallet_call, record_sats_received},
+    mint_policy::MintTrust,
+    mints::{mint_balances, normalize_mint_url},
     models::*,
+    topup::start_topup,
+    transfer::{self, SentTransfer},
+    withdraw,
 };
 use axum::{
     Json,
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::Response,
 };
-use serde::Deserialize;
+use cdk::nuts::PublicKey;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
 use serde_json::{self, json};
-use std::sync::Arc;
-use wallet::{api::CashuWalletApi, models::ServerConfig};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+use uuid::Uuid;
+use wallet::{
+    api::{CashuWalletApi, RestoreResponse},
+    models::{ModelPricing, ServerConfig},
+};
 
-pub async fn list_openai_models(
-    State(state): State<Arc<AppState>>,
+pub async fn list_openai_models<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
     headers: HeaderMap,
 ) -> Response {
     crate::forward::forward_list_models(State(state), headers).await
 }
 
-pub async fn redeem_token(
-    State(state): State<Arc<AppState>>,
+pub async fn redeem_token<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
     Json(payload): Json<Token>,
 ) -> Json<TokenRedeemResponse> {
-    if let Ok(response) = state.wallet.receive(Some(&payload.token), None, None).await {
+    let checked = match state
+        .mint_policy
+        .check_token(&state.db, &state.wallet, &payload.token)
+        .await
+    {
+        Ok(checked) => checked,
+        Err(violation) => {
+            return Json(TokenRedeemResponse {
+                amount: None,
+                success: false,
+                message: Some(violation.to_string()),
+            });
+        }
+    };
+
+    if let Ok(response) = observe_wallet_call(
+        "receive",
+        state.wallet.receive(Some(&payload.token), None, None),
+    )
+    .await
+    {
+        record_sats_received(response.amount);
+        if let Err(e) = add_transaction(
+            &state.db,
+            &state.cipher,
+            &payload.token,
+            response.amount * MSAT_PER_SAT,
+            TransactionDirection::Incoming,
+            None,
+            checked.dleq,
+        )
+        .await
+        {
+            tracing::error!("Failed to record a redeemed token: {}", e);
+        }
         return Json(TokenRedeemResponse {
             amount: Some(response.balance.to_string()),
             success: true,
@@ -44,49 +110,273 @@ pub async fn redeem_token(
     })
 }
 
-pub async fn get_balance(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
-    Json(json!({"balance": state.wallet.balance().await.unwrap().balance.to_string()}))
+pub async fn get_balance<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+) -> Json<serde_json::Value> {
+    let balance = observe_wallet_call("balance", state.wallet.balance())
+        .await
+        .unwrap();
+    Json(json!({
+        "balance": balance.balance.to_string(),
+        "mints": mint_balances(&balance),
+    }))
+}
+
+/// Recovers proofs derived from the wallet seed (NUT-09/NUT-13).
+pub async fn restore_wallet<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+) -> Result<Json<RestoreResponse>, AppError> {
+    observe_wallet_call("restore", state.wallet.restore(0))
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Wallet restore failed: {}", e);
+            AppError::InternalServerError
+        })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopupRequest {
+    pub amount: i64,
+    /// Mint to mint the ecash at, the wallet's default mint when unset.
+    pub mint: Option<String>,
+}
+
+/// Creates a mint quote and returns its bolt11 invoice. The ecash is minted
+/// in the background once the invoice is paid.
+pub async fn create_topup<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Json(payload): Json<TopupRequest>,
+) -> Result<Json<Topup>, AppError> {
+    if payload.amount <= 0 || payload.amount > state.topup.max_sats {
+        return Err(AppError::ValidationError(format!(
+            "amount must be between 1 and {} sat",
+            state.topup.max_sats
+        )));
+    }
+    let mint = match payload.mint.as_deref() {
+        Some(mint) => {
+            let mint = normalize_mint_url(mint)
+                .map_err(|_| AppError::ValidationError("Invalid mint URL".to_string()))?;
+            let trust = state
+                .mint_policy
+                .evaluate(&state.db, &mint)
+                .await
+                .map_err(|_| AppError::InternalServerError)?;
+            if !trust.trusted {
+                return Err(AppError::ValidationError(format!(
+                    "mint {} is not trusted",
+                    mint
+                )));
+            }
+            Some(mint)
+        }
+        None => None,
+    };
+
+    start_topup(state, payload.amount, mint.as_deref())
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to create top-up invoice: {}", e);
+            AppError::InternalServerError
+        })
+}
+
+pub async fn get_topup_status<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Topup>, AppError> {
+    match get_topup(&state.db, id).await {
+        Ok(Some(topup)) => Ok(Json(topup)),
+        Ok(None) => Err(AppError::NotFound),
+        Err(_) => Err(AppError::InternalServerError),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WithdrawRequest {
+    pub bolt11: Option<String>,
+    /// Lightning Address or LNURL-pay string to pay instead of an invoice.
+    pub address: Option<String>,
+    /// Sats to send to `address`.
+    pub amount: Option<i64>,
+    /// Comment passed to `address`, when it accepts one.
+    pub comment: Option<String>,
+    /// Mint to pay from, the one the wallet picks when unset.
+    pub mint: Option<String>,
+}
+
+/// Quotes paying a Lightning invoice, Lightning Address or LNURL out of the
+/// wallet. Nothing is paid until the withdrawal is confirmed.
+pub async fn quote_withdrawal<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Json(payload): Json<WithdrawRequest>,
+) -> Result<Json<Withdrawal>, AppError> {
+    let mint = payload
+        .mint
+        .as_deref()
+        .map(normalize_mint_url)
+        .transpose()
+        .map_err(|_| AppError::ValidationError("Invalid mint URL".to_string()))?;
+
+    let withdrawal = match (&payload.bolt11, &payload.address) {
+        (Some(bolt11), None) => {
+            withdraw::quote_withdrawal(&state, bolt11.trim(), mint.as_deref()).await?
+        }
+        (None, Some(address)) => {
+            let Some(amount) = payload.amount.filter(|amount| *amount > 0) else {
+                return Err(AppError::ValidationError(
+                    "A positive amount is required to pay an address".to_string(),
+                ));
+            };
+            withdraw::quote_lnurl_withdrawal(
+                &state,
+                address,
+                amount,
+                payload.comment.as_deref(),
+                mint.as_deref(),
+            )
+            .await?
+        }
+        _ => {
+            return Err(AppError::ValidationError(
+                "Either bolt11 or address is required".to_string(),
+            ));
+        }
+    };
+    Ok(Json(withdrawal))
+}
+
+pub async fn confirm_withdrawal<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Withdrawal>, AppError> {
+    Ok(Json(withdraw::confirm_withdrawal(state, id).await?))
+}
+
+pub async fn get_withdrawal_status<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Withdrawal>, AppError> {
+    match get_withdrawal(&state.db, id).await {
+        Ok(Some(withdrawal)) => Ok(Json(withdrawal)),
+        Ok(None) => Err(AppError::NotFound),
+        Err(_) => Err(AppError::InternalServerError),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendRequest {
+    pub amount: i64,
+    /// Mint to send from, the one the wallet picks when unset.
+    pub mint: Option<String>,
+    /// Hex public key to lock the token to (NUT-11).
+    pub lock: Option<String>,
+    /// Nostr recipient to DM the token to.
+    pub npub: Option<String>,
+}
+
+/// Sends ecash out of the wallet as a token. The token is only in this
+/// response; the transfer can be followed until it is claimed.
+pub async fn send_ecash<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Json(payload): Json<SendRequest>,
+) -> Result<Json<SentTransfer>, AppError> {
+    if payload.amount <= 0 {
+        return Err(AppError::ValidationError(
+            "Amount must be positive".to_string(),
+        ));
+    }
+    let mint = payload
+        .mint
+        .as_deref()
+        .map(normalize_mint_url)
+        .transpose()
+        .map_err(|_| AppError::ValidationError("Invalid mint URL".to_string()))?;
+
+    Ok(Json(
+        transfer::send_ecash(
+            &state,
+            payload.amount,
+            mint.as_deref(),
+            payload.lock.as_deref().map(str::trim),
+            payload.npub.as_deref().map(str::trim),
+        )
+        .await?,
+    ))
+}
+
+pub async fn get_transfer_status<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Transfer>, AppError> {
+    Ok(Json(transfer::refresh_transfer(&state, id).await?))
+}
+
+pub async fn reclaim_transfer<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Transfer>, AppError> {
+    Ok(Json(transfer::reclaim_transfer(&state, id).await?))
 }
 
-pub async fn update_server_config(
-    State(state): State<Arc<AppState>>,
-    Json(config): Json<ServerConfig>,
+pub async fn update_server_config<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Json(mut config): Json<ServerConfig>,
 ) -> Result<Json<ServerConfig>, StatusCode> {
-    let db_config = get_server_config(&state.db.clone()).await;
+    config.accepted_mints = config
+        .accepted_mints
+        .iter()
+        .map(|mint| normalize_mint_url(mint))
+        .collect::<Result<_, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    config.p2pk_pubkey = config
+        .p2pk_pubkey
+        .as_deref()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| PublicKey::from_str(key.trim_start_matches("P2PK:")).map(|key| key.to_hex()))
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let db_config = get_server_config(&state.db, &state.cipher).await;
     if let Some(c) = db_config {
-        update_config(&state.db.clone(), c.id, &config)
+        // Clients echo back the masked key they were given; keep the stored one.
+        if config.api_key == mask_secret(c.api_key.expose_secret()) {
+            config.api_key = c.api_key.expose_secret().to_string();
+        }
+        update_config(&state.db, &state.cipher, c.id, &config)
             .await
             .unwrap();
     } else {
-        create_config(&state.db.clone(), &config).await.unwrap();
+        create_config(&state.db, &state.cipher, &config)
+            .await
+            .unwrap();
     }
 
-    let config = get_server_config(&state.db.clone()).await.unwrap();
-    Ok(Json(ServerConfig {
-        endpoint: config.endpoint,
-        api_key: config.api_key,
-    }))
+    let config = get_server_config(&state.db, &state.cipher).await.unwrap();
+    Ok(Json(config.to_model()))
 }
 
-pub async fn get_current_server_config(
-    State(state): State<Arc<AppState>>,
+pub async fn get_current_server_config<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
 ) -> Result<Json<ServerConfig>, StatusCode> {
-    let config = get_server_config(&state.db.clone()).await;
+    let config = get_server_config(&state.db, &state.cipher).await;
     if let Some(c) = config {
-        return Ok(Json(ServerConfig {
-            endpoint: c.endpoint,
-            api_key: c.api_key,
-        }));
+        return Ok(Json(c.to_model()));
     }
 
-    return Ok(Json(ServerConfig {
+    Ok(Json(ServerConfig {
         endpoint: "".to_string(),
         api_key: "".to_string(),
-    }));
+        accepted_mints: Vec::new(),
+        p2pk_pubkey: None,
+    }))
 }
 
-pub async fn get_server_config(db: &Pool) -> Option<ServerConfigRecord> {
-    if let Ok(c) = get_default_config(db).await {
+pub async fn get_server_config(db: &Pool, cipher: &SecretCipher) -> Option<ServerConfigRecord> {
+    if let Ok(c) = get_default_config(db, cipher).await {
         return c;
     }
 
@@ -94,28 +384,452 @@ pub async fn get_server_config(db: &Pool) -> Option<ServerConfigRecord> {
 }
 
 #[derive(Deserialize)]
-pub struct PaginationParams {
+pub struct LedgerListParams {
     page: Option<i64>,
     #[serde(rename = "pageSize")]
     page_size: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<SortField>,
+    order: Option<SortOrder>,
+    direction: Option<TransactionDirection>,
+    redeemed: Option<bool>,
+    quarantined: Option<bool>,
+    min_amount_msat: Option<i64>,
+    max_amount_msat: Option<i64>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    q: Option<String>,
 }
 
-pub async fn get_all_credits(
-    State(state): State<Arc<AppState>>,
-    params: Query<PaginationParams>,
-) -> Result<Json<CreditListResponse>, StatusCode> {
-    match get_credits(&state.db, params.page, params.page_size).await {
-        Ok(response) => Ok(Json(response)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+impl LedgerListParams {
+    fn list_options(&self) -> Result<ListOptions, AppError> {
+        let cursor = match &self.cursor {
+            Some(encoded) => Some(
+                Cursor::decode(encoded)
+                    .ok_or_else(|| AppError::ValidationError("invalid cursor".to_string()))?,
+            ),
+            None => None,
+        };
+
+        Ok(ListOptions {
+            sort: self.sort.unwrap_or_default(),
+            order: self.order.unwrap_or_default(),
+            page: self.page,
+            page_size: self.page_size,
+            cursor,
+        })
+    }
+}
+
+pub async fn get_all_credits<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<LedgerListParams>,
+) -> Result<Json<CreditListResponse>, AppError> {
+    let options = params.list_options()?;
+    let filter = CreditFilter {
+        redeemed: params.redeemed,
+        quarantined: params.quarantined,
+        min_amount_msat: params.min_amount_msat,
+        max_amount_msat: params.max_amount_msat,
+        from: params.from,
+        to: params.to,
+        search: params.q,
+    };
+
+    get_credits(&state.db, &filter, &options)
+        .await
+        .map(Json)
+        .map_err(|_| AppError::InternalServerError)
+}
+
+pub async fn get_credit_by_id<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Credit>, AppError> {
+    match get_credit(&state.db, id).await {
+        Ok(Some(credit)) => Ok(Json(credit)),
+        Ok(None) => Err(AppError::NotFound),
+        Err(_) => Err(AppError::InternalServerError),
+    }
+}
+
+/// Redeems a credit into the wallet, e.g. change quarantined from a mint
+/// that has been trusted since.
+pub async fn redeem_credit<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Credit>, AppError> {
+    let credit = get_credit(&state.db, id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::NotFound)?;
+    if credit.redeemed {
+        return Err(AppError::ValidationError(
+            "credit was redeemed already".to_string(),
+        ));
+    }
+    let token = get_credit_token(&state.db, &state.cipher, id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::NotFound)?;
+    let token = token.expose_secret();
+    let checked = state
+        .mint_policy
+        .check_token(&state.db, &state.wallet, token)
+        .await
+        .map_err(|violation| AppError::ValidationError(violation.to_string()))?;
+
+    let received = observe_wallet_call("receive", state.wallet.receive(Some(token), None, None))
+        .await
+        .map_err(|e| AppError::ValidationError(format!("Token could not be redeemed: {}", e)))?;
+    let sats = received.amount;
+    record_sats_received(sats);
+
+    mark_credit_redeemed(&state.db, id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    add_transaction(
+        &state.db,
+        &state.cipher,
+        token,
+        sats * MSAT_PER_SAT,
+        TransactionDirection::Incoming,
+        credit.request_id,
+        checked.dleq,
+    )
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    get_credit(&state.db, id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .map(Json)
+        .ok_or(AppError::NotFound)
+}
+
+pub async fn get_all_transactions<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<LedgerListParams>,
+) -> Result<Json<TransactionListResponse>, AppError> {
+    let options = params.list_options()?;
+    let filter = TransactionFilter {
+        direction: params.direction,
+        min_amount_msat: params.min_amount_msat,
+        max_amount_msat: params.max_amount_msat,
+        from: params.from,
+        to: params.to,
+        search: params.q,
+    };
+
+    get_transactions(&state.db, &filter, &options)
+        .await
+        .map(Json)
+        .map_err(|_| AppError::InternalServerError)
+}
+
+pub async fn get_transaction_by_id<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Transaction>, AppError> {
+    match get_transaction(&state.db, id).await {
+        Ok(Some(transaction)) => Ok(Json(transaction)),
+        Ok(None) => Err(AppError::NotFound),
+        Err(_) => Err(AppError::InternalServerError),
     }
 }
 
-pub async fn get_all_transactions(
-    State(state): State<Arc<AppState>>,
-    params: Query<PaginationParams>,
-) -> Result<Json<TransactionListResponse>, StatusCode> {
-    match get_transactions(&state.db, params.page, params.page_size).await {
+#[derive(Deserialize)]
+pub struct RequestListParams {
+    page: Option<i64>,
+    #[serde(rename = "pageSize")]
+    page_size: Option<i64>,
+    model: Option<String>,
+    endpoint: Option<String>,
+    provider: Option<String>,
+    client_key: Option<String>,
+    status: Option<i32>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+pub async fn get_all_requests<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<RequestListParams>,
+) -> Result<Json<RequestListResponse>, StatusCode> {
+    let filter = RequestFilter {
+        model: params.model,
+        endpoint: params.endpoint,
+        provider: params.provider,
+        client_key: params.client_key,
+        status: params.status,
+        from: params.from,
+        to: params.to,
+    };
+
+    match get_requests(&state.db, &filter, params.page, params.page_size).await {
         Ok(response) => Ok(Json(response)),
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
+
+#[derive(Deserialize)]
+pub struct AnalyticsParams {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    bucket: Option<Bucket>,
+    group_by: Option<GroupBy>,
+}
+
+impl AnalyticsParams {
+    /// Defaults to the last 30 days.
+    fn range(&self) -> Result<(DateTime<Utc>, DateTime<Utc>), AppError> {
+        let to = self.to.unwrap_or_else(Utc::now);
+        let from = self.from.unwrap_or(to - chrono::Duration::days(30));
+        if from >= to {
+            return Err(AppError::ValidationError(
+                "`from` must be before `to`".to_string(),
+            ));
+        }
+        Ok((from, to))
+    }
+}
+
+pub async fn get_spend_analytics<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<AnalyticsParams>,
+) -> Result<Json<Vec<SpendPoint>>, AppError> {
+    let (from, to) = params.range()?;
+    get_spend_series(
+        &state.db,
+        from,
+        to,
+        params.bucket.unwrap_or_default(),
+        params.group_by,
+    )
+    .await
+    .map(Json)
+    .map_err(|_| AppError::InternalServerError)
+}
+
+pub async fn get_summary_analytics<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<AnalyticsParams>,
+) -> Result<Json<Vec<SpendSummary>>, AppError> {
+    let (from, to) = params.range()?;
+    get_spend_summary(&state.db, from, to, params.group_by)
+        .await
+        .map(Json)
+        .map_err(|_| AppError::InternalServerError)
+}
+
+#[derive(Serialize)]
+pub struct PricingResponse {
+    pub rounding: SubSatRounding,
+    pub min_payment_sats: i64,
+    pub default_price: ModelPricing,
+    /// Models with a rate of their own, stored rates winning over configured ones.
+    pub models: HashMap<String, ModelPricing>,
+    /// Key the charge breakdowns are signed with, when running as a provider.
+    pub public_key: Option<String>,
+    /// Key clients should lock their payments to (NUT-11).
+    pub p2pk_pubkey: Option<String>,
+}
+
+pub async fn get_pricing<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+) -> Result<Json<PricingResponse>, AppError> {
+    let settings = state.pricing.settings();
+    let models = state
+        .pricing
+        .price_list(&state.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    let p2pk_pubkey = match state.wallet.lock().await {
+        Ok(lock) => lock
+            .p2pk
+            .map(|key| key.trim_start_matches("P2PK:").to_string()),
+        Err(e) => {
+            tracing::warn!("Wallet has no P2PK key to advertise: {}", e);
+            None
+        }
+    };
+
+    Ok(Json(PricingResponse {
+        rounding: settings.rounding,
+        min_payment_sats: settings.min_payment_sats,
+        default_price: settings.default_price.clone(),
+        models,
+        public_key: state.pricing.public_key().map(|key| key.to_hex()),
+        p2pk_pubkey,
+    }))
+}
+
+pub async fn update_model_price<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(model): Path<String>,
+    Json(pricing): Json<ModelPricing>,
+) -> Result<Json<ModelPriceRecord>, AppError> {
+    if pricing.prompt_msat_per_1k < 0
+        || pricing.completion_msat_per_1k < 0
+        || pricing.request_msat < 0
+    {
+        return Err(AppError::ValidationError(
+            "rates must not be negative".to_string(),
+        ));
+    }
+
+    upsert_model_price(&state.db, &model, &pricing)
+        .await
+        .map(Json)
+        .map_err(|_| AppError::InternalServerError)
+}
+
+/// Drops the stored rate of a model so the configured one applies again.
+pub async fn reset_model_price<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(model): Path<String>,
+) -> Result<StatusCode, AppError> {
+    match delete_model_price(&state.db, &model).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(AppError::NotFound),
+        Err(_) => Err(AppError::InternalServerError),
+    }
+}
+
+/// A mint as the gateway sees it: its rule, whether it is trusted and what
+/// we hold there.
+#[derive(Serialize)]
+pub struct MintView {
+    pub url: String,
+    pub status: Option<MintStatus>,
+    #[serde(flatten)]
+    pub trust: MintTrust,
+    pub balance_sats: i64,
+    pub info: Option<serde_json::Value>,
+    pub info_fetched_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct MintRuleRequest {
+    pub status: Option<MintStatus>,
+    pub max_balance_sats: Option<i64>,
+}
+
+fn mint_view<W: CashuWalletApi>(
+    state: &AppState<W>,
+    url: &str,
+    record: Option<&MintRecord>,
+    balances: &HashMap<String, i64>,
+) -> MintView {
+    MintView {
+        url: url.to_string(),
+        status: record.and_then(|record| record.status),
+        trust: state.mint_policy.trust(url, record),
+        balance_sats: balances.get(url).copied().unwrap_or(0),
+        info: record.and_then(|record| record.info.clone()),
+        info_fetched_at: record.and_then(|record| record.info_fetched_at),
+    }
+}
+
+fn mint_path(url: &str) -> Result<String, AppError> {
+    normalize_mint_url(url).map_err(|_| AppError::ValidationError("invalid mint URL".to_string()))
+}
+
+/// Every mint with a rule, seen in a token or holding part of our balance.
+pub async fn list_mints<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+) -> Result<Json<Vec<MintView>>, AppError> {
+    let records = get_mints(&state.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    let balances = match observe_wallet_call("balance", state.wallet.balance()).await {
+        Ok(balance) => mint_balances(&balance),
+        Err(e) => {
+            tracing::warn!("Failed to read the wallet balance: {}", e);
+            HashMap::new()
+        }
+    };
+
+    let mut urls: Vec<String> = records
+        .iter()
+        .map(|record| record.url.clone())
+        .chain(state.mint_policy.configured_mints().cloned())
+        .chain(balances.keys().cloned())
+        .collect();
+    urls.sort();
+    urls.dedup();
+
+    Ok(Json(
+        urls.iter()
+            .map(|url| {
+                let record = records.iter().find(|record| &record.url == url);
+                mint_view(&state, url, record, &balances)
+            })
+            .collect(),
+    ))
+}
+
+/// Allows or denies a mint and sets its balance limit, then fetches its
+/// info.
+pub async fn update_mint<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(url): Path<String>,
+    Json(rule): Json<MintRuleRequest>,
+) -> Result<Json<MintView>, AppError> {
+    let url = mint_path(&url)?;
+    if rule.max_balance_sats.is_some_and(|max| max < 0) {
+        return Err(AppError::ValidationError(
+            "max_balance_sats must not be negative".to_string(),
+        ));
+    }
+
+    upsert_mint_rule(&state.db, &url, rule.status, rule.max_balance_sats)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    if let Err(e) = state.mint_policy.refresh_info(&state.db, &url).await {
+        tracing::warn!("Failed to fetch the info of mint {}: {}", url, e);
+    }
+
+    mint_by_url(&state, &url).await.map(Json)
+}
+
+/// Forgets the rule and info of a mint, so the configuration applies again.
+pub async fn reset_mint<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(url): Path<String>,
+) -> Result<StatusCode, AppError> {
+    match delete_mint(&state.db, &mint_path(&url)?).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(AppError::NotFound),
+        Err(_) => Err(AppError::InternalServerError),
+    }
+}
+
+/// Fetches the NUT-06 info of a mint again.
+pub async fn refresh_mint_info<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Path(url): Path<String>,
+) -> Result<Json<MintView>, AppError> {
+    let url = mint_path(&url)?;
+    state
+        .mint_policy
+        .refresh_info(&state.db, &url)
+        .await
+        .map_err(|e| AppError::ValidationError(format!("Failed to fetch mint info: {}", e)))?;
+
+    mint_by_url(&state, &url).await.map(Json)
+}
+
+async fn mint_by_url<W: CashuWalletApi>(
+    state: &AppState<W>,
+    url: &str,
+) -> Result<MintView, AppError> {
+    let record = get_mint(&state.db, url)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    let balances = observe_wallet_call("balance", state.wallet.balance())
+        .await
+        .map(|balance| mint_balances(&balance))
+        .unwrap_or_default();
+    Ok(mint_view(state, url, record.as_ref(), &balances))
+}
@@ -0,0 +1,192 @@
+use crate::{handlers::get_server_config, metrics::observe_wallet_call, models::AppState};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use reqwest::Client;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use wallet::api::CashuWalletApi;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Error,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyCheck {
+    pub status: CheckStatus,
+    pub latency_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl DependencyCheck {
+    fn skipped(reason: &str) -> Self {
+        Self {
+            status: CheckStatus::Skipped,
+            latency_ms: None,
+            error: None,
+            details: Some(json!({ "reason": reason })),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessChecks {
+    pub database: DependencyCheck,
+    pub wallet: DependencyCheck,
+    pub upstream: DependencyCheck,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub status: &'static str,
+    pub checks: ReadinessChecks,
+}
+
+#[derive(Deserialize)]
+pub struct ReadinessParams {
+    /// Also probe the configured provider's `/v1/models`. Off by default so
+    /// a provider outage doesn't take the gateway out of rotation.
+    upstream: Option<bool>,
+}
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness: every dependency a paid request needs is reachable. Answers
+/// 503 with the same body when any check fails.
+pub async fn readyz<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    Query(params): Query<ReadinessParams>,
+) -> Response {
+    let (database, wallet, upstream) = tokio::join!(
+        check_database(&state),
+        check_wallet(&state),
+        check_upstream(&state, params.upstream.unwrap_or(false)),
+    );
+
+    let checks = ReadinessChecks {
+        database,
+        wallet,
+        upstream,
+    };
+    let ready = [&checks.database, &checks.wallet, &checks.upstream]
+        .iter()
+        .all(|check| check.status != CheckStatus::Error);
+
+    let (status, label) = if ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+
+    (
+        status,
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(ReadinessResponse {
+            status: label,
+            checks,
+        }),
+    )
+        .into_response()
+}
+
+async fn check_database<W: CashuWalletApi>(state: &AppState<W>) -> DependencyCheck {
+    timed(async {
+        sqlx::query("SELECT 1")
+            .execute(&state.db)
+            .await
+            .map(|_| None)
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+async fn check_wallet<W: CashuWalletApi>(state: &AppState<W>) -> DependencyCheck {
+    timed(async {
+        let info = observe_wallet_call("info", state.wallet.info())
+            .await
+            .map_err(|e| format!("info failed: {}", e))?;
+        let balance = observe_wallet_call("balance", state.wallet.balance())
+            .await
+            .map_err(|e| format!("balance failed: {}", e))?;
+
+        Ok(Some(json!({
+            "version": info.version,
+            "mint_urls": info.mint_urls,
+            "balance": balance.balance,
+        })))
+    })
+    .await
+}
+
+async fn check_upstream<W: CashuWalletApi>(state: &AppState<W>, probe: bool) -> DependencyCheck {
+    if !probe {
+        return DependencyCheck::skipped("not requested");
+    }
+    let Some(config) = get_server_config(&state.db, &state.cipher).await else {
+        return DependencyCheck::skipped("no provider configured");
+    };
+
+    timed(async {
+        let response = Client::new()
+            .get(format!("{}/v1/models", config.endpoint))
+            .bearer_auth(config.api_key.expose_secret())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(Some(json!({ "endpoint": config.endpoint })))
+        } else {
+            Err(format!("{} answered {}", config.endpoint, status))
+        }
+    })
+    .await
+}
+
+async fn timed(check: impl Future<Output = Result<Option<Value>, String>>) -> DependencyCheck {
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())));
+    let latency_ms = Some(started.elapsed().as_millis() as i64);
+
+    match result {
+        Ok(details) => DependencyCheck {
+            status: CheckStatus::Ok,
+            latency_ms,
+            error: None,
+            details,
+        },
+        Err(error) => {
+            tracing::warn!("Readiness check failed: {}", error);
+            DependencyCheck {
+                status: CheckStatus::Error,
+                latency_ms,
+                error: Some(error),
+                details: None,
+            }
+        }
+    }
+}
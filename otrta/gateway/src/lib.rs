@@ -1,7 +1,24 @@
+pub mod app;
 pub mod connection;
+pub mod crypto;
 pub mod db;
 pub mod error;
+pub mod export;
 pub mod forward;
 pub mod handlers;
+pub mod health;
+pub mod lnurl;
+pub mod metrics;
+pub mod mint_policy;
+pub mod mints;
 pub mod models;
+pub mod pricing;
+pub mod provider;
+pub mod rebalance;
+pub mod telemetry;
+pub mod token;
+pub mod topup;
+pub mod transfer;
+pub mod usage;
 pub mod wallet;
+pub mod withdraw;
@@ -0,0 +1,225 @@
+//! Paying Lightning Addresses (LUD-16) and LNURL-pay (LUD-06) links: the
+//! service is asked for an invoice, which is checked before it is paid.
+
+use cdk::lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{str::FromStr, time::Duration};
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum LnurlError {
+    #[error("{0} is not a Lightning Address or LNURL")]
+    InvalidTarget(String),
+    #[error("LNURL {0} has to use https")]
+    Insecure(String),
+    #[error("LNURL service could not be reached: {0}")]
+    Unreachable(String),
+    #[error("LNURL service refused: {0}")]
+    Refused(String),
+    #[error("LNURL service answered with something else than {0}")]
+    InvalidResponse(&'static str),
+    #[error("Amount has to be between {min} and {max} sat for this destination")]
+    OutOfBounds { min: i64, max: i64 },
+    #[error("Comments can be at most {0} characters for this destination")]
+    CommentTooLong(usize),
+    #[error("Invoice from the LNURL service is for {actual} msat instead of {expected}")]
+    AmountMismatch { expected: u64, actual: u64 },
+    #[error("Invoice from the LNURL service doesn't commit to its metadata")]
+    DescriptionMismatch,
+}
+
+/// LUD-06 `payRequest` of a service, amounts in millisats.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayRequest {
+    pub callback: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    pub metadata: String,
+    pub tag: String,
+    /// Longest comment accepted (LUD-12), none when unset.
+    #[serde(default)]
+    pub comment_allowed: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Response<T> {
+    Error { status: String, reason: String },
+    Ok(T),
+}
+
+#[derive(Deserialize)]
+struct InvoiceResponse {
+    pr: String,
+}
+
+pub struct LnurlClient {
+    client: reqwest::Client,
+    /// Allow plain http services, for testing against a local one.
+    allow_http: bool,
+}
+
+impl LnurlClient {
+    pub fn new(allow_http: bool) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+            allow_http,
+        }
+    }
+
+    /// The URL a Lightning Address, `lnurl1...` string or `lnurlp://` link
+    /// points at.
+    pub fn resolve(&self, target: &str) -> Result<Url, LnurlError> {
+        let invalid = || LnurlError::InvalidTarget(target.to_string());
+        let trimmed = target.trim();
+        let trimmed = trimmed
+            .get(..10)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("lightning:"))
+            .map_or(trimmed, |_| &trimmed[10..]);
+
+        let url = if let Some((user, domain)) = trimmed.split_once('@') {
+            let valid_user = !user.is_empty()
+                && user
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c));
+            if !valid_user || domain.is_empty() || domain.contains('/') {
+                return Err(invalid());
+            }
+            let scheme = if self.allow_http { "http" } else { "https" };
+            format!(
+                "{}://{}/.well-known/lnurlp/{}",
+                scheme,
+                domain,
+                user.to_lowercase()
+            )
+        } else if trimmed.to_lowercase().starts_with("lnurl1") {
+            let (hrp, data) = bech32::decode(trimmed).map_err(|_| invalid())?;
+            if hrp.to_lowercase() != "lnurl" {
+                return Err(invalid());
+            }
+            String::from_utf8(data).map_err(|_| invalid())?
+        } else if let Some(rest) = trimmed.strip_prefix("lnurlp://") {
+            // LUD-17: onion services are reached over http.
+            let host = rest.split(['/', ':']).next().unwrap_or_default();
+            let scheme = if host.ends_with(".onion") {
+                "http"
+            } else {
+                "https"
+            };
+            format!("{}://{}", scheme, rest)
+        } else {
+            return Err(invalid());
+        };
+
+        let url = Url::parse(&url).map_err(|_| invalid())?;
+        let onion = url.host_str().is_some_and(|host| host.ends_with(".onion"));
+        match url.scheme() {
+            "https" => Ok(url),
+            "http" if self.allow_http || onion => Ok(url),
+            _ => Err(LnurlError::Insecure(url.to_string())),
+        }
+    }
+
+    pub async fn pay_request(&self, url: Url) -> Result<PayRequest, LnurlError> {
+        let request: PayRequest = self.get(url).await?;
+        if request.tag != "payRequest" {
+            return Err(LnurlError::InvalidResponse("a payRequest"));
+        }
+        Ok(request)
+    }
+
+    /// Asks the service behind `target` for an invoice of `amount_sats` and
+    /// checks that it is for that amount and commits to the service's
+    /// metadata.
+    pub async fn fetch_invoice(
+        &self,
+        target: &str,
+        amount_sats: i64,
+        comment: Option<&str>,
+    ) -> Result<String, LnurlError> {
+        let request = self.pay_request(self.resolve(target)?).await?;
+
+        let amount_msat = u64::try_from(amount_sats).unwrap_or(0) * 1000;
+        if amount_msat == 0
+            || amount_msat < request.min_sendable
+            || amount_msat > request.max_sendable
+        {
+            return Err(LnurlError::OutOfBounds {
+                min: request.min_sendable.div_ceil(1000) as i64,
+                max: (request.max_sendable / 1000) as i64,
+            });
+        }
+        let comment = comment.filter(|comment| !comment.is_empty());
+        if let Some(comment) = comment
+            && comment.chars().count() > request.comment_allowed
+        {
+            return Err(LnurlError::CommentTooLong(request.comment_allowed));
+        }
+
+        let mut callback = Url::parse(&request.callback)
+            .map_err(|_| LnurlError::InvalidResponse("a callback URL"))?;
+        if callback.scheme() != "https" && !self.allow_http {
+            return Err(LnurlError::Insecure(callback.to_string()));
+        }
+        callback
+            .query_pairs_mut()
+            .append_pair("amount", &amount_msat.to_string());
+        if let Some(comment) = comment {
+            callback.query_pairs_mut().append_pair("comment", comment);
+        }
+        let invoice: InvoiceResponse = self.get(callback).await?;
+
+        verify_invoice(&invoice.pr, amount_msat, &request.metadata)?;
+        Ok(invoice.pr)
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: Url) -> Result<T, LnurlError> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| LnurlError::Unreachable(e.to_string()))?;
+        let body: Response<T> = response
+            .json()
+            .await
+            .map_err(|_| LnurlError::InvalidResponse("LNURL JSON"))?;
+
+        match body {
+            Response::Ok(body) => Ok(body),
+            Response::Error { status, reason } if status.eq_ignore_ascii_case("error") => {
+                Err(LnurlError::Refused(reason))
+            }
+            Response::Error { .. } => Err(LnurlError::InvalidResponse("LNURL JSON")),
+        }
+    }
+}
+
+/// Checks that `bolt11` is for `amount_msat` and that its description hash
+/// is the SHA-256 of `metadata`, as LUD-06 requires.
+pub fn verify_invoice(bolt11: &str, amount_msat: u64, metadata: &str) -> Result<(), LnurlError> {
+    let invoice = Bolt11Invoice::from_str(bolt11)
+        .map_err(|_| LnurlError::InvalidResponse("a bolt11 invoice"))?;
+
+    let actual = invoice.amount_milli_satoshis().unwrap_or(0);
+    if actual != amount_msat {
+        return Err(LnurlError::AmountMismatch {
+            expected: amount_msat,
+            actual,
+        });
+    }
+
+    match invoice.description() {
+        Bolt11InvoiceDescription::Hash(hash)
+            if AsRef::<[u8]>::as_ref(&hash.0) == Sha256::digest(metadata.as_bytes()).as_slice() =>
+        {
+            Ok(())
+        }
+        _ => Err(LnurlError::DescriptionMismatch),
+    }
+}
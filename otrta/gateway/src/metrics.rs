@@ -0,0 +1,145 @@
+use crate::models::AppState;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::{future::Future, sync::Arc, time::Instant};
+use wallet::api::CashuWalletApi;
+
+pub const HTTP_REQUESTS: &str = "gateway_http_requests_total";
+pub const HTTP_REQUEST_DURATION: &str = "gateway_http_request_duration_seconds";
+pub const UPSTREAM_REQUESTS: &str = "gateway_upstream_requests_total";
+pub const UPSTREAM_REQUEST_DURATION: &str = "gateway_upstream_request_duration_seconds";
+pub const UPSTREAM_ERRORS: &str = "gateway_upstream_errors_total";
+pub const SATS_SPENT: &str = "gateway_sats_spent_total";
+pub const SATS_RECEIVED: &str = "gateway_sats_received_total";
+pub const PAYMENT_FAILURES: &str = "gateway_payment_failures_total";
+pub const WALLET_CALLS: &str = "gateway_wallet_calls_total";
+pub const WALLET_CALL_DURATION: &str = "gateway_wallet_call_duration_seconds";
+pub const WALLET_BALANCE: &str = "gateway_wallet_balance_sats";
+pub const SWAPS: &str = "gateway_swaps_total";
+pub const SWAP_FEES: &str = "gateway_swap_fees_sats_total";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Installs the global Prometheus recorder. Must be called once at startup,
+/// before any metric is recorded.
+pub fn install_recorder() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets(LATENCY_BUCKETS)
+        .expect("Invalid latency buckets.")
+        .install_recorder()
+        .expect("Failed to install Prometheus recorder.")
+}
+
+pub async fn track_http_metrics(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("route", route),
+        ("method", method),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION, &labels).record(started.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Times a call to the wallet backend and counts its outcome.
+pub async fn observe_wallet_call<T, E>(
+    operation: &'static str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let result = call.await;
+
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    histogram!(WALLET_CALL_DURATION, "operation" => operation)
+        .record(started.elapsed().as_secs_f64());
+    counter!(WALLET_CALLS, "operation" => operation, "outcome" => outcome).increment(1);
+
+    result
+}
+
+pub fn record_upstream_response(
+    endpoint: &str,
+    model: Option<&str>,
+    provider: &str,
+    status: u16,
+    started: Instant,
+) {
+    let labels = [
+        ("endpoint", endpoint.to_string()),
+        ("model", model_label(model, status).to_string()),
+        ("provider", provider.to_string()),
+        ("status", status.to_string()),
+    ];
+    counter!(UPSTREAM_REQUESTS, &labels).increment(1);
+    histogram!(UPSTREAM_REQUEST_DURATION, &labels).record(started.elapsed().as_secs_f64());
+
+    if status >= 500 {
+        record_upstream_error(provider, "server_error");
+    } else if status == 402 {
+        record_upstream_error(provider, "payment_required");
+    }
+}
+
+/// The model named by the client only becomes a label once the upstream
+/// served it, so that clients can't create series at will; others are `other`.
+fn model_label(model: Option<&str>, status: u16) -> &str {
+    match model {
+        Some(model) if (200..300).contains(&status) => model,
+        Some(_) => "other",
+        None => "",
+    }
+}
+
+pub fn record_upstream_error(provider: &str, kind: &'static str) {
+    counter!(UPSTREAM_ERRORS, "provider" => provider.to_string(), "kind" => kind).increment(1);
+}
+
+pub fn record_payment_failure(cause: &'static str) {
+    counter!(PAYMENT_FAILURES, "cause" => cause).increment(1);
+}
+
+pub fn record_sats_spent(sats: i64) {
+    counter!(SATS_SPENT).increment(sats.max(0) as u64);
+}
+
+pub fn record_sats_received(sats: i64) {
+    counter!(SATS_RECEIVED).increment(sats.max(0) as u64);
+}
+
+pub fn record_swap(outcome: &'static str, fee_sats: i64) {
+    counter!(SWAPS, "outcome" => outcome).increment(1);
+    counter!(SWAP_FEES).increment(fee_sats.max(0) as u64);
+}
+
+pub async fn metrics_handler<W: CashuWalletApi>(State(state): State<Arc<AppState<W>>>) -> Response {
+    // The balance gauge is refreshed on scrape so it never goes stale.
+    if let Ok(balance) = observe_wallet_call("balance", state.wallet.balance()).await {
+        gauge!(WALLET_BALANCE).set(balance.balance as f64);
+    }
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
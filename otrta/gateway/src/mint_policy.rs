@@ -0,0 +1,248 @@
+use crate::{
+    connection::MintPolicySettings,
+    db::{
+        mint::{MintRecord, MintStatus, get_mint, record_mint, store_mint_info},
+        transaction::DleqStatus,
+    },
+    metrics::observe_wallet_call,
+    mints::{mint_balances, normalize_mint_url},
+    token::summarize_token,
+};
+use anyhow::Context;
+use cdk::nuts::MintInfo;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use wallet::api::CashuWalletApi;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum PolicyViolation {
+    #[error("token names no mint")]
+    UnknownMint,
+    #[error("tokens in {0} are not accepted, only sat")]
+    UnsupportedUnit(String),
+    #[error("mint {0} is not trusted")]
+    Untrusted(String),
+    #[error("mint {mint} would hold {balance} sat, over its limit of {limit}")]
+    OverLimit {
+        mint: String,
+        balance: i64,
+        limit: i64,
+    },
+    #[error("proofs of the token were not signed by mint {0} (NUT-12)")]
+    InvalidDleq(String),
+    #[error("token carries no DLEQ proofs, which mint {0} provides (NUT-12)")]
+    MissingDleq(String),
+    #[error("mint policy could not be checked: {0}")]
+    Unavailable(String),
+}
+
+/// A token that passed the policy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckedToken {
+    pub mint: String,
+    /// `None` when the wallet can't verify DLEQ proofs.
+    pub dleq: Option<DleqStatus>,
+}
+
+/// Where a mint stands under the policy.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MintTrust {
+    pub trusted: bool,
+    pub max_balance_sats: Option<i64>,
+}
+
+#[derive(Clone)]
+pub struct MintPolicy {
+    settings: MintPolicySettings,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    client: reqwest::Client,
+}
+
+impl MintPolicy {
+    /// `wallet_mints` are the mints the wallet was set up with, which are
+    /// trusted unless denied.
+    pub fn new(settings: MintPolicySettings, wallet_mints: &[String]) -> Self {
+        let normalize = |mints: &[String]| -> Vec<String> {
+            mints
+                .iter()
+                .map(|mint| normalize_mint_url(mint).unwrap_or_else(|_| mint.clone()))
+                .collect()
+        };
+        let mut allow = normalize(&settings.allow);
+        allow.extend(normalize(wallet_mints));
+        let deny = normalize(&settings.deny);
+
+        Self {
+            settings,
+            allow,
+            deny,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    pub fn settings(&self) -> &MintPolicySettings {
+        &self.settings
+    }
+
+    /// Mints with a rule in the configuration.
+    pub fn configured_mints(&self) -> impl Iterator<Item = &String> {
+        self.allow.iter().chain(&self.deny)
+    }
+
+    /// Trust of `url` given its stored record, which wins over the
+    /// configuration.
+    pub fn trust(&self, url: &str, record: Option<&MintRecord>) -> MintTrust {
+        let trusted = match record.and_then(|record| record.status) {
+            Some(status) => status == MintStatus::Allowed,
+            None if self.deny.iter().any(|mint| mint == url) => false,
+            None if self.allow.iter().any(|mint| mint == url) => true,
+            None => self.settings.trust_unlisted,
+        };
+        let max_balance_sats = record
+            .and_then(|record| record.max_balance_sats)
+            .or(self.settings.max_balance_sats);
+
+        MintTrust {
+            trusted,
+            max_balance_sats,
+        }
+    }
+
+    pub async fn evaluate(&self, pool: &PgPool, url: &str) -> Result<MintTrust, sqlx::Error> {
+        let record = get_mint(pool, url).await?;
+        Ok(self.trust(url, record.as_ref()))
+    }
+
+    /// Checks that `token` may be redeemed into `wallet`: it has to be in sats,
+    /// its mint has to be trusted and stay within its balance limit, and its proofs must carry
+    /// valid DLEQ proofs when the mint supports NUT-12.
+    pub async fn check_token<W: CashuWalletApi>(
+        &self,
+        pool: &PgPool,
+        wallet: &W,
+        token: &str,
+    ) -> Result<CheckedToken, PolicyViolation> {
+        let summary = summarize_token(token);
+        // The ledger books every amount in sats.
+        if let Some(unit) = summary.unit.as_deref().filter(|unit| *unit != "sat") {
+            return Err(PolicyViolation::UnsupportedUnit(unit.to_string()));
+        }
+        let mint = summary
+            .mint_url
+            .as_deref()
+            .and_then(|mint| normalize_mint_url(mint).ok())
+            .ok_or(PolicyViolation::UnknownMint)?;
+
+        let record = get_mint(pool, &mint)
+            .await
+            .map_err(|e| PolicyViolation::Unavailable(e.to_string()))?;
+        let trust = self.trust(&mint, record.as_ref());
+        if !trust.trusted {
+            if record.is_none() {
+                self.note_unknown_mint(pool, &mint).await;
+            }
+            return Err(PolicyViolation::Untrusted(mint));
+        }
+
+        if let Some(limit) = trust.max_balance_sats {
+            let balance = observe_wallet_call("balance", wallet.balance())
+                .await
+                .map_err(|e| PolicyViolation::Unavailable(e.to_string()))?;
+            let held = mint_balances(&balance).get(&mint).copied().unwrap_or(0);
+            let balance = held + summary.amount.unwrap_or(0) as i64;
+            if balance > limit {
+                return Err(PolicyViolation::OverLimit {
+                    mint,
+                    balance,
+                    limit,
+                });
+            }
+        }
+
+        let dleq = match observe_wallet_call("verify_dleq", wallet.verify_dleq(token)).await {
+            Ok(status) => Some(DleqStatus::from(status)),
+            Err(e) => {
+                tracing::debug!("DLEQ proofs of a token from {} not checked: {}", mint, e);
+                None
+            }
+        };
+        match dleq {
+            Some(DleqStatus::Invalid) => Err(PolicyViolation::InvalidDleq(mint)),
+            Some(DleqStatus::Missing) if self.supports_dleq(pool, &mint, record.as_ref()).await => {
+                Err(PolicyViolation::MissingDleq(mint))
+            }
+            _ => Ok(CheckedToken { mint, dleq }),
+        }
+    }
+
+    /// Whether the NUT-06 info of a mint says it signs DLEQ proofs. Mints the
+    /// operator vouched for get their info fetched when none is stored yet,
+    /// e.g. those of the wallet configuration; others are never contacted.
+    async fn supports_dleq(&self, pool: &PgPool, url: &str, record: Option<&MintRecord>) -> bool {
+        let info = match record.and_then(|record| record.info.clone()) {
+            Some(info) => info,
+            None if self.vouched_for(url, record) => match self.refresh_info(pool, url).await {
+                Ok(info) => info,
+                Err(e) => {
+                    tracing::warn!("Failed to fetch the info of mint {}: {}", url, e);
+                    return false;
+                }
+            },
+            None => return false,
+        };
+        info.pointer("/nuts/12/supported")
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// Whether `url` is trusted by a rule or the configuration rather than
+    /// only through `trust_unlisted`.
+    fn vouched_for(&self, url: &str, record: Option<&MintRecord>) -> bool {
+        match record.and_then(|record| record.status) {
+            Some(status) => status == MintStatus::Allowed,
+            None => {
+                !self.deny.iter().any(|mint| mint == url)
+                    && self.allow.iter().any(|mint| mint == url)
+            }
+        }
+    }
+
+    /// Fetches the NUT-06 info of `url` and stores it.
+    pub async fn refresh_info(
+        &self,
+        pool: &PgPool,
+        url: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        let info: serde_json::Value = self
+            .client
+            .get(format!("{}/v1/info", url.trim_end_matches('/')))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        serde_json::from_value::<MintInfo>(info.clone()).context("Not a NUT-06 mint info")?;
+
+        store_mint_info(pool, url, &info).await?;
+        Ok(info)
+    }
+
+    /// Lists a mint we just met for the operator to look at. Only its URL is
+    /// kept: it comes from whoever sent the token, so nothing is fetched
+    /// from it until the operator asks.
+    async fn note_unknown_mint(&self, pool: &PgPool, url: &str) {
+        if !url.starts_with("https://") {
+            return;
+        }
+        match record_mint(pool, url, self.settings.max_unreviewed_mints).await {
+            Ok(true) => {}
+            Ok(false) => tracing::debug!("Not recording mint {}: too many unreviewed mints", url),
+            Err(e) => tracing::warn!("Failed to record mint {}: {}", url, e),
+        }
+    }
+}
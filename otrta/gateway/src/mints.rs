@@ -0,0 +1,62 @@
+use cdk::mint_url::MintUrl;
+use std::{collections::HashMap, str::FromStr};
+use wallet::api::models::BalanceResponse;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum MintSelectionError {
+    #[error("no mint holds {0} sat")]
+    InsufficientFunds(i64),
+    #[error("no mint accepted by the provider holds {0} sat")]
+    NoAcceptedMint(i64),
+}
+
+/// Canonical form of a mint URL, so that URLs differing only in a trailing
+/// slash or the case of the host compare equal.
+pub fn normalize_mint_url(url: &str) -> Result<String, cdk::mint_url::Error> {
+    MintUrl::from_str(url.trim()).map(|url| url.to_string())
+}
+
+/// Spendable sats per mint. Both nutshell and the CDK wallet report
+/// `available` for each mint; `balance` is used when it is missing.
+pub fn mint_balances(balance: &BalanceResponse) -> HashMap<String, i64> {
+    balance
+        .mints
+        .iter()
+        .flatten()
+        .filter_map(|(mint, value)| {
+            let sats = value
+                .get("available")
+                .or_else(|| value.get("balance"))
+                .and_then(|sats| sats.as_i64())?;
+            let mint = normalize_mint_url(mint).unwrap_or_else(|_| mint.clone());
+            Some((mint, sats))
+        })
+        .collect()
+}
+
+/// Picks the mint to pay `amount` sats from: among the mints in `accepted`,
+/// or all of them when it is empty, the one holding the most that can cover
+/// the amount.
+pub fn select_mint(
+    balances: &HashMap<String, i64>,
+    accepted: &[String],
+    amount: i64,
+) -> Result<String, MintSelectionError> {
+    let accepted: Vec<String> = accepted
+        .iter()
+        .filter_map(|mint| normalize_mint_url(mint).ok())
+        .collect();
+
+    balances
+        .iter()
+        .filter(|(mint, _)| accepted.is_empty() || accepted.contains(mint))
+        .filter(|(_, sats)| **sats >= amount)
+        // Ties go to the first URL so the choice doesn't depend on map order.
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(mint, _)| mint.clone())
+        .ok_or(if accepted.is_empty() {
+            MintSelectionError::InsufficientFunds(amount)
+        } else {
+            MintSelectionError::NoAcceptedMint(amount)
+        })
+}
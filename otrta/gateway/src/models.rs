@@ -1,8 +1,16 @@
+use crate::{
+    connection::{PaymentSettings, TopupSettings, TransferSettings, WithdrawSettings},
+    crypto::SecretCipher,
+    mint_policy::MintPolicy,
+    pricing::PricingEngine,
+    rebalance::Rebalancer,
+};
 use chrono::{DateTime, Utc};
+use metrics_exporter_prometheus::PrometheusHandle;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tokio::sync::RwLock;
-use wallet::api::CashuWalletClient;
+use wallet::api::CashuWalletApi;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum UserRole {
@@ -228,7 +236,8 @@ pub struct RegisterResponse {
     pub theme: String,
 }
 
-pub struct AppState {
+/// Shared state of the gateway, generic over the wallet that pays upstream.
+pub struct AppState<W: CashuWalletApi> {
     pub db: sqlx::PgPool,
     pub users: RwLock<HashMap<String, User>>,
     pub organizations: RwLock<HashMap<String, Organization>>,
@@ -236,5 +245,14 @@ pub struct AppState {
     pub models: RwLock<HashMap<String, Model>>,
     pub providers: RwLock<HashMap<String, Provider>>,
     pub credits: RwLock<HashMap<String, Credit>>,
-    pub wallet: CashuWalletClient,
+    pub wallet: W,
+    pub cipher: SecretCipher,
+    pub payments: PaymentSettings,
+    pub metrics: PrometheusHandle,
+    pub pricing: PricingEngine,
+    pub rebalancer: Rebalancer,
+    pub mint_policy: MintPolicy,
+    pub topup: TopupSettings,
+    pub withdraw: WithdrawSettings,
+    pub transfer: TransferSettings,
 }
@@ -0,0 +1,236 @@
+use crate::{
+    connection::{ProviderSettings, SubSatRounding},
+    db::{
+        MSAT_PER_SAT,
+        pricing::{get_model_price, get_model_prices},
+    },
+    usage::Usage,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use cdk::{
+    nuts::{PublicKey, SecretKey},
+    secp256k1::schnorr::Signature,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{collections::HashMap, str::FromStr};
+use uuid::Uuid;
+use wallet::models::ModelPricing;
+
+/// Charge breakdown of a response, base64url encoded JSON.
+pub const CHARGE_HEADER: &str = "X-CHARGE";
+/// Hex BIP340 signature over the SHA-256 of the `X-CHARGE` value.
+pub const CHARGE_SIGNATURE_HEADER: &str = "X-CHARGE-SIGNATURE";
+pub const CHARGE_PUBKEY_HEADER: &str = "X-CHARGE-PUBKEY";
+
+/// What a request cost and how that was worked out.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Charge {
+    pub request_id: Option<Uuid>,
+    pub model: String,
+    pub pricing: ModelPricing,
+    /// Whether the upstream reported usage. Without it the cost is unknown
+    /// and the whole payment is kept.
+    pub usage_reported: bool,
+    /// Set when the upstream failed and the payment was handed back.
+    pub refunded: bool,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// Exact cost, rounded up to the millisat.
+    pub cost_msat: i64,
+    pub rounding: SubSatRounding,
+    pub paid_sats: i64,
+    pub charged_sats: i64,
+    pub change_sats: i64,
+}
+
+/// A charge as sent in headers or the change event of a stream.
+#[derive(Clone, Debug)]
+pub struct SignedCharge {
+    pub encoded: String,
+    pub signature: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChargeError {
+    #[error("malformed charge")]
+    Malformed,
+    #[error("charge signature does not verify")]
+    BadSignature,
+}
+
+#[derive(Clone)]
+pub struct PricingEngine {
+    settings: ProviderSettings,
+    signer: Option<SecretKey>,
+}
+
+impl PricingEngine {
+    /// Charges are only signed when a `signer` is given.
+    pub fn new(settings: ProviderSettings, signer: Option<SecretKey>) -> Self {
+        Self { settings, signer }
+    }
+
+    pub fn settings(&self) -> &ProviderSettings {
+        &self.settings
+    }
+
+    pub fn public_key(&self) -> Option<PublicKey> {
+        self.signer.as_ref().map(SecretKey::public_key)
+    }
+
+    /// Rate of `model`: the stored one, else the configured one, else the
+    /// default.
+    pub async fn price(&self, pool: &PgPool, model: &str) -> Result<ModelPricing, sqlx::Error> {
+        Ok(match get_model_price(pool, model).await? {
+            Some(record) => record.pricing,
+            None => self.configured_price(model),
+        })
+    }
+
+    /// Every model with a rate of its own, stored rates winning over
+    /// configured ones. Models missing here cost `default_price`.
+    pub async fn price_list(
+        &self,
+        pool: &PgPool,
+    ) -> Result<HashMap<String, ModelPricing>, sqlx::Error> {
+        let mut prices = self.settings.models.clone();
+        for record in get_model_prices(pool).await? {
+            prices.insert(record.model, record.pricing);
+        }
+        Ok(prices)
+    }
+
+    pub fn configured_price(&self, model: &str) -> ModelPricing {
+        self.settings
+            .models
+            .get(model)
+            .unwrap_or(&self.settings.default_price)
+            .clone()
+    }
+
+    /// Works out what to keep of `paid_sats` given the upstream's usage.
+    pub fn charge(
+        &self,
+        model: &str,
+        pricing: &ModelPricing,
+        usage: Option<&Usage>,
+        paid_sats: i64,
+        request_id: Option<Uuid>,
+    ) -> Charge {
+        let prompt_tokens = usage
+            .and_then(|usage| usage.prompt_tokens)
+            .unwrap_or(0)
+            .max(0);
+        let completion_tokens = usage
+            .and_then(|usage| usage.completion_tokens)
+            .unwrap_or(0)
+            .max(0);
+
+        let (cost_msat, charged_sats) = match usage {
+            Some(_) => {
+                let cost_msat = cost_msat(pricing, prompt_tokens, completion_tokens);
+                let charged = round_to_sats(cost_msat, self.settings.rounding);
+                (cost_msat, charged.clamp(0, paid_sats))
+            }
+            None => (0, paid_sats),
+        };
+
+        Charge {
+            request_id,
+            model: model.to_string(),
+            pricing: pricing.clone(),
+            usage_reported: usage.is_some(),
+            refunded: false,
+            prompt_tokens,
+            completion_tokens,
+            cost_msat,
+            rounding: self.settings.rounding,
+            paid_sats,
+            charged_sats,
+            change_sats: paid_sats - charged_sats,
+        }
+    }
+
+    /// A charge handing the whole payment back.
+    pub fn refund(
+        &self,
+        model: &str,
+        pricing: &ModelPricing,
+        paid_sats: i64,
+        request_id: Option<Uuid>,
+    ) -> Charge {
+        Charge {
+            refunded: true,
+            cost_msat: 0,
+            charged_sats: 0,
+            change_sats: paid_sats,
+            ..self.charge(model, pricing, None, paid_sats, request_id)
+        }
+    }
+
+    pub fn sign(&self, charge: &Charge) -> Option<SignedCharge> {
+        let signer = self.signer.as_ref()?;
+        let encoded = URL_SAFE_NO_PAD.encode(serde_json::to_vec(charge).ok()?);
+        let signature = signer
+            .sign(encoded.as_bytes())
+            .inspect_err(|e| tracing::error!("Failed to sign charge: {}", e))
+            .ok()?;
+
+        Some(SignedCharge {
+            encoded,
+            signature: signature.to_string(),
+        })
+    }
+}
+
+/// Exact cost of a request in millisats, any fraction of a millisat rounded
+/// up.
+pub fn cost_msat(pricing: &ModelPricing, prompt_tokens: i64, completion_tokens: i64) -> i64 {
+    // Thousandths of a millisat, as rates are per thousand tokens.
+    let micro = prompt_tokens * pricing.prompt_msat_per_1k
+        + completion_tokens * pricing.completion_msat_per_1k;
+    (micro + 999) / 1000 + pricing.request_msat
+}
+
+/// Completion tokens `paid_sats` still covers after a prompt of
+/// `prompt_tokens`, or `None` when it doesn't cover the prompt.
+pub fn affordable_completion_tokens(
+    pricing: &ModelPricing,
+    prompt_tokens: i64,
+    paid_sats: i64,
+) -> Option<i64> {
+    let left_msat = paid_sats * MSAT_PER_SAT - cost_msat(pricing, prompt_tokens, 0);
+    if left_msat < 0 {
+        return None;
+    }
+    if pricing.completion_msat_per_1k <= 0 {
+        return Some(i64::MAX);
+    }
+    Some(left_msat * 1000 / pricing.completion_msat_per_1k)
+}
+
+pub fn round_to_sats(msat: i64, rounding: SubSatRounding) -> i64 {
+    match rounding {
+        SubSatRounding::Up => (msat + MSAT_PER_SAT - 1) / MSAT_PER_SAT,
+        SubSatRounding::Down => msat / MSAT_PER_SAT,
+        SubSatRounding::Nearest => (msat + MSAT_PER_SAT / 2) / MSAT_PER_SAT,
+    }
+}
+
+/// Checks a charge received in `X-CHARGE` against the provider's key.
+pub fn verify_charge(
+    encoded: &str,
+    signature: &str,
+    public_key: &PublicKey,
+) -> Result<Charge, ChargeError> {
+    let signature = Signature::from_str(signature).map_err(|_| ChargeError::Malformed)?;
+    public_key
+        .verify(encoded.as_bytes(), &signature)
+        .map_err(|_| ChargeError::BadSignature)?;
+
+    let json = URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|_| ChargeError::Malformed)?;
+    serde_json::from_slice(&json).map_err(|_| ChargeError::Malformed)
+}
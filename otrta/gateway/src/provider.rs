@@ -0,0 +1,733 @@
+use crate::{
+    db::{
+        MSAT_PER_SAT,
+        request::{NewRequest, create_request},
+        transaction::{TransactionDirection, add_transaction},
+    },
+    forward::{client_key, finish_request, record_request_cost, trace_headers, upstream_span},
+    handlers::get_server_config,
+    metrics::{self, observe_wallet_call},
+    models::AppState,
+    pricing::{
+        CHARGE_HEADER, CHARGE_PUBKEY_HEADER, CHARGE_SIGNATURE_HEADER, Charge,
+        affordable_completion_tokens,
+    },
+    token::summarize_token,
+    usage::{UsageCollector, extract_usage},
+};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use reqwest::Client;
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+use uuid::Uuid;
+use wallet::{
+    api::CashuWalletApi,
+    models::{
+        ChatCompletionRequest, EmbeddingRequest, ImageGenerationRequest, OpenAIModelList,
+        OpenAIModelObject,
+    },
+};
+
+pub const PAYMENT_HEADER: &str = "X-PAYMENT-SATS";
+pub const CHANGE_HEADER: &str = "X-CHANGE-SATS";
+/// Server-sent event carrying the change and the signed charge of a streamed
+/// response. It follows the upstream's last event, so OpenAI clients that
+/// stop at `[DONE]` never see it.
+pub const CHANGE_EVENT: &str = "change";
+
+/// A request paid for up front.
+pub trait PaidRequest: Serialize {
+    /// Keeps the upstream from generating more than `max_tokens` tokens, or
+    /// returns false when that isn't enough for any. Requests that generate
+    /// no tokens have nothing to limit.
+    fn limit_completion(&mut self, _max_tokens: i64) -> bool {
+        true
+    }
+}
+
+impl PaidRequest for ChatCompletionRequest {
+    fn limit_completion(&mut self, max_tokens: i64) -> bool {
+        let choices = i64::from(self.n.unwrap_or(1).max(1));
+        let per_choice = u32::try_from(max_tokens / choices).unwrap_or(u32::MAX);
+        if per_choice == 0 {
+            return false;
+        }
+
+        // Newer models take `max_completion_tokens` and refuse `max_tokens`.
+        if let Some(limit) = self.extra.get_mut("max_completion_tokens") {
+            let requested = limit.as_u64().unwrap_or(u64::MAX);
+            *limit = json!(requested.min(u64::from(per_choice)));
+            self.max_tokens = self.max_tokens.map(|requested| requested.min(per_choice));
+        } else {
+            self.max_tokens = Some(
+                self.max_tokens
+                    .map_or(per_choice, |requested| requested.min(per_choice)),
+            );
+        }
+        true
+    }
+}
+
+impl PaidRequest for EmbeddingRequest {}
+
+impl PaidRequest for ImageGenerationRequest {}
+
+pub async fn provide_chat_completions<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    headers: HeaderMap,
+    Json(mut request): Json<ChatCompletionRequest>,
+) -> Response {
+    let is_streaming = request.stream.unwrap_or(false);
+    if is_streaming {
+        // Streams only report usage when asked to, and usage is what we charge.
+        request
+            .extra
+            .insert("stream_options".into(), json!({ "include_usage": true }));
+    }
+    let model = request.model.clone();
+
+    serve_paid_request(
+        &state,
+        headers,
+        "/v1/chat/completions",
+        &model,
+        request,
+        is_streaming,
+    )
+    .await
+}
+
+pub async fn provide_embeddings<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    headers: HeaderMap,
+    Json(request): Json<EmbeddingRequest>,
+) -> Response {
+    let model = request.model.clone();
+    serve_paid_request(&state, headers, "/v1/embeddings", &model, request, false).await
+}
+
+pub async fn provide_image_generations<W: CashuWalletApi>(
+    State(state): State<Arc<AppState<W>>>,
+    headers: HeaderMap,
+    Json(request): Json<ImageGenerationRequest>,
+) -> Response {
+    let model = request.model.clone();
+    serve_paid_request(
+        &state,
+        headers,
+        "/v1/images/generations",
+        &model,
+        request,
+        false,
+    )
+    .await
+}
+
+pub async fn provide_models<W: CashuWalletApi>(State(state): State<Arc<AppState<W>>>) -> Response {
+    serve_free_request(&state, "/v1/models").await
+}
+
+pub async fn provide_model<W: CashuWalletApi>(
+    Path(model_id): Path<String>,
+    State(state): State<Arc<AppState<W>>>,
+) -> Response {
+    serve_free_request(&state, &format!("/v1/models/{}", model_id)).await
+}
+
+/// Redeems the payment, calls the upstream and hands back whatever the
+/// upstream's `usage` didn't use up, along with the signed charge. Failed
+/// upstream calls are refunded in full.
+#[tracing::instrument(name = "provided_request", skip_all, fields(streaming = is_streaming))]
+async fn serve_paid_request<T: PaidRequest, W: CashuWalletApi>(
+    state: &Arc<AppState<W>>,
+    headers: HeaderMap,
+    path: &str,
+    model: &str,
+    mut body: T,
+    is_streaming: bool,
+) -> Response {
+    let started = Instant::now();
+    let Some(config) = get_server_config(&state.db, &state.cipher).await else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Server configuration missing. Cannot process request without a configured endpoint.",
+            "server_error",
+        );
+    };
+
+    let Some(token) = headers
+        .get(PAYMENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+    else {
+        return payment_required(&format!(
+            "Pay with a Cashu token in the {} header",
+            PAYMENT_HEADER
+        ));
+    };
+    let min_payment_sats = state.pricing.settings().min_payment_sats;
+    let offered_sats = match summarize_token(token).amount {
+        None => return payment_required("Payment is not a valid Cashu token"),
+        Some(amount) if (amount as i64) < min_payment_sats => {
+            return payment_required(&format!(
+                "Payments must be at least {} sat",
+                min_payment_sats
+            ));
+        }
+        Some(amount) => amount as i64,
+    };
+    let checked = match state
+        .mint_policy
+        .check_token(&state.db, &state.wallet, token)
+        .await
+    {
+        Ok(checked) => checked,
+        Err(violation) => return payment_required(&format!("Payment refused: {}", violation)),
+    };
+
+    let pricing = match state.pricing.price(&state.db, model).await {
+        Ok(pricing) => pricing,
+        Err(e) => {
+            tracing::error!("Failed to look up the price of {}: {}", model, e);
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Pricing is unavailable",
+                "server_error",
+            );
+        }
+    };
+
+    // The payment is the most a request may cost: the prompt has to fit in
+    // it and the completion is cut off where it runs out. Tokens are at
+    // least a byte, so the size of the request bounds its prompt.
+    let prompt_tokens = serde_json::to_vec(&body).map_or(0, |json| json.len() as i64);
+    let covered = affordable_completion_tokens(&pricing, prompt_tokens, offered_sats)
+        .is_some_and(|max_tokens| body.limit_completion(max_tokens));
+    if !covered {
+        return payment_required(&format!(
+            "A payment of {} sat does not cover this request",
+            offered_sats
+        ));
+    }
+
+    let request_id = match create_request(
+        &state.db,
+        &NewRequest {
+            endpoint: path.to_string(),
+            model: Some(model.to_string()),
+            provider: config.endpoint.clone(),
+            client_key: client_key(&headers),
+        },
+    )
+    .await
+    {
+        Ok(id) => Some(id),
+        Err(e) => {
+            tracing::error!("Failed to record request: {}", e);
+            None
+        }
+    };
+
+    let paid =
+        match observe_wallet_call("receive", state.wallet.receive(Some(token), None, None)).await {
+            Ok(received) => received.amount,
+            Err(e) => {
+                metrics::record_payment_failure("payment_receive");
+                let message = format!("Payment could not be redeemed: {}", e);
+                finish_request(
+                    &state.db,
+                    request_id,
+                    Some(StatusCode::PAYMENT_REQUIRED.as_u16() as i32),
+                    None,
+                    started,
+                    Some(&message),
+                )
+                .await;
+                return payment_required(&message);
+            }
+        };
+    metrics::record_sats_received(paid);
+    if let Err(e) = add_transaction(
+        &state.db,
+        &state.cipher,
+        token,
+        paid * MSAT_PER_SAT,
+        TransactionDirection::Incoming,
+        request_id,
+        checked.dleq,
+    )
+    .await
+    {
+        // The payment is in our wallet but not in the ledger, so it goes back
+        // in full rather than paying for a request we can't account for.
+        tracing::error!("Failed to book a payment of {} sat: {}", paid, e);
+        let message = "Payment could not be booked";
+        let charge = state.pricing.refund(model, &pricing, paid, request_id);
+        let change = settle(state, &charge).await;
+        finish_request(&state.db, request_id, None, None, started, Some(message)).await;
+
+        let mut response =
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, message, "server_error");
+        attach_charge(state, response.headers_mut(), &charge, change);
+        return response;
+    }
+
+    let span = upstream_span("POST", path);
+    let upstream_started = Instant::now();
+    let upstream = upstream_client(is_streaming)
+        .post(format!("{}{}", config.endpoint, path))
+        .bearer_auth(config.api_key.expose_secret())
+        .headers(trace_headers(&span))
+        .json(&body)
+        .send()
+        .instrument(span.clone())
+        .await;
+
+    let resp = match upstream {
+        Ok(resp) => resp,
+        Err(e) => {
+            metrics::record_upstream_error(&config.endpoint, "connection");
+            let message = format!("Error forwarding request: {}", e);
+            let charge = state.pricing.refund(model, &pricing, paid, request_id);
+            let change = settle(state, &charge).await;
+            finish_request(&state.db, request_id, None, None, started, Some(&message)).await;
+
+            let mut response = error_response(StatusCode::BAD_GATEWAY, &message, "gateway_error");
+            attach_charge(state, response.headers_mut(), &charge, change);
+            return response;
+        }
+    };
+
+    let status = resp.status();
+    span.record("http.response.status_code", status.as_u16());
+    metrics::record_upstream_response(
+        path,
+        Some(model),
+        &config.endpoint,
+        status.as_u16(),
+        upstream_started,
+    );
+    let upstream_headers = resp.headers().clone();
+
+    if !status.is_success() || !is_streaming {
+        let bytes = match resp.bytes().await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let message = format!("Error reading from upstream: {}", e);
+                let charge = state.pricing.refund(model, &pricing, paid, request_id);
+                let change = settle(state, &charge).await;
+                finish_request(
+                    &state.db,
+                    request_id,
+                    Some(status.as_u16() as i32),
+                    None,
+                    started,
+                    Some(&message),
+                )
+                .await;
+
+                let mut response =
+                    error_response(StatusCode::BAD_GATEWAY, &message, "gateway_error");
+                attach_charge(state, response.headers_mut(), &charge, change);
+                return response;
+            }
+        };
+
+        let usage = status
+            .is_success()
+            .then(|| extract_usage(&bytes, false))
+            .flatten();
+        let charge = if status.is_success() {
+            state
+                .pricing
+                .charge(model, &pricing, usage.as_ref(), paid, request_id)
+        } else {
+            state.pricing.refund(model, &pricing, paid, request_id)
+        };
+        let change = settle(state, &charge).await;
+        finish_request(
+            &state.db,
+            request_id,
+            Some(status.as_u16() as i32),
+            usage.as_ref(),
+            started,
+            None,
+        )
+        .await;
+
+        let mut response = Response::builder().status(status);
+        let response_headers = response.headers_mut().unwrap();
+        copy_headers(&upstream_headers, response_headers);
+        attach_charge(state, response_headers, &charge, change);
+        return response.body(Body::from(bytes)).unwrap();
+    }
+
+    let (tx, rx) = mpsc::channel::<Result<Vec<u8>, io::Error>>(100);
+    let mut stream = resp.bytes_stream();
+    let task_state = state.clone();
+    let model = model.to_string();
+
+    tokio::spawn(
+        async move {
+            let state = task_state;
+            let mut usage = UsageCollector::default();
+            let mut error = None;
+            while let Some(item) = stream.next().await {
+                match item {
+                    Ok(chunk) => {
+                        usage.push(&chunk);
+                        if tx.send(Ok(chunk.to_vec())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let message = format!("Error reading from upstream: {}", e);
+                        let _ = tx.send(Err(io::Error::other(message.clone()))).await;
+                        error = Some(message);
+                        break;
+                    }
+                }
+            }
+
+            let usage = usage.finish(true);
+            let charge = state
+                .pricing
+                .charge(&model, &pricing, usage.as_ref(), paid, request_id);
+            // Change the client can no longer receive would be lost, so it is
+            // only handed back while the stream is intact.
+            if error.is_none() && !tx.is_closed() {
+                let change = settle(&state, &charge).await;
+                let _ = tx.send(Ok(change_event(&state, &charge, change))).await;
+            } else {
+                record_request_cost(&state.db, request_id, paid * MSAT_PER_SAT).await;
+            }
+            finish_request(
+                &state.db,
+                request_id,
+                Some(status.as_u16() as i32),
+                usage.as_ref(),
+                started,
+                error.as_deref(),
+            )
+            .await;
+        }
+        .in_current_span(),
+    );
+
+    let mut response = Response::builder().status(status);
+    let response_headers = response.headers_mut().unwrap();
+    copy_headers(&upstream_headers, response_headers);
+    if !response_headers.contains_key(header::CONTENT_TYPE) {
+        response_headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/event-stream"),
+        );
+    }
+
+    let body = Body::from_stream(
+        ReceiverStream::new(rx).map(|result| result.map(axum::body::Bytes::from)),
+    );
+    response.body(body).unwrap()
+}
+
+/// Passes unpaid reads such as the model list through with our API key.
+async fn serve_free_request<W: CashuWalletApi>(state: &AppState<W>, path: &str) -> Response {
+    let Some(config) = get_server_config(&state.db, &state.cipher).await else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Server configuration missing. Cannot process request without a configured endpoint.",
+            "server_error",
+        );
+    };
+
+    let span = upstream_span("GET", path);
+    let upstream = Client::new()
+        .get(format!("{}{}", config.endpoint, path))
+        .bearer_auth(config.api_key.expose_secret())
+        .headers(trace_headers(&span))
+        .send()
+        .instrument(span)
+        .await;
+    let resp = match upstream {
+        Ok(resp) => resp,
+        Err(e) => {
+            metrics::record_upstream_error(&config.endpoint, "connection");
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                &format!("Error forwarding request: {}", e),
+                "gateway_error",
+            );
+        }
+    };
+
+    let status = resp.status();
+    let upstream_headers = resp.headers().clone();
+    match resp.bytes().await {
+        Ok(bytes) => {
+            let priced = match status.is_success() {
+                true => with_prices(state, &bytes).await,
+                false => None,
+            };
+            let mut response = Response::builder().status(status);
+            copy_headers(&upstream_headers, response.headers_mut().unwrap());
+            match priced {
+                Some(priced) => response.body(Body::from(priced)).unwrap(),
+                None => response.body(Body::from(bytes)).unwrap(),
+            }
+        }
+        Err(e) => error_response(
+            StatusCode::BAD_GATEWAY,
+            &format!("Error reading from upstream: {}", e),
+            "gateway_error",
+        ),
+    }
+}
+
+/// Adds our rates to a model list or model object from the upstream.
+async fn with_prices<W: CashuWalletApi>(state: &AppState<W>, body: &[u8]) -> Option<Vec<u8>> {
+    let prices = state
+        .pricing
+        .price_list(&state.db)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to read model prices: {}", e))
+        .ok()?;
+    let price = |model: &str| {
+        prices
+            .get(model)
+            .cloned()
+            .unwrap_or_else(|| state.pricing.settings().default_price.clone())
+    };
+
+    if let Ok(mut list) = serde_json::from_slice::<OpenAIModelList>(body) {
+        for model in &mut list.data {
+            model.pricing = Some(price(&model.id));
+        }
+        return serde_json::to_vec(&list).ok();
+    }
+    let mut model = serde_json::from_slice::<OpenAIModelObject>(body).ok()?;
+    model.pricing = Some(price(&model.id));
+    serde_json::to_vec(&model).ok()
+}
+
+/// Hands back the change of `charge` and books what was kept. Change that
+/// couldn't be minted stays with us.
+async fn settle<W: CashuWalletApi>(state: &AppState<W>, charge: &Charge) -> Option<String> {
+    let change = issue_change(state, charge.change_sats, charge.request_id).await;
+    let kept_sats = match change {
+        Some(_) => charge.charged_sats,
+        None => charge.paid_sats,
+    };
+    record_request_cost(&state.db, charge.request_id, kept_sats * MSAT_PER_SAT).await;
+    change
+}
+
+/// Mints `sats` of change from our wallet and books it as outgoing.
+async fn issue_change<W: CashuWalletApi>(
+    state: &AppState<W>,
+    sats: i64,
+    request_id: Option<Uuid>,
+) -> Option<String> {
+    if sats <= 0 {
+        return None;
+    }
+
+    let sent = observe_wallet_call(
+        "send",
+        state.wallet.send(sats, None, None, None, None, None),
+    )
+    .await
+    .inspect_err(|e| {
+        metrics::record_payment_failure("change_send");
+        tracing::error!("Failed to create {} sat of change: {}", sats, e);
+    })
+    .ok()?;
+
+    metrics::record_sats_spent(sats);
+    // The change has left the wallet, so it is handed back even when it
+    // can't be booked.
+    if let Err(e) = add_transaction(
+        &state.db,
+        &state.cipher,
+        &sent.token,
+        sats * MSAT_PER_SAT,
+        TransactionDirection::Outgoing,
+        request_id,
+        None,
+    )
+    .await
+    {
+        tracing::error!("Failed to book {} sat of change: {}", sats, e);
+    }
+    Some(sent.token)
+}
+
+fn attach_charge<W: CashuWalletApi>(
+    state: &AppState<W>,
+    headers: &mut HeaderMap,
+    charge: &Charge,
+    change: Option<String>,
+) {
+    if let Some(value) = change.and_then(|token| HeaderValue::from_str(&token).ok()) {
+        headers.insert(CHANGE_HEADER, value);
+    }
+    let Some(signed) = state.pricing.sign(charge) else {
+        return;
+    };
+    for (name, value) in [
+        (CHARGE_HEADER, Some(signed.encoded)),
+        (CHARGE_SIGNATURE_HEADER, Some(signed.signature)),
+        (
+            CHARGE_PUBKEY_HEADER,
+            state.pricing.public_key().map(|key| key.to_hex()),
+        ),
+    ] {
+        if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// The closing event of a stream: its change, if any, and the signed charge.
+fn change_event<W: CashuWalletApi>(
+    state: &AppState<W>,
+    charge: &Charge,
+    change: Option<String>,
+) -> Vec<u8> {
+    let signed = state.pricing.sign(charge);
+    let data = json!({
+        "token": change,
+        "amount": if change.is_some() { charge.change_sats } else { 0 },
+        "charge": signed.as_ref().map(|signed| &signed.encoded),
+        "signature": signed.as_ref().map(|signed| &signed.signature),
+        "pubkey": state.pricing.public_key().map(|key| key.to_hex()),
+    });
+    format!("event: {}\ndata: {}\n\n", CHANGE_EVENT, data).into_bytes()
+}
+
+/// Reads the token of the change event from a streamed response body.
+pub fn change_from_stream(body: &[u8]) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+
+    let mut in_change = false;
+    for line in body.lines().map(str::trim) {
+        if line.is_empty() {
+            in_change = false;
+        } else if let Some(event) = line.strip_prefix("event:") {
+            in_change = event.trim() == CHANGE_EVENT;
+        } else if in_change && let Some(data) = line.strip_prefix("data:") {
+            let value: Value = serde_json::from_str(data.trim()).ok()?;
+            return value["token"].as_str().map(String::from);
+        }
+    }
+    None
+}
+
+/// Relays a provider's stream event by event, holding back its change event:
+/// the token in it is ours to redeem, not the downstream client's.
+#[derive(Default)]
+pub struct ChangeEventFilter {
+    pending: Vec<u8>,
+}
+
+impl ChangeEventFilter {
+    /// The complete events received so far, minus the change event.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        let mut relayed = Vec::new();
+        while let Some(end) = event_end(&self.pending) {
+            let event: Vec<u8> = self.pending.drain(..end).collect();
+            if !is_change_event(&event) {
+                relayed.extend(event);
+            }
+        }
+        relayed
+    }
+
+    /// Whatever followed the last complete event once the stream ended.
+    pub fn finish(self) -> Vec<u8> {
+        if is_change_event(&self.pending) {
+            Vec::new()
+        } else {
+            self.pending
+        }
+    }
+}
+
+/// The end of the first event in `buffer`, past the blank line closing it.
+fn event_end(buffer: &[u8]) -> Option<usize> {
+    let lf = buffer
+        .windows(2)
+        .position(|window| window == b"\n\n")
+        .map(|start| start + 2);
+    let crlf = buffer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|start| start + 4);
+    lf.into_iter().chain(crlf).min()
+}
+
+fn is_change_event(event: &[u8]) -> bool {
+    std::str::from_utf8(event).is_ok_and(|event| {
+        event.lines().any(|line| {
+            line.trim()
+                .strip_prefix("event:")
+                .is_some_and(|name| name.trim() == CHANGE_EVENT)
+        })
+    })
+}
+
+fn upstream_client(is_streaming: bool) -> Client {
+    let mut builder = Client::builder();
+    if is_streaming {
+        builder = builder
+            .timeout(Duration::from_secs(300))
+            .pool_idle_timeout(None)
+            .pool_max_idle_per_host(0);
+    }
+    builder.build().unwrap()
+}
+
+fn copy_headers(from: &HeaderMap, to: &mut HeaderMap) {
+    for (name, value) in from.iter() {
+        if name != header::CONNECTION
+            && name != header::TRANSFER_ENCODING
+            && name != header::CONTENT_LENGTH
+        {
+            to.insert(name, value.clone());
+        }
+    }
+}
+
+fn payment_required(message: &str) -> Response {
+    error_response(StatusCode::PAYMENT_REQUIRED, message, "payment_error")
+}
+
+fn error_response(status: StatusCode, message: &str, kind: &str) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {
+                "message": message,
+                "type": kind,
+            }
+        })),
+    )
+        .into_response()
+}
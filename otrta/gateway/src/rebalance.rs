@@ -0,0 +1,267 @@
+use crate::{
+    connection::RebalanceSettings,
+    metrics::{self, observe_wallet_call},
+    mint_policy::MintPolicy,
+    mints::{MintSelectionError, mint_balances, normalize_mint_url, select_mint},
+    models::AppState,
+};
+use sqlx::PgPool;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::Mutex, task::JoinHandle};
+use wallet::api::CashuWalletApi;
+
+/// A Lightning transfer of `amount` sats from one mint to another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Swap {
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+}
+
+pub struct Rebalancer {
+    settings: RebalanceSettings,
+    /// `settings.targets` keyed by normalized mint URL.
+    targets: HashMap<String, i64>,
+    /// Swaps run one at a time, so that two payments short of funds don't
+    /// both move them.
+    running: Mutex<()>,
+}
+
+impl Rebalancer {
+    pub fn new(settings: RebalanceSettings) -> Self {
+        let targets = settings
+            .targets
+            .iter()
+            .map(|(mint, sats)| {
+                let mint = normalize_mint_url(mint).unwrap_or_else(|_| mint.clone());
+                (mint, *sats)
+            })
+            .collect();
+
+        Self {
+            settings,
+            targets,
+            running: Mutex::new(()),
+        }
+    }
+
+    pub fn settings(&self) -> &RebalanceSettings {
+        &self.settings
+    }
+
+    /// Most a swap of `amount` sats may cost in fees.
+    pub fn fee_allowance(&self, amount: i64) -> i64 {
+        let percent = (amount as f64 * self.settings.max_fee_percent / 100.0).ceil() as i64;
+        self.settings.max_fee_base_sats + percent
+    }
+
+    /// Largest amount that can leave `mint` when it holds `balance`, with
+    /// room for the fees and without dipping into its reserve or target.
+    fn movable(&self, mint: &str, balance: i64) -> i64 {
+        let floor = self
+            .settings
+            .min_reserve_sats
+            .max(self.targets.get(mint).copied().unwrap_or(0));
+        let available = balance - floor;
+
+        let spare = available - self.settings.max_fee_base_sats;
+        let mut amount =
+            (spare as f64 / (1.0 + self.settings.max_fee_percent / 100.0)).floor() as i64;
+        // Rounding the percentage up may leave the estimate a sat too high.
+        while amount > 0 && amount + self.fee_allowance(amount) > available {
+            amount -= 1;
+        }
+        amount.max(0)
+    }
+
+    /// Moves up to `amount` sats into `to` from the mint that can spare the
+    /// most. `None` when no mint can spare anything.
+    pub fn plan(&self, balances: &HashMap<String, i64>, to: &str, amount: i64) -> Option<Swap> {
+        balances
+            .iter()
+            .filter(|(mint, _)| mint.as_str() != to)
+            .map(|(mint, balance)| (mint, self.movable(mint, *balance)))
+            .filter(|(_, movable)| *movable > 0)
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(from, movable)| Swap {
+                from: from.clone(),
+                to: to.to_string(),
+                amount: amount.min(movable),
+            })
+    }
+
+    /// Swaps into one of the `accepted` mints so that it can pay `sats`,
+    /// and returns it. The accepted mints holding the most are tried first,
+    /// skipping those `policy` doesn't trust or that would go over their
+    /// limit.
+    pub async fn cover<W: CashuWalletApi>(
+        &self,
+        wallet: &W,
+        policy: &MintPolicy,
+        pool: &PgPool,
+        accepted: &[String],
+        sats: i64,
+    ) -> anyhow::Result<String> {
+        let _running = self.running.lock().await;
+
+        // Another payment may have swapped while we were waiting.
+        let balances = mint_balances(&observe_wallet_call("balance", wallet.balance()).await?);
+        if let Ok(mint) = select_mint(&balances, accepted, sats) {
+            return Ok(mint);
+        }
+
+        let mut accepted: Vec<(String, i64)> = accepted
+            .iter()
+            .filter_map(|mint| normalize_mint_url(mint).ok())
+            .map(|mint| {
+                let held = balances.get(&mint).copied().unwrap_or(0);
+                (mint, held)
+            })
+            .collect();
+        accepted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        for (mint, held) in accepted {
+            let shortfall = sats - held;
+            if room(policy, pool, &mint, held).await? < shortfall {
+                tracing::debug!("Not swapping into {}: the mint policy won't take it", mint);
+                continue;
+            }
+            let Some(swap) = self
+                .plan(&balances, &mint, shortfall)
+                .filter(|swap| swap.amount == shortfall)
+            else {
+                continue;
+            };
+            self.swap(wallet, &swap).await?;
+            return Ok(mint);
+        }
+
+        Err(MintSelectionError::NoAcceptedMint(sats).into())
+    }
+
+    /// Tops up every mint below its target from the mints with funds to
+    /// spare, as far as `policy` lets it hold them. Returns the swaps that
+    /// went through.
+    pub async fn rebalance<W: CashuWalletApi>(
+        &self,
+        wallet: &W,
+        policy: &MintPolicy,
+        pool: &PgPool,
+    ) -> anyhow::Result<Vec<Swap>> {
+        let _running = self.running.lock().await;
+
+        let mut targets: Vec<_> = self.targets.iter().collect();
+        targets.sort();
+
+        let mut swaps = Vec::new();
+        for (mint, target) in targets {
+            let balances = mint_balances(&observe_wallet_call("balance", wallet.balance()).await?);
+            let held = balances.get(mint).copied().unwrap_or(0);
+            let deficit = match room(policy, pool, mint, held).await {
+                Ok(room) => (target - held).min(room),
+                Err(e) => {
+                    tracing::warn!("Failed to check the policy of {}: {}", mint, e);
+                    continue;
+                }
+            };
+            if deficit <= 0 {
+                continue;
+            }
+            let Some(swap) = self.plan(&balances, mint, deficit) else {
+                tracing::debug!("No mint can spare sats to top up {}", mint);
+                continue;
+            };
+
+            match self.swap(wallet, &swap).await {
+                Ok(()) => swaps.push(swap),
+                Err(e) => tracing::warn!("Failed to top up {}: {}", mint, e),
+            }
+        }
+        Ok(swaps)
+    }
+
+    async fn swap<W: CashuWalletApi>(&self, wallet: &W, swap: &Swap) -> anyhow::Result<()> {
+        let before = observe_wallet_call("balance", wallet.balance())
+            .await?
+            .balance;
+        let swapped = observe_wallet_call(
+            "swap",
+            wallet.swap(
+                swap.amount,
+                &swap.from,
+                &swap.to,
+                (!self.settings.uncapped_fees).then(|| self.fee_allowance(swap.amount)),
+            ),
+        )
+        .await;
+        if let Err(e) = swapped {
+            metrics::record_swap("error", 0);
+            return Err(e);
+        }
+
+        // Wallets don't report the Lightning fee, it is what went missing.
+        let fee = before
+            - observe_wallet_call("balance", wallet.balance())
+                .await?
+                .balance;
+        metrics::record_swap("ok", fee);
+        if fee > self.fee_allowance(swap.amount) {
+            tracing::warn!(
+                "Swap of {} sat from {} to {} cost {} sat in fees, over the limit of {}",
+                swap.amount,
+                swap.from,
+                swap.to,
+                fee,
+                self.fee_allowance(swap.amount)
+            );
+        } else {
+            tracing::info!(
+                "Swapped {} sat from {} to {} for {} sat in fees",
+                swap.amount,
+                swap.from,
+                swap.to,
+                fee
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Sats `mint` may still take in under `policy` when it holds `held`: none
+/// when it isn't trusted, and no more than its balance limit.
+async fn room(
+    policy: &MintPolicy,
+    pool: &PgPool,
+    mint: &str,
+    held: i64,
+) -> Result<i64, sqlx::Error> {
+    let trust = policy.evaluate(pool, mint).await?;
+    Ok(match (trust.trusted, trust.max_balance_sats) {
+        (false, _) => 0,
+        (true, Some(limit)) => limit - held,
+        (true, None) => i64::MAX,
+    })
+}
+
+/// Runs the rebalancer every `interval_secs`. Nothing is spawned when no
+/// targets are configured.
+pub fn spawn_rebalancer<W: CashuWalletApi>(state: Arc<AppState<W>>) -> Option<JoinHandle<()>> {
+    if state.rebalancer.targets.is_empty() {
+        return None;
+    }
+    let period = Duration::from_secs(state.rebalancer.settings.interval_secs.max(1));
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let rebalanced = state
+                .rebalancer
+                .rebalance(&state.wallet, &state.mint_policy, &state.db)
+                .await;
+            if let Err(e) = rebalanced {
+                tracing::warn!("Rebalancing failed: {}", e);
+            }
+        }
+    }))
+}
@@ -0,0 +1,80 @@
+use crate::connection::TelemetrySettings;
+use opentelemetry::{global, propagation::Injector, trace::TracerProvider};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+const DEFAULT_FILTER: &str = "wallet_gateway=debug,gateway=info,wallet=info,tower_http=debug";
+
+/// Sets up the global subscriber. Spans are always logged to stdout and are
+/// additionally exported over OTLP/HTTP when an endpoint is configured. The
+/// returned provider must be shut down on exit so buffered spans get flushed.
+pub fn init_tracing(settings: &TelemetrySettings) -> Option<SdkTracerProvider> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let provider = settings.otlp_endpoint.as_ref().map(|endpoint| {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()
+            .expect("Failed to build OTLP span exporter.");
+
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(settings.service_name.clone())
+                    .build(),
+            )
+            .build()
+    });
+
+    let otel_layer = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("gateway")));
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_FILTER.into()),
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        tracing::info!("Exporting traces to {}", endpoint);
+    }
+
+    provider
+}
+
+pub fn shutdown_tracing(provider: Option<SdkTracerProvider>) {
+    if let Some(Err(e)) = provider.map(|provider| provider.shutdown()) {
+        eprintln!("Failed to flush traces: {}", e);
+    }
+}
+
+/// Writes the W3C `traceparent` (and `tracestate`) of `span` into `headers`
+/// so the upstream provider can join the trace.
+pub fn inject_trace_context(span: &Span, headers: &mut HeaderMap) {
+    let context = span.context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use cdk::nuts::Token;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// What the ledger keeps about a token instead of the spendable token itself.
+#[derive(Clone, Debug)]
+pub struct TokenSummary {
+    pub fingerprint: String,
+    pub mint_url: Option<String>,
+    pub keyset_ids: Vec<String>,
+    pub unit: Option<String>,
+    pub amount: Option<u64>,
+}
+
+/// Summarises a Cashu token for storage. The fingerprint is the SHA-256 of the
+/// sorted proof Y values, so it identifies the proofs without being spendable.
+/// Strings that don't parse as a token are fingerprinted as-is.
+pub fn summarize_token(token: &str) -> TokenSummary {
+    let parsed = match Token::from_str(token.trim()) {
+        Ok(parsed) => parsed,
+        Err(_) => {
+            return TokenSummary {
+                fingerprint: hex::encode(Sha256::digest(token.as_bytes())),
+                mint_url: None,
+                keyset_ids: Vec::new(),
+                unit: None,
+                amount: None,
+            };
+        }
+    };
+
+    let proofs = parsed.proofs();
+
+    let mut ys: Vec<[u8; 33]> = proofs
+        .iter()
+        .filter_map(|proof| proof.y().ok())
+        .map(|y| y.to_bytes())
+        .collect();
+    ys.sort();
+    let mut hasher = Sha256::new();
+    for y in &ys {
+        hasher.update(y);
+    }
+
+    let mut keyset_ids: Vec<String> = proofs.iter().map(|p| p.keyset_id.to_string()).collect();
+    keyset_ids.sort();
+    keyset_ids.dedup();
+
+    TokenSummary {
+        fingerprint: hex::encode(hasher.finalize()),
+        mint_url: parsed.mint_url().ok().map(|url| url.to_string()),
+        keyset_ids,
+        unit: parsed.unit().map(|unit| unit.to_string()),
+        amount: parsed.value().ok().map(u64::from),
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    db::topup::{Topup, TopupStatus, add_topup, close_topup, complete_topup, get_pending_topups},
+    metrics::{self, observe_wallet_call},
+    models::AppState,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use wallet::api::{CashuWalletApi, PaymentResult};
+
+/// Asks `mint` (the wallet's default mint when `None`) for an invoice of
+/// `amount_sats`, stores the top-up and watches it until it is paid.
+pub async fn start_topup<W: CashuWalletApi>(
+    state: Arc<AppState<W>>,
+    amount_sats: i64,
+    mint: Option<&str>,
+) -> anyhow::Result<Topup> {
+    let invoice = observe_wallet_call(
+        "create_invoice",
+        state.wallet.create_invoice(amount_sats, mint),
+    )
+    .await?;
+    let payment_request = match invoice.payment_request {
+        Some(payment_request) if invoice.ok => payment_request,
+        _ => {
+            return Err(anyhow!(
+                "Mint did not issue an invoice: {}",
+                invoice.error_message.unwrap_or_default()
+            ));
+        }
+    };
+
+    let expires_at = Utc::now() + chrono::Duration::seconds(state.topup.expiry_secs);
+    let topup = add_topup(
+        &state.db,
+        amount_sats,
+        mint,
+        invoice.checking_id.as_deref(),
+        &payment_request,
+        expires_at,
+    )
+    .await?;
+
+    watch_topup(state, topup.clone());
+    Ok(topup)
+}
+
+/// Polls the invoice of `topup` until the mint reports it paid, which mints
+/// the ecash, or until it expires.
+pub fn watch_topup<W: CashuWalletApi>(state: Arc<AppState<W>>, topup: Topup) -> JoinHandle<()> {
+    let period = Duration::from_millis(state.topup.poll_interval_ms.max(1));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+
+            let paid = observe_wallet_call(
+                "invoice_state",
+                state
+                    .wallet
+                    .invoice_state(Some(&topup.payment_request), topup.mint_url.as_deref()),
+            )
+            .await;
+            let outcome = match paid {
+                Ok(status) => match status.result {
+                    PaymentResult::Success => Some(Ok(())),
+                    PaymentResult::Failed => Some(Err(status
+                        .error_message
+                        .unwrap_or_else(|| "Invoice failed".to_string()))),
+                    PaymentResult::Pending | PaymentResult::Unknown => None,
+                },
+                // The mint may be briefly unreachable, keep asking.
+                Err(e) => {
+                    tracing::debug!("Failed to check top-up {}: {}", topup.id, e);
+                    None
+                }
+            };
+
+            match outcome {
+                Some(Ok(())) => return finish(&state, &topup).await,
+                Some(Err(error)) => {
+                    return close(&state, &topup, TopupStatus::Failed, Some(&error)).await;
+                }
+                None if Utc::now() >= topup.expires_at => {
+                    return close(&state, &topup, TopupStatus::Expired, None).await;
+                }
+                None => {}
+            }
+        }
+    })
+}
+
+/// Watches the top-ups still pending from a previous run.
+pub async fn resume_topups<W: CashuWalletApi>(
+    state: Arc<AppState<W>>,
+) -> Result<usize, sqlx::Error> {
+    let pending = get_pending_topups(&state.db).await?;
+    let count = pending.len();
+    for topup in pending {
+        watch_topup(state.clone(), topup);
+    }
+    Ok(count)
+}
+
+async fn finish<W: CashuWalletApi>(state: &AppState<W>, topup: &Topup) {
+    match complete_topup(&state.db, topup.id).await {
+        Ok(Some(_)) => {
+            metrics::record_sats_received(topup.amount_sats);
+            tracing::info!("Top-up {} paid, minted {} sat", topup.id, topup.amount_sats);
+        }
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to book top-up {}: {}", topup.id, e),
+    }
+}
+
+async fn close<W: CashuWalletApi>(
+    state: &AppState<W>,
+    topup: &Topup,
+    status: TopupStatus,
+    error: Option<&str>,
+) {
+    tracing::info!("Top-up {} ended as {:?}", topup.id, status);
+    if let Err(e) = close_topup(&state.db, topup.id, status, error).await {
+        tracing::error!("Failed to close top-up {}: {}", topup.id, e);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{
+    db::transfer::{
+        NewTransfer, Transfer, TransferStatus, add_transfer, book_reclaimed_transfer, get_transfer,
+        get_transfer_token, set_transfer_status,
+    },
+    error::AppError,
+    metrics::observe_wallet_call,
+    mints::mint_balances,
+    models::AppState,
+};
+use cdk::nuts::PublicKey;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use std::str::FromStr;
+use uuid::Uuid;
+use wallet::api::CashuWalletApi;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("{0} is not a valid public key")]
+    InvalidPubkey(String),
+    #[error("{0} is not a valid npub")]
+    InvalidNpub(String),
+    #[error("Sending needs {needed} sat, only {held} sat are available")]
+    InsufficientFunds { needed: i64, held: i64 },
+    #[error("Failed to send: {0}")]
+    Send(String),
+    #[error("Transfer not found")]
+    NotFound,
+    #[error("Transfer is already {0:?}")]
+    NotPending(TransferStatus),
+    #[error("The token is locked to the recipient's key and can't be reclaimed")]
+    Locked,
+    #[error("The token is locked to the recipient's key until {0}")]
+    LockedUntil(DateTime<Utc>),
+    #[error("Failed to reclaim the token: {0}")]
+    Reclaim(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to read the wallet balance: {0}")]
+    Wallet(String),
+}
+
+impl From<TransferError> for AppError {
+    fn from(error: TransferError) -> Self {
+        match error {
+            TransferError::NotFound => AppError::NotFound,
+            TransferError::Database(_) | TransferError::Wallet(_) => {
+                tracing::error!("Transfer failed: {}", error);
+                AppError::InternalServerError
+            }
+            error => AppError::ValidationError(error.to_string()),
+        }
+    }
+}
+
+/// A transfer as it is sent, the only time its token is handed out.
+#[derive(Debug, Serialize)]
+pub struct SentTransfer {
+    #[serde(flatten)]
+    pub transfer: Transfer,
+    pub token: String,
+}
+
+/// Creates a token of `amount` sats from `mint`, or the mint the wallet
+/// picks, optionally locked to `lock` (NUT-11) and sent to `npub` as a Nostr
+/// DM, and books it as an outgoing transfer. Locked tokens can be reclaimed
+/// once `lock_secs` passed.
+pub async fn send_ecash<W: CashuWalletApi>(
+    state: &AppState<W>,
+    amount: i64,
+    mint: Option<&str>,
+    lock: Option<&str>,
+    npub: Option<&str>,
+) -> Result<SentTransfer, TransferError> {
+    let lock = lock
+        .map(|key| {
+            PublicKey::from_str(key.trim_start_matches("P2PK:"))
+                .map(|key| key.to_hex())
+                .map_err(|_| TransferError::InvalidPubkey(key.to_string()))
+        })
+        .transpose()?;
+    if let Some(npub) = npub
+        && !is_npub(npub)
+    {
+        return Err(TransferError::InvalidNpub(npub.to_string()));
+    }
+
+    let balance = observe_wallet_call("balance", state.wallet.balance())
+        .await
+        .map_err(|e| TransferError::Wallet(e.to_string()))?;
+    let held = match mint {
+        Some(mint) => mint_balances(&balance).get(mint).copied().unwrap_or(0),
+        None => balance.balance,
+    };
+    if held < amount {
+        return Err(TransferError::InsufficientFunds {
+            needed: amount,
+            held,
+        });
+    }
+
+    let locked_until = lock
+        .is_some()
+        .then(|| Utc::now() + chrono::Duration::seconds(state.transfer.lock_secs));
+    let sent = observe_wallet_call(
+        "send",
+        state.wallet.send(
+            amount,
+            npub,
+            lock.as_deref(),
+            locked_until.map(|until| until.timestamp()),
+            mint,
+            None,
+        ),
+    )
+    .await
+    .map_err(|e| TransferError::Send(e.to_string()))?;
+
+    let transfer = add_transfer(
+        &state.db,
+        &state.cipher,
+        &NewTransfer {
+            token: sent.token.clone(),
+            amount_sats: amount,
+            lock_pubkey: lock,
+            locked_until,
+            npub: sent.npub.or_else(|| npub.map(str::to_string)),
+        },
+    )
+    .await?;
+    tracing::info!("Sent {} sat as transfer {}", amount, transfer.id);
+
+    Ok(SentTransfer {
+        transfer,
+        token: sent.token,
+    })
+}
+
+/// The transfer, marked claimed once the mint reports its token spent.
+/// Wallets that can't check tokens leave it as it is.
+pub async fn refresh_transfer<W: CashuWalletApi>(
+    state: &AppState<W>,
+    id: Uuid,
+) -> Result<Transfer, TransferError> {
+    let transfer = get_transfer(&state.db, id)
+        .await?
+        .ok_or(TransferError::NotFound)?;
+    if transfer.status != TransferStatus::Pending {
+        return Ok(transfer);
+    }
+
+    if !is_claimed(state, id).await? {
+        return Ok(transfer);
+    }
+    Ok(set_transfer_status(
+        &state.db,
+        id,
+        TransferStatus::Pending,
+        TransferStatus::Claimed,
+    )
+    .await?
+    .unwrap_or(transfer))
+}
+
+/// Takes the token of a transfer nobody claimed back into the wallet and
+/// books it as incoming.
+pub async fn reclaim_transfer<W: CashuWalletApi>(
+    state: &AppState<W>,
+    id: Uuid,
+) -> Result<Transfer, TransferError> {
+    let transfer = get_transfer(&state.db, id)
+        .await?
+        .ok_or(TransferError::NotFound)?;
+    if transfer.lock_pubkey.is_some() {
+        match transfer.locked_until {
+            Some(until) if until <= Utc::now() => {}
+            Some(until) => return Err(TransferError::LockedUntil(until)),
+            None => return Err(TransferError::Locked),
+        }
+    }
+    // Flipped first so the token is redeemed once.
+    let Some(transfer) = set_transfer_status(
+        &state.db,
+        id,
+        TransferStatus::Pending,
+        TransferStatus::Reclaimed,
+    )
+    .await?
+    else {
+        return Err(TransferError::NotPending(transfer.status));
+    };
+
+    let token = get_transfer_token(&state.db, &state.cipher, id)
+        .await?
+        .ok_or(TransferError::NotFound)?;
+    let received = observe_wallet_call(
+        "receive",
+        state
+            .wallet
+            .receive(Some(token.expose_secret()), None, None),
+    )
+    .await;
+
+    match received {
+        Ok(_) => {
+            book_reclaimed_transfer(&state.db, id).await?;
+            tracing::info!("Reclaimed {} sat of transfer {}", transfer.amount_sats, id);
+            Ok(transfer)
+        }
+        Err(e) => {
+            // The recipient may have claimed it in the meantime.
+            if is_claimed(state, id).await.unwrap_or(false) {
+                set_transfer_status(
+                    &state.db,
+                    id,
+                    TransferStatus::Reclaimed,
+                    TransferStatus::Claimed,
+                )
+                .await?;
+                return Err(TransferError::NotPending(TransferStatus::Claimed));
+            }
+            set_transfer_status(
+                &state.db,
+                id,
+                TransferStatus::Reclaimed,
+                TransferStatus::Pending,
+            )
+            .await?;
+            Err(TransferError::Reclaim(e.to_string()))
+        }
+    }
+}
+
+async fn is_claimed<W: CashuWalletApi>(
+    state: &AppState<W>,
+    id: Uuid,
+) -> Result<bool, TransferError> {
+    let token = get_transfer_token(&state.db, &state.cipher, id)
+        .await?
+        .ok_or(TransferError::NotFound)?;
+    match observe_wallet_call(
+        "token_state",
+        state.wallet.token_state(token.expose_secret()),
+    )
+    .await
+    {
+        Ok(token_state) => Ok(token_state.spent > 0 && token_state.unspent == 0),
+        Err(e) => {
+            tracing::debug!("Failed to check transfer {}: {}", id, e);
+            Ok(false)
+        }
+    }
+}
+
+fn is_npub(npub: &str) -> bool {
+    bech32::decode(npub)
+        .is_ok_and(|(hrp, data)| hrp.as_str().eq_ignore_ascii_case("npub") && data.len() == 32)
+}
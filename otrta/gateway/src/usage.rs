@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Largest upstream body kept around to read the `usage` block from.
+pub const MAX_USAGE_BUFFER: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: Option<i64>,
+    #[serde(default)]
+    pub completion_tokens: Option<i64>,
+    #[serde(default)]
+    pub total_tokens: Option<i64>,
+}
+
+/// Collects an upstream response body while it is streamed to the client so
+/// the token usage can be read once the body is complete.
+#[derive(Default)]
+pub struct UsageCollector {
+    buffer: Vec<u8>,
+    overflowed: bool,
+}
+
+impl UsageCollector {
+    pub fn push(&mut self, chunk: &[u8]) {
+        if self.overflowed {
+            return;
+        }
+        if self.buffer.len() + chunk.len() > MAX_USAGE_BUFFER {
+            self.overflowed = true;
+            self.buffer = Vec::new();
+            return;
+        }
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// The body collected so far, or `None` once it grew past the limit.
+    pub fn body(&self) -> Option<&[u8]> {
+        (!self.overflowed).then_some(self.buffer.as_slice())
+    }
+
+    pub fn finish(self, is_streaming: bool) -> Option<Usage> {
+        if self.overflowed {
+            return None;
+        }
+        extract_usage(&self.buffer, is_streaming)
+    }
+}
+
+/// Reads the OpenAI `usage` block from a response body. For server-sent event
+/// streams the last `data:` event carrying usage wins.
+pub fn extract_usage(body: &[u8], is_streaming: bool) -> Option<Usage> {
+    let body = std::str::from_utf8(body).ok()?;
+
+    if is_streaming {
+        return body
+            .lines()
+            .rev()
+            .filter_map(|line| line.trim().strip_prefix("data:"))
+            .map(str::trim)
+            .filter(|data| *data != "[DONE]")
+            .find_map(usage_from_json);
+    }
+
+    usage_from_json(body)
+}
+
+fn usage_from_json(json: &str) -> Option<Usage> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    let usage = value.get("usage")?;
+    if usage.is_null() {
+        return None;
+    }
+    serde_json::from_value(usage.clone()).ok()
+}
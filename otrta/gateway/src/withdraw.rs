@@ -0,0 +1,318 @@
+use crate::{
+    db::withdrawal::{
+        NewWithdrawal, Withdrawal, WithdrawalStatus, add_withdrawal, begin_withdrawal,
+        complete_withdrawal, fail_withdrawal, get_pending_withdrawals, get_withdrawal,
+        set_withdrawal_checking_id,
+    },
+    error::AppError,
+    lnurl::{LnurlClient, LnurlError},
+    metrics::observe_wallet_call,
+    mints::{mint_balances, normalize_mint_url},
+    models::AppState,
+};
+use chrono::{DateTime, Utc};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use wallet::api::{Amount, CashuWalletApi, PaymentResult, Unit};
+
+/// How long a quote is kept when the mint doesn't say.
+const DEFAULT_QUOTE_SECS: i64 = 600;
+/// Failed checks in a row after which a pending withdrawal is left alone
+/// until the gateway starts again.
+const MAX_FAILED_CHECKS: u32 = 8;
+/// Checks of a failing mint are at most 2^this poll intervals apart.
+const MAX_BACKOFF_EXPONENT: u32 = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WithdrawError {
+    #[error("Failed to get a quote: {0}")]
+    Quote(String),
+    #[error("Paying needs {needed} sat including the fee reserve, only {held} sat are available")]
+    InsufficientFunds { needed: i64, held: i64 },
+    #[error("Withdrawal not found")]
+    NotFound,
+    #[error("The quote has expired, ask for a new one")]
+    Expired,
+    #[error("Withdrawal is already {0:?}")]
+    NotQuoted(WithdrawalStatus),
+    #[error(transparent)]
+    Lnurl(#[from] LnurlError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to read the wallet balance: {0}")]
+    Wallet(String),
+}
+
+impl From<WithdrawError> for AppError {
+    fn from(error: WithdrawError) -> Self {
+        match error {
+            WithdrawError::NotFound => AppError::NotFound,
+            WithdrawError::Database(_) | WithdrawError::Wallet(_) => {
+                tracing::error!("Withdrawal failed: {}", error);
+                AppError::InternalServerError
+            }
+            error => AppError::ValidationError(error.to_string()),
+        }
+    }
+}
+
+/// Gets a melt quote for `bolt11` from `mint`, or the mint the wallet picks,
+/// and stores it to be confirmed. The wallet has to hold the amount and the
+/// fee reserve at that mint.
+pub async fn quote_withdrawal<W: CashuWalletApi>(
+    state: &AppState<W>,
+    bolt11: &str,
+    mint: Option<&str>,
+) -> Result<Withdrawal, WithdrawError> {
+    quote_invoice(state, bolt11, None, mint).await
+}
+
+/// Like [`quote_withdrawal`], for `amount_sats` to a Lightning Address or
+/// LNURL-pay `destination`, which is asked for the invoice.
+pub async fn quote_lnurl_withdrawal<W: CashuWalletApi>(
+    state: &AppState<W>,
+    destination: &str,
+    amount_sats: i64,
+    comment: Option<&str>,
+    mint: Option<&str>,
+) -> Result<Withdrawal, WithdrawError> {
+    let bolt11 = LnurlClient::new(state.withdraw.allow_insecure_lnurl)
+        .fetch_invoice(destination, amount_sats, comment)
+        .await?;
+    quote_invoice(state, &bolt11, Some(destination.trim()), mint).await
+}
+
+async fn quote_invoice<W: CashuWalletApi>(
+    state: &AppState<W>,
+    bolt11: &str,
+    destination: Option<&str>,
+    mint: Option<&str>,
+) -> Result<Withdrawal, WithdrawError> {
+    let quote = observe_wallet_call("melt_quote", state.wallet.melt_quote(bolt11, mint))
+        .await
+        .map_err(|e| WithdrawError::Quote(e.to_string()))?;
+    let mint = quote
+        .mint
+        .as_deref()
+        .and_then(|mint| normalize_mint_url(mint).ok())
+        .or_else(|| mint.map(str::to_string));
+
+    let balance = observe_wallet_call("balance", state.wallet.balance())
+        .await
+        .map_err(|e| WithdrawError::Wallet(e.to_string()))?;
+    let held = match &mint {
+        Some(mint) => mint_balances(&balance).get(mint).copied().unwrap_or(0),
+        None => balance.balance,
+    };
+    let needed = quote.amount + quote.fee_reserve;
+    if held < needed {
+        return Err(WithdrawError::InsufficientFunds { needed, held });
+    }
+
+    let expires_at = quote
+        .expiry
+        .and_then(|expiry| DateTime::from_timestamp(expiry, 0))
+        .unwrap_or_else(|| Utc::now() + chrono::Duration::seconds(DEFAULT_QUOTE_SECS));
+    Ok(add_withdrawal(
+        &state.db,
+        &NewWithdrawal {
+            bolt11: bolt11.to_string(),
+            destination: destination.map(str::to_string),
+            mint_url: mint,
+            quote_id: quote.quote,
+            amount_sats: quote.amount,
+            fee_reserve_sats: quote.fee_reserve,
+            expires_at,
+        },
+    )
+    .await?)
+}
+
+/// Pays a quoted withdrawal. Payments still in flight once `pay_invoice`
+/// returns are followed in the background, and so are payments whose outcome
+/// was lost to an error: the mint may have taken them all the same.
+pub async fn confirm_withdrawal<W: CashuWalletApi>(
+    state: Arc<AppState<W>>,
+    id: Uuid,
+) -> Result<Withdrawal, WithdrawError> {
+    let Some(mut withdrawal) = begin_withdrawal(&state.db, id).await? else {
+        return Err(match get_withdrawal(&state.db, id).await? {
+            None => WithdrawError::NotFound,
+            Some(withdrawal) if withdrawal.status == WithdrawalStatus::Quoted => {
+                WithdrawError::Expired
+            }
+            Some(withdrawal) => WithdrawError::NotQuoted(withdrawal.status),
+        });
+    };
+
+    let paid = observe_wallet_call(
+        "pay_invoice",
+        state
+            .wallet
+            .pay_invoice(&withdrawal.bolt11, withdrawal.mint_url.as_deref()),
+    )
+    .await;
+    match paid {
+        Ok(payment) => {
+            if let Some(checking_id) = &payment.checking_id {
+                set_withdrawal_checking_id(&state.db, id, checking_id).await?;
+                withdrawal.checking_id = Some(checking_id.clone());
+            }
+            match payment.result {
+                PaymentResult::Success => {
+                    finish(
+                        &state,
+                        &withdrawal,
+                        fee_sats(payment.fee.as_ref()),
+                        payment.preimage.as_deref(),
+                    )
+                    .await
+                }
+                PaymentResult::Failed => {
+                    let error = payment
+                        .error_message
+                        .unwrap_or_else(|| "Payment failed".to_string());
+                    fail(&state, &withdrawal, &error).await
+                }
+                PaymentResult::Pending | PaymentResult::Unknown => {
+                    watch_withdrawal(state.clone(), withdrawal);
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Paying withdrawal {} returned an error, following it up: {}",
+                withdrawal.id,
+                e
+            );
+            watch_withdrawal(state.clone(), withdrawal);
+        }
+    }
+
+    get_withdrawal(&state.db, id)
+        .await?
+        .ok_or(WithdrawError::NotFound)
+}
+
+/// Asks the mint about a pending payment until it is settled either way.
+/// Payments the mint knows nothing about once their quote expired never
+/// reached it, and fail. Payments the mint can't be asked about stay
+/// pending.
+pub fn watch_withdrawal<W: CashuWalletApi>(
+    state: Arc<AppState<W>>,
+    withdrawal: Withdrawal,
+) -> JoinHandle<()> {
+    let period = Duration::from_millis(state.withdraw.poll_interval_ms.max(1));
+    let checking_id = withdrawal
+        .checking_id
+        .clone()
+        .unwrap_or_else(|| withdrawal.quote_id.clone());
+
+    tokio::spawn(async move {
+        let mut failed_checks = 0;
+        loop {
+            let status = observe_wallet_call(
+                "payment_state",
+                state
+                    .wallet
+                    .payment_state(Some(&checking_id), withdrawal.mint_url.as_deref()),
+            )
+            .await;
+            if status.is_ok() {
+                failed_checks = 0;
+            }
+            match status {
+                Ok(status) => match status.result {
+                    PaymentResult::Success => {
+                        let fee = fee_sats(status.fee.as_ref());
+                        return finish(&state, &withdrawal, fee, status.preimage.as_deref()).await;
+                    }
+                    PaymentResult::Failed => {
+                        let error = status
+                            .error_message
+                            .unwrap_or_else(|| "Payment failed".to_string());
+                        return fail(&state, &withdrawal, &error).await;
+                    }
+                    PaymentResult::Unknown if withdrawal.expires_at < Utc::now() => {
+                        return fail(
+                            &state,
+                            &withdrawal,
+                            "The mint never took the payment and its quote expired",
+                        )
+                        .await;
+                    }
+                    PaymentResult::Pending | PaymentResult::Unknown => {}
+                },
+                Err(e) => {
+                    failed_checks += 1;
+                    if failed_checks >= MAX_FAILED_CHECKS {
+                        tracing::warn!(
+                            "Giving up on withdrawal {} until the next start: {}",
+                            withdrawal.id,
+                            e
+                        );
+                        return;
+                    }
+                    tracing::debug!("Failed to check withdrawal {}: {}", withdrawal.id, e);
+                }
+            }
+
+            // A mint that keeps failing is asked less and less often.
+            tokio::time::sleep(period * 2u32.pow(failed_checks.min(MAX_BACKOFF_EXPONENT))).await;
+        }
+    })
+}
+
+/// Watches the withdrawals still pending from a previous run.
+pub async fn resume_withdrawals<W: CashuWalletApi>(
+    state: Arc<AppState<W>>,
+) -> Result<usize, sqlx::Error> {
+    let pending = get_pending_withdrawals(&state.db).await?;
+    let count = pending.len();
+    for withdrawal in pending {
+        watch_withdrawal(state.clone(), withdrawal);
+    }
+    Ok(count)
+}
+
+/// `None` when the wallet couldn't tell, which is booked as such rather
+/// than as a free payment.
+fn fee_sats(fee: Option<&Amount>) -> Option<i64> {
+    match fee? {
+        Amount {
+            unit: Unit::Msat,
+            amount,
+        } => Some((amount + 999) / 1000),
+        fee => Some(fee.amount),
+    }
+}
+
+async fn finish<W: CashuWalletApi>(
+    state: &AppState<W>,
+    withdrawal: &Withdrawal,
+    fee_sats: Option<i64>,
+    preimage: Option<&str>,
+) {
+    match complete_withdrawal(&state.db, withdrawal.id, fee_sats, preimage).await {
+        Ok(_) => match fee_sats {
+            Some(fee_sats) => tracing::info!(
+                "Withdrew {} sat for {} sat in fees",
+                withdrawal.amount_sats,
+                fee_sats
+            ),
+            None => tracing::info!(
+                "Withdrew {} sat, the mint did not say for what fee",
+                withdrawal.amount_sats
+            ),
+        },
+        Err(e) => tracing::error!("Failed to book withdrawal {}: {}", withdrawal.id, e),
+    }
+}
+
+async fn fail<W: CashuWalletApi>(state: &AppState<W>, withdrawal: &Withdrawal, error: &str) {
+    tracing::warn!("Withdrawal {} failed: {}", withdrawal.id, error);
+    if let Err(e) = fail_withdrawal(&state.db, withdrawal.id, error).await {
+        tracing::error!("Failed to close withdrawal {}: {}", withdrawal.id, e);
+    }
+}
@@ -0,0 +1,97 @@
+mod support;
+
+use gateway::db::{
+    MSAT_PER_SAT,
+    request::{NewRequest, create_request, set_request_cost},
+    transaction::{TransactionDirection, add_transaction},
+};
+use serde_json::Value;
+use support::TestGateway;
+use uuid::Uuid;
+
+async fn request(gateway: &TestGateway, model: &str) -> Uuid {
+    let id = create_request(
+        &gateway.pool,
+        &NewRequest {
+            endpoint: "/v1/chat/completions".to_string(),
+            model: Some(model.to_string()),
+            provider: gateway.upstream.url.clone(),
+            client_key: None,
+        },
+    )
+    .await
+    .unwrap();
+    // What the request thought it cost, which the ledger may disagree with.
+    set_request_cost(&gateway.pool, id, 30 * MSAT_PER_SAT)
+        .await
+        .unwrap();
+    id
+}
+
+async fn book(
+    gateway: &TestGateway,
+    request_id: Option<Uuid>,
+    sats: u64,
+    direction: TransactionDirection,
+) {
+    add_transaction(
+        &gateway.pool,
+        &gateway.cipher(),
+        &gateway.mint.issue(sats),
+        sats as i64 * MSAT_PER_SAT,
+        direction,
+        request_id,
+        None,
+    )
+    .await
+    .unwrap();
+}
+
+async fn analytics(gateway: &TestGateway, path: &str) -> Vec<Value> {
+    gateway
+        .get(&format!("/api/analytics/{}?group_by=model", path))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn spend_of<'a>(rows: &'a [Value], model: &str) -> &'a Value {
+    rows.iter().find(|row| row["group"] == model).unwrap()
+}
+
+#[tokio::test]
+async fn adds_up_spend_from_the_ledger() {
+    let gateway = TestGateway::start(0).await;
+
+    // Paid 30 and got 12 back as change.
+    let changed = request(&gateway, "changed").await;
+    book(&gateway, Some(changed), 30, TransactionDirection::Outgoing).await;
+    book(&gateway, Some(changed), 12, TransactionDirection::Incoming).await;
+    // Paid 30, refunded in full.
+    let refunded = request(&gateway, "refunded").await;
+    book(&gateway, Some(refunded), 30, TransactionDirection::Outgoing).await;
+    book(&gateway, Some(refunded), 30, TransactionDirection::Incoming).await;
+    // Never paid.
+    request(&gateway, "refunded").await;
+    // Not spent on a request.
+    book(&gateway, None, 50, TransactionDirection::Outgoing).await;
+
+    let summary = analytics(&gateway, "summary").await;
+    assert_eq!(summary.len(), 2);
+    assert_eq!(spend_of(&summary, "changed")["spend_msat"], 18_000);
+    let refunded = spend_of(&summary, "refunded");
+    assert_eq!(refunded["requests"], 2);
+    assert_eq!(refunded["spend_msat"], 0);
+    assert_eq!(refunded["avg_cost_msat"], 0);
+
+    let series = analytics(&gateway, "spend").await;
+    assert_eq!(series.len(), 2);
+    assert_eq!(spend_of(&series, "changed")["spend_msat"], 18_000);
+    assert_eq!(spend_of(&series, "changed")["avg_cost_msat"], 18_000);
+    assert_eq!(spend_of(&series, "refunded")["spend_msat"], 0);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,242 @@
+mod support;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use gateway::{
+    crypto::{CryptoError, SecretCipher, mask_secret},
+    db::server_config::{
+        create_config, encrypt_plaintext_api_keys, get_config_by_id, get_default_config,
+    },
+};
+use secrecy::{ExposeSecret, SecretString};
+use support::TestGateway;
+use wallet::models::ServerConfig;
+
+fn cipher(byte: u8) -> SecretCipher {
+    SecretCipher::new(&SecretString::from(STANDARD.encode([byte; 32]))).unwrap()
+}
+
+/// Puts config `id` back the way installations that predate encryption at
+/// rest left it.
+async fn store_plaintext(gateway: &TestGateway, id: &str, api_key: &str) {
+    sqlx::query(
+        "UPDATE server_config SET api_key_plaintext = $1, api_key_encrypted = NULL WHERE id = $2",
+    )
+    .bind(api_key)
+    .bind(id)
+    .execute(&gateway.pool)
+    .await
+    .unwrap();
+}
+
+async fn plaintext(gateway: &TestGateway, id: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT api_key_plaintext FROM server_config WHERE id = $1")
+        .bind(id)
+        .fetch_one(&gateway.pool)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn round_trips_secrets() {
+    let cipher = cipher(1);
+
+    let first = cipher.encrypt("sk-upstream").unwrap();
+    let second = cipher.encrypt("sk-upstream").unwrap();
+
+    // A fresh nonce per secret, so equal secrets do not look equal at rest.
+    assert_ne!(first, second);
+    assert!(!first.contains("sk-upstream"));
+    assert_eq!(
+        cipher.decrypt(&first).unwrap().expose_secret(),
+        "sk-upstream"
+    );
+    assert_eq!(
+        cipher.decrypt(&second).unwrap().expose_secret(),
+        "sk-upstream"
+    );
+    assert_eq!(
+        cipher
+            .decrypt(&cipher.encrypt("").unwrap())
+            .unwrap()
+            .expose_secret(),
+        ""
+    );
+}
+
+#[test]
+fn rejects_the_wrong_key() {
+    let encrypted = cipher(1).encrypt("sk-upstream").unwrap();
+
+    assert!(matches!(
+        cipher(2).decrypt(&encrypted),
+        Err(CryptoError::Decrypt)
+    ));
+}
+
+#[test]
+fn rejects_tampered_ciphertext() {
+    let cipher = cipher(1);
+    let encrypted = STANDARD
+        .decode(cipher.encrypt("sk-upstream").unwrap())
+        .unwrap();
+
+    // Flipping any bit, of the nonce, the ciphertext or the tag, breaks it.
+    for index in [0, 12, encrypted.len() - 1] {
+        let mut tampered = encrypted.clone();
+        tampered[index] ^= 0x01;
+        assert!(matches!(
+            cipher.decrypt(&STANDARD.encode(&tampered)),
+            Err(CryptoError::Decrypt)
+        ));
+    }
+    assert!(matches!(
+        cipher.decrypt(&STANDARD.encode(&encrypted[..encrypted.len() - 1])),
+        Err(CryptoError::Decrypt)
+    ));
+}
+
+#[test]
+fn rejects_malformed_ciphertext() {
+    let cipher = cipher(1);
+
+    assert!(matches!(
+        cipher.decrypt("not base64!"),
+        Err(CryptoError::MalformedCiphertext)
+    ));
+    assert!(matches!(
+        cipher.decrypt(&STANDARD.encode([0u8; 11])),
+        Err(CryptoError::MalformedCiphertext)
+    ));
+}
+
+#[test]
+fn rejects_keys_that_are_not_32_bytes_of_base64() {
+    for key in [
+        String::new(),
+        "not base64!".to_string(),
+        STANDARD.encode([1u8; 16]),
+        STANDARD.encode([1u8; 33]),
+    ] {
+        assert!(matches!(
+            SecretCipher::new(&SecretString::from(key)),
+            Err(CryptoError::InvalidKey)
+        ));
+    }
+    // Surrounding whitespace, as left by editors and env files, is fine.
+    let key = format!(" {}\n", STANDARD.encode([1u8; 32]));
+    assert!(SecretCipher::new(&SecretString::from(key)).is_ok());
+}
+
+#[test]
+fn masks_all_but_the_end_of_long_secrets() {
+    assert_eq!(mask_secret(""), "");
+    assert_eq!(mask_secret("sk-short"), "********");
+    assert_eq!(mask_secret("sk-elevenab"), "********");
+    assert_eq!(mask_secret("sk-twelve123"), "********e123");
+    assert_eq!(mask_secret("sk-upstream-secret"), "********cret");
+}
+
+#[tokio::test]
+async fn encrypts_plaintext_api_keys() {
+    let gateway = TestGateway::start(0).await;
+    let cipher = gateway.cipher();
+    let config = get_default_config(&gateway.pool, &cipher)
+        .await
+        .unwrap()
+        .unwrap();
+    store_plaintext(&gateway, &config.id, "sk-old").await;
+
+    let migrated = encrypt_plaintext_api_keys(&gateway.pool, &cipher)
+        .await
+        .unwrap();
+
+    assert_eq!(migrated, 1);
+    assert_eq!(plaintext(&gateway, &config.id).await, None);
+    let config = get_config_by_id(&gateway.pool, &cipher, &config.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(config.api_key.expose_secret(), "sk-old");
+
+    // Nothing is left to migrate the next time the gateway starts.
+    let migrated = encrypt_plaintext_api_keys(&gateway.pool, &cipher)
+        .await
+        .unwrap();
+    assert_eq!(migrated, 0);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn migrates_all_api_keys_or_none() {
+    let gateway = TestGateway::start(0).await;
+    let cipher = gateway.cipher();
+    let first = get_default_config(&gateway.pool, &cipher)
+        .await
+        .unwrap()
+        .unwrap();
+    let second = create_config(
+        &gateway.pool,
+        &cipher,
+        &ServerConfig {
+            endpoint: "http://second.invalid".to_string(),
+            api_key: "sk-second".to_string(),
+            accepted_mints: Vec::new(),
+            p2pk_pubkey: None,
+        },
+    )
+    .await
+    .unwrap();
+    store_plaintext(&gateway, &first.id, "sk-first").await;
+    store_plaintext(&gateway, &second.id, "sk-second").await;
+    sqlx::raw_sql(&format!(
+        r#"
+        CREATE FUNCTION fail_migration() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'migration refused';
+        END
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER fail_migration BEFORE UPDATE ON server_config
+            FOR EACH ROW WHEN (NEW.id = '{}')
+            EXECUTE FUNCTION fail_migration();
+        "#,
+        second.id
+    ))
+    .execute(&gateway.pool)
+    .await
+    .unwrap();
+
+    assert!(
+        encrypt_plaintext_api_keys(&gateway.pool, &cipher)
+            .await
+            .is_err()
+    );
+
+    // Whichever row went first, neither is left half migrated.
+    assert_eq!(
+        plaintext(&gateway, &first.id).await.as_deref(),
+        Some("sk-first")
+    );
+    assert_eq!(
+        plaintext(&gateway, &second.id).await.as_deref(),
+        Some("sk-second")
+    );
+    let encrypted: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM server_config WHERE api_key_encrypted IS NOT NULL",
+    )
+    .fetch_one(&gateway.pool)
+    .await
+    .unwrap();
+    assert_eq!(encrypted, 0);
+
+    sqlx::raw_sql("DROP TRIGGER fail_migration ON server_config")
+        .execute(&gateway.pool)
+        .await
+        .unwrap();
+    let migrated = encrypt_plaintext_api_keys(&gateway.pool, &cipher)
+        .await
+        .unwrap();
+    assert_eq!(migrated, 2);
+
+    gateway.shutdown().await;
+}
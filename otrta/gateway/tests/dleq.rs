@@ -0,0 +1,168 @@
+mod support;
+
+use axum::http::StatusCode;
+use gateway::db::transaction::{DleqStatus, TransactionDirection};
+use serde_json::{Value, json};
+use support::{
+    TestGateway, chat,
+    fake_wallet::{FAKE_MINT_URL, FakeMint},
+    upstream::{Change, Script},
+};
+
+async fn redeem(gateway: &TestGateway, token: &str) -> Value {
+    gateway
+        .post("/api/wallet/redeem")
+        .json(&json!({ "token": token }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Pays for a completion that returns `change` sats and gives back the
+/// DLEQ check recorded on the incoming transaction booked for it.
+async fn change_dleq_status(gateway: &TestGateway, change: u64) -> Option<DleqStatus> {
+    gateway
+        .upstream
+        .script(Script::Respond(Change::Sats(change)));
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    gateway
+        .transactions()
+        .await
+        .into_iter()
+        .find(|t| matches!(t.direction, TransactionDirection::Incoming))
+        .expect("change was not booked")
+        .dleq_status
+}
+
+#[tokio::test]
+async fn records_the_dleq_check_of_change() {
+    let gateway = TestGateway::start(100).await;
+
+    assert_eq!(
+        change_dleq_status(&gateway, 12).await,
+        Some(DleqStatus::Valid)
+    );
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn flags_change_without_dleq_from_mints_not_known_to_sign_them() {
+    let gateway = TestGateway::start(100).await;
+    // The wallet's mint has no stored info and its `/v1/info` can't be
+    // reached, so nothing says it signs DLEQ proofs.
+    gateway.mint.set_signs_dleq(false);
+
+    assert_eq!(
+        change_dleq_status(&gateway, 12).await,
+        Some(DleqStatus::Missing)
+    );
+    assert_eq!(gateway.wallet.balance_at(FAKE_MINT_URL), 100 - 30 + 12);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_tokens_without_dleq_from_nut12_mints() {
+    let gateway = TestGateway::start(100).await;
+    // The mock upstream answers `/v1/info` with NUT-12 support.
+    let mint = FakeMint::at(&gateway.upstream.url);
+    gateway.wallet.add_mint(mint.clone(), 0);
+    let response = gateway
+        .set_mint_rule(mint.url(), json!({ "status": "allowed" }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    mint.set_signs_dleq(false);
+    let response = redeem(&gateway, &mint.issue(20)).await;
+    assert_eq!(response["success"], false);
+    assert_eq!(
+        response["message"],
+        format!(
+            "token carries no DLEQ proofs, which mint {} provides (NUT-12)",
+            mint.url()
+        )
+    );
+    assert_eq!(gateway.wallet.balance_at(mint.url()), 0);
+
+    mint.set_signs_dleq(true);
+    let response = redeem(&gateway, &mint.issue(20)).await;
+    assert_eq!(response["success"], true);
+    assert_eq!(gateway.wallet.balance_at(mint.url()), 20);
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 1);
+    assert!(matches!(
+        transactions[0].direction,
+        TransactionDirection::Incoming
+    ));
+    assert_eq!(transactions[0].amount_msat, 20_000);
+    assert_eq!(transactions[0].dleq_status, Some(DleqStatus::Valid));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn fetches_missing_info_of_trusted_mints_to_check_dleq() {
+    let gateway = TestGateway::start(100).await;
+    let mint = FakeMint::at(&gateway.upstream.url);
+    gateway.wallet.add_mint(mint.clone(), 0);
+    gateway
+        .set_mint_rule(mint.url(), json!({ "status": "allowed" }))
+        .await;
+    // As if the mint had been down when its rule was set.
+    sqlx::query("UPDATE mints SET info = NULL, info_fetched_at = NULL WHERE url = $1")
+        .bind(mint.url())
+        .execute(&gateway.pool)
+        .await
+        .unwrap();
+
+    mint.set_signs_dleq(false);
+    let response = redeem(&gateway, &mint.issue(20)).await;
+    assert_eq!(response["success"], false);
+    assert_eq!(gateway.wallet.balance_at(mint.url()), 0);
+
+    let mints = gateway
+        .get("/api/mints")
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Value>>()
+        .await
+        .unwrap();
+    let listed = mints.iter().find(|m| m["url"] == mint.url()).unwrap();
+    assert_eq!(listed["info"]["nuts"]["12"]["supported"], true);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_tokens_the_mint_did_not_sign() {
+    let gateway = TestGateway::start(100).await;
+    // Same URL, other keys.
+    let forged = FakeMint::at(FAKE_MINT_URL).issue(50);
+
+    let response = redeem(&gateway, &forged).await;
+    assert_eq!(response["success"], false);
+    assert_eq!(
+        response["message"],
+        format!(
+            "proofs of the token were not signed by mint {} (NUT-12)",
+            FAKE_MINT_URL
+        )
+    );
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,157 @@
+mod support;
+
+use gateway::db::{
+    MSAT_PER_SAT,
+    request::{NewRequest, create_request},
+    transaction::{TransactionDirection, add_transaction},
+};
+use serde_json::Value;
+use support::TestGateway;
+
+const AWKWARD_MODEL: &str = "team \"alpha\", v2\nbeta";
+
+/// Books a transaction of `sats` for a request to `model`.
+async fn seed(gateway: &TestGateway, model: &str, sats: u64, direction: TransactionDirection) {
+    let request_id = create_request(
+        &gateway.pool,
+        &NewRequest {
+            endpoint: "/v1/chat/completions".to_string(),
+            model: Some(model.to_string()),
+            provider: gateway.upstream.url.clone(),
+            client_key: None,
+        },
+    )
+    .await
+    .unwrap();
+    add_transaction(
+        &gateway.pool,
+        &gateway.cipher(),
+        &gateway.mint.issue(sats),
+        sats as i64 * MSAT_PER_SAT,
+        direction,
+        Some(request_id),
+        None,
+    )
+    .await
+    .unwrap();
+}
+
+async fn export(gateway: &TestGateway, query: &str) -> String {
+    gateway
+        .get(&format!("/api/transactions/export?{}", query))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+/// Reads CSV records, with quoted fields spanning commas, quotes and lines.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    records
+}
+
+/// Ids of the transactions `/api/transactions` lists for `query`.
+async fn listed_ids(gateway: &TestGateway, query: &str) -> Vec<String> {
+    let page: Value = gateway
+        .get(&format!("/api/transactions?pageSize=500&{}", query))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let mut ids: Vec<String> = page["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["id"].as_str().unwrap().to_string())
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn escapes_csv_fields() {
+    let gateway = TestGateway::start(0).await;
+    seed(&gateway, AWKWARD_MODEL, 5, TransactionDirection::Outgoing).await;
+
+    let csv = export(&gateway, "format=csv").await;
+    assert!(csv.contains("\"team \"\"alpha\"\", v2\nbeta\""));
+    let records = parse_csv(&csv);
+    assert_eq!(records.len(), 2);
+    let header = &records[0];
+    assert_eq!(records[1].len(), header.len());
+    let model = header.iter().position(|name| name == "model").unwrap();
+    assert_eq!(records[1][model], AWKWARD_MODEL);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn exports_what_the_listing_shows() {
+    let gateway = TestGateway::start(0).await;
+    seed(&gateway, AWKWARD_MODEL, 5, TransactionDirection::Outgoing).await;
+    seed(&gateway, "mock-model", 7, TransactionDirection::Outgoing).await;
+    seed(&gateway, "mock-model", 3, TransactionDirection::Incoming).await;
+
+    let listed = listed_ids(&gateway, "direction=Outgoing").await;
+    assert_eq!(listed.len(), 2);
+
+    let csv = parse_csv(&export(&gateway, "format=csv&direction=Outgoing").await);
+    let id = csv[0].iter().position(|name| name == "id").unwrap();
+    let mut exported: Vec<String> = csv[1..].iter().map(|row| row[id].clone()).collect();
+    exported.sort();
+    assert_eq!(exported, listed);
+
+    let ndjson = export(&gateway, "format=ndjson&direction=Outgoing").await;
+    let mut exported: Vec<String> = ndjson
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<Value>(line).unwrap()["id"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    exported.sort();
+    assert_eq!(exported, listed);
+
+    // A model filter with the characters CSV has to quote.
+    let mut filter = reqwest::Url::parse("http://gateway/?format=ndjson").unwrap();
+    filter.query_pairs_mut().append_pair("model", AWKWARD_MODEL);
+    let ndjson = export(&gateway, filter.query().unwrap()).await;
+    let rows: Vec<Value> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["model"], AWKWARD_MODEL);
+    assert_eq!(rows[0]["amount_msat"], 5_000);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,29 @@
+mod support;
+
+use sqlx::{Connection, PgConnection};
+use support::TestGateway;
+
+#[tokio::test]
+async fn drops_the_database_of_a_failing_test() {
+    let failing = tokio::spawn(async {
+        let gateway = TestGateway::start(0).await;
+        let name: String = sqlx::query_scalar("SELECT current_database()::text")
+            .fetch_one(&gateway.pool)
+            .await
+            .unwrap();
+        if !name.is_empty() {
+            panic!("{name}");
+        }
+    });
+    let panic = failing.await.unwrap_err().into_panic();
+    let name = panic.downcast_ref::<String>().unwrap();
+
+    let url = std::env::var("DATABASE_URL").unwrap();
+    let mut connection = PgConnection::connect(&url).await.unwrap();
+    let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pg_database WHERE datname = $1")
+        .bind(name)
+        .fetch_one(&mut connection)
+        .await
+        .unwrap();
+    assert_eq!(left, 0);
+}
@@ -0,0 +1,129 @@
+mod support;
+
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use gateway::db::{
+    MSAT_PER_SAT,
+    listing::MAX_PAGE_SIZE,
+    transaction::{TransactionDirection, add_transaction},
+};
+use serde_json::Value;
+use support::TestGateway;
+use uuid::Uuid;
+
+/// Books a transaction of `sats` for each entry, all at the same time.
+async fn seed(gateway: &TestGateway, amounts: &[u64]) -> Vec<(i64, Uuid)> {
+    let mut seeded = Vec::new();
+    for sats in amounts {
+        let amount_msat = *sats as i64 * MSAT_PER_SAT;
+        let id = add_transaction(
+            &gateway.pool,
+            &gateway.cipher(),
+            &gateway.mint.issue(*sats),
+            amount_msat,
+            TransactionDirection::Incoming,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        seeded.push((amount_msat, id));
+    }
+    sqlx::query("UPDATE transactions SET created_at = '2025-06-01T00:00:00Z'")
+        .execute(&gateway.pool)
+        .await
+        .unwrap();
+    seeded
+}
+
+async fn list(gateway: &TestGateway, query: &str) -> reqwest::Response {
+    gateway
+        .get(&format!("/api/transactions?{}", query))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Follows the cursors from the first page to the last.
+async fn walk(gateway: &TestGateway, sort: &str, order: &str) -> Vec<Uuid> {
+    let mut ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut query = format!("sort={}&order={}&pageSize=2", sort, order);
+        if let Some(cursor) = &cursor {
+            query.push_str(&format!("&cursor={}", cursor));
+        }
+        let page: Value = list(gateway, &query).await.json().await.unwrap();
+        for row in page["data"].as_array().unwrap() {
+            ids.push(row["id"].as_str().unwrap().parse().unwrap());
+        }
+        match page["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => return ids,
+        }
+    }
+}
+
+#[tokio::test]
+async fn pages_across_ties_in_both_directions() {
+    let gateway = TestGateway::start(0).await;
+    let mut seeded = seed(&gateway, &[5, 5, 5, 7, 5, 7, 5]).await;
+
+    // Every row shares its time, and most share their amount, so only the id
+    // keeps the pages apart.
+    seeded.sort();
+    let by_amount: Vec<Uuid> = seeded.iter().map(|(_, id)| *id).collect();
+    let mut by_id = by_amount.clone();
+    by_id.sort();
+
+    for (sort, order, expected) in [
+        ("amount", "asc", by_amount.clone()),
+        ("amount", "desc", by_amount.iter().rev().copied().collect()),
+        ("created_at", "asc", by_id.clone()),
+        ("created_at", "desc", by_id.iter().rev().copied().collect()),
+    ] {
+        assert_eq!(
+            walk(&gateway, sort, order).await,
+            expected,
+            "{sort} {order}"
+        );
+    }
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn rejects_malformed_cursors() {
+    let gateway = TestGateway::start(0).await;
+    seed(&gateway, &[5, 5, 5]).await;
+
+    let page: Value = list(&gateway, "pageSize=2").await.json().await.unwrap();
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let tampered = &cursor[..cursor.len() - 4];
+
+    for cursor in [
+        "not a cursor".to_string(),
+        URL_SAFE_NO_PAD.encode("not json"),
+        URL_SAFE_NO_PAD.encode(r#"{"id":"00000000-0000-0000-0000-000000000000"}"#),
+        tampered.to_string(),
+    ] {
+        let response = list(&gateway, &format!("cursor={}", cursor)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{cursor}");
+    }
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn caps_the_page_size() {
+    let gateway = TestGateway::start(0).await;
+
+    let page: Value = list(&gateway, "pageSize=100000")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["pagination"]["page_size"], MAX_PAGE_SIZE);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,138 @@
+mod support;
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+use support::{
+    TestGateway,
+    lnurl::{Behaviour, MockLnurl},
+};
+
+async fn quote(gateway: &TestGateway, body: Value) -> reqwest::Response {
+    gateway
+        .post("/api/wallet/withdraw")
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn pays_a_lightning_address() {
+    let gateway = TestGateway::start(100).await;
+    let service = MockLnurl::start().await;
+    gateway.wallet.set_melt_fees(2, 1);
+
+    let address = service.address("alice");
+    let response = quote(
+        &gateway,
+        json!({ "address": address, "amount": 21, "comment": "thanks" }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let withdrawal: Value = response.json().await.unwrap();
+    assert_eq!(withdrawal["status"], "quoted");
+    assert_eq!(withdrawal["amount_sats"], 21);
+    assert_eq!(withdrawal["destination"], address.as_str());
+    assert!(withdrawal["bolt11"].as_str().unwrap().starts_with("lnbc"));
+
+    let requests = service.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].user, "alice");
+    assert_eq!(requests[0].amount_msat, 21_000);
+    assert_eq!(requests[0].comment.as_deref(), Some("thanks"));
+
+    let id = withdrawal["id"].as_str().unwrap();
+    let withdrawal: Value = gateway
+        .post(&format!("/api/wallet/withdraw/{}/confirm", id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(withdrawal["status"], "paid");
+    assert_eq!(gateway.wallet.current_balance(), 78);
+    assert_eq!(gateway.transactions().await[0].amount_msat, 21_000);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn pays_a_bech32_lnurl() {
+    let gateway = TestGateway::start(100).await;
+    let service = MockLnurl::start().await;
+
+    let lnurl = service.lnurl("bob").to_uppercase();
+    let response = quote(
+        &gateway,
+        json!({ "address": format!("lightning:{}", lnurl), "amount": 5 }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let withdrawal: Value = response.json().await.unwrap();
+    assert_eq!(withdrawal["amount_sats"], 5);
+    assert_eq!(service.requests()[0].user, "bob");
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_invoices_that_do_not_match_the_request() {
+    let gateway = TestGateway::start(100).await;
+    let service = MockLnurl::start().await;
+    let address = service.address("carol");
+
+    let error = |response: reqwest::Response| async move {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = response.json().await.unwrap();
+        body["error"].as_str().unwrap().to_string()
+    };
+
+    // Bounds are checked before an invoice is asked for.
+    let response = quote(&gateway, json!({ "address": address, "amount": 1001 })).await;
+    assert_eq!(
+        error(response).await,
+        "Amount has to be between 1 and 1000 sat for this destination"
+    );
+    let response = quote(
+        &gateway,
+        json!({ "address": address, "amount": 5, "comment": "x".repeat(33) }),
+    )
+    .await;
+    assert_eq!(
+        error(response).await,
+        "Comments can be at most 32 characters for this destination"
+    );
+    assert!(service.requests().is_empty());
+
+    service.behave(Behaviour::WrongAmount);
+    let response = quote(&gateway, json!({ "address": address, "amount": 5 })).await;
+    assert_eq!(
+        error(response).await,
+        "Invoice from the LNURL service is for 6000 msat instead of 5000"
+    );
+
+    service.behave(Behaviour::WrongDescription);
+    let response = quote(&gateway, json!({ "address": address, "amount": 5 })).await;
+    assert_eq!(
+        error(response).await,
+        "Invoice from the LNURL service doesn't commit to its metadata"
+    );
+
+    // Neither an amount nor a single destination.
+    let response = quote(&gateway, json!({ "address": address })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = quote(
+        &gateway,
+        json!({ "address": "not-an-address", "amount": 5 }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = quote(&gateway, json!({ "amount": 5 })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(gateway.wallet.current_balance(), 100);
+    assert!(gateway.transactions().await.is_empty());
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,243 @@
+mod support;
+
+use axum::http::StatusCode;
+use cdk::nuts::CurrencyUnit;
+use gateway::{db::transaction::TransactionDirection, provider::PAYMENT_HEADER};
+use serde_json::{Value, json};
+use support::{
+    TestGateway, chat,
+    fake_wallet::{FAKE_MINT_URL, FakeMint},
+    upstream::{Change, MINT_NAME, Script},
+};
+
+const PRICE_SATS: i64 = 30;
+const ROGUE_MINT_URL: &str = "https://rogue-mint.test";
+
+#[tokio::test]
+async fn quarantines_change_from_denied_mints_until_they_are_trusted() {
+    let gateway = TestGateway::start(100).await;
+    let response = gateway
+        .set_mint_rule(FAKE_MINT_URL, json!({ "status": "denied" }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mint: Value = response.json().await.unwrap();
+    assert_eq!(mint["trusted"], false);
+    gateway.upstream.script(Script::Respond(Change::Sats(12)));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    // The change is kept aside and not counted as a refund.
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS);
+    let request = gateway.completed_request().await;
+    assert_eq!(request.cost_msat, PRICE_SATS * 1000);
+    let credits = gateway.credits().await;
+    assert_eq!(credits.len(), 1);
+    assert_eq!(
+        credits[0].quarantine_reason.as_deref(),
+        Some(format!("mint {} is not trusted", FAKE_MINT_URL).as_str())
+    );
+    assert_eq!(credits[0].amount_msat, 12_000);
+
+    let redeem = gateway.post(&format!("/api/credits/{}/redeem", credits[0].id));
+    assert_eq!(
+        redeem.try_clone().unwrap().send().await.unwrap().status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    gateway
+        .set_mint_rule(FAKE_MINT_URL, json!({ "status": "allowed" }))
+        .await;
+    let response = redeem.send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let credit: Value = response.json().await.unwrap();
+    assert_eq!(credit["redeemed"], true);
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS + 12);
+    assert!(gateway.transactions().await.iter().any(|t| {
+        matches!(t.direction, TransactionDirection::Incoming) && t.amount_msat == 12_000
+    }));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn quarantines_change_that_would_exceed_the_balance_limit() {
+    let gateway = TestGateway::start(100).await;
+    gateway
+        .set_mint_rule(
+            FAKE_MINT_URL,
+            json!({ "status": "allowed", "max_balance_sats": 75 }),
+        )
+        .await;
+    gateway.upstream.script(Script::Respond(Change::Sats(12)));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+    gateway.completed_request().await;
+
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS);
+    let credits = gateway.credits().await;
+    assert_eq!(
+        credits[0].quarantine_reason.as_deref(),
+        Some(
+            format!(
+                "mint {} would hold 82 sat, over its limit of 75",
+                FAKE_MINT_URL
+            )
+            .as_str()
+        )
+    );
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_tokens_in_units_other_than_sat() {
+    let gateway = TestGateway::start(100).await;
+    let token = gateway.mint.issue_in(50, CurrencyUnit::Usd);
+
+    let response: Value = gateway
+        .post("/api/wallet/redeem")
+        .json(&json!({ "token": token }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["success"], false);
+    assert_eq!(
+        response["message"],
+        "tokens in usd are not accepted, only sat"
+    );
+    assert_eq!(gateway.wallet.current_balance(), 100);
+    assert!(gateway.mint.is_unspent(&token));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_tokens_from_unknown_mints_and_records_them() {
+    let gateway = TestGateway::start(100).await;
+    let token = FakeMint::at(ROGUE_MINT_URL).issue(50);
+
+    let response: Value = gateway
+        .post("/api/wallet/redeem")
+        .json(&json!({ "token": token }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["success"], false);
+    assert_eq!(
+        response["message"],
+        format!("mint {} is not trusted", ROGUE_MINT_URL)
+    );
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    // Mints over plain HTTP are not even recorded.
+    let token = FakeMint::at("http://plain-mint.test").issue(50);
+    let response = gateway
+        .post("/api/wallet/redeem")
+        .json(&json!({ "token": token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The mint is recorded for the operator to inspect, but nothing is
+    // fetched from it.
+    let mints = gateway
+        .get("/api/mints")
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Value>>()
+        .await
+        .unwrap();
+    assert_eq!(mints.len(), 2);
+    let rogue = mints.iter().find(|m| m["url"] == ROGUE_MINT_URL).unwrap();
+    assert_eq!(rogue["trusted"], false);
+    assert_eq!(rogue["status"], Value::Null);
+    assert_eq!(rogue["info"], Value::Null);
+    let ours = mints.iter().find(|m| m["url"] == FAKE_MINT_URL).unwrap();
+    assert_eq!(ours["trusted"], true);
+    assert_eq!(ours["balance_sats"], 100);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn provider_refuses_payments_from_untrusted_mints() {
+    let provider = TestGateway::start_provider(0).await;
+    provider
+        .set_mint_rule(FAKE_MINT_URL, json!({ "status": "denied" }))
+        .await;
+    let token = provider.mint.issue(30);
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, &token)
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"]["message"],
+        format!("Payment refused: mint {} is not trusted", FAKE_MINT_URL)
+    );
+    assert!(provider.mint.is_unspent(&token));
+    assert!(provider.upstream.seen().is_empty());
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn fetches_the_info_of_mints() {
+    let gateway = TestGateway::start(100).await;
+    let mint_url = gateway.upstream.url.clone();
+
+    let response = gateway
+        .set_mint_rule(&mint_url, json!({ "status": "allowed" }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mint: Value = response.json().await.unwrap();
+    assert_eq!(mint["trusted"], true);
+    assert_eq!(mint["info"]["name"], MINT_NAME);
+    assert_eq!(mint["info"]["nuts"]["12"]["supported"], true);
+    assert!(mint["info_fetched_at"].is_string());
+
+    let response = gateway
+        .client
+        .post(gateway.mint_api(&mint_url, &["info"]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = gateway
+        .client
+        .delete(gateway.mint_api(&mint_url, &[]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,170 @@
+mod support;
+
+use axum::{
+    Json, Router,
+    extract::{RawQuery, State},
+    http::StatusCode,
+    routing::post,
+};
+use gateway::connection::PaymentSettings;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+use support::{TestGateway, UPSTREAM_API_KEY, chat, fake_wallet::FAKE_P2PK_PUBKEY};
+use tokio::net::TcpListener;
+use wallet::api::{CashuWalletApi, CashuWalletClient};
+
+async fn set_p2pk_pubkey(gateway: &TestGateway, key: Option<&str>) -> reqwest::Response {
+    gateway
+        .post("/api/server-config")
+        .json(&json!({
+            "endpoint": gateway.upstream.url,
+            "api_key": UPSTREAM_API_KEY,
+            "accepted_mints": [],
+            "p2pk_pubkey": key,
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn locks_payments_to_the_provider_key() {
+    let gateway = TestGateway::start(100).await;
+
+    // Keys are stored as bare hex, whichever form they were given in.
+    let response = set_p2pk_pubkey(&gateway, Some(&format!("P2PK:{FAKE_P2PK_PUBKEY}"))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let config: Value = response.json().await.unwrap();
+    assert_eq!(config["p2pk_pubkey"], FAKE_P2PK_PUBKEY);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let sends = gateway.wallet.sends();
+    assert_eq!(sends.len(), 1);
+    assert_eq!(sends[0].lock.as_deref(), Some(FAKE_P2PK_PUBKEY));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_unlocked_payments_by_default() {
+    let gateway = TestGateway::start(100).await;
+    assert_eq!(
+        set_p2pk_pubkey(&gateway, None).await.status(),
+        StatusCode::OK
+    );
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "unlocked_payment_refused");
+
+    assert!(gateway.upstream.seen().is_empty());
+    assert!(gateway.wallet.sends().is_empty());
+    assert_eq!(gateway.wallet.current_balance(), 100);
+    let requests = gateway.requests().await;
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].error.is_some());
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn pays_unlocked_when_allowed() {
+    let gateway = TestGateway::start_with(
+        100,
+        PaymentSettings {
+            allow_unlocked: true,
+        },
+    )
+    .await;
+    set_p2pk_pubkey(&gateway, None).await;
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let sends = gateway.wallet.sends();
+    assert_eq!(sends.len(), 1);
+    assert!(sends[0].lock.is_none());
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn rejects_an_invalid_provider_key() {
+    let gateway = TestGateway::start(100).await;
+
+    let response = set_p2pk_pubkey(&gateway, Some("not-a-key")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn provider_advertises_its_wallet_key() {
+    let provider = TestGateway::start_provider(0).await;
+
+    let pricing: Value = provider
+        .get("/api/pricing")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(pricing["p2pk_pubkey"], FAKE_P2PK_PUBKEY);
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn nutshell_is_sent_locks_in_its_own_form() {
+    // Stands in for nutshell's `/send` and keeps the query of each call.
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let app = Router::new()
+        .route(
+            "/send",
+            post(
+                |State(queries): State<Arc<Mutex<Vec<String>>>>, RawQuery(query): RawQuery| async move {
+                    queries.lock().unwrap().push(query.unwrap_or_default());
+                    Json(json!({ "balance": 0, "token": "cashuBfake", "npub": null }))
+                },
+            ),
+        )
+        .with_state(queries.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let nutshell = CashuWalletClient::new(&url);
+    for lock in [
+        FAKE_P2PK_PUBKEY.to_string(),
+        format!("P2PK:{FAKE_P2PK_PUBKEY}"),
+    ] {
+        nutshell
+            .send(10, None, Some(&lock), None, None, None)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        *queries.lock().unwrap(),
+        vec![format!("amount=10&lock=P2PK:{FAKE_P2PK_PUBKEY}"); 2]
+    );
+}
@@ -0,0 +1,511 @@
+mod support;
+
+use axum::http::StatusCode;
+use gateway::db::transaction::TransactionDirection;
+use serde_json::Value;
+use std::time::Duration;
+use support::{
+    TestGateway, UPSTREAM_API_KEY, chat,
+    fake_wallet::{FAKE_MINT_URL, FakeMint},
+    upstream::{Change, STREAM_CHUNKS, Script, USAGE},
+};
+
+const PRICE_SATS: i64 = 30;
+const OTHER_MINT_URL: &str = "https://other-mint.test";
+
+#[tokio::test]
+async fn pays_upstream_and_records_the_request() {
+    let gateway = TestGateway::start(100).await;
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["usage"]["total_tokens"], USAGE.2);
+
+    let seen = gateway.upstream.seen();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].redeemed, Some(PRICE_SATS as u64));
+    assert_eq!(
+        seen[0].authorization.as_deref(),
+        Some(format!("Bearer {}", UPSTREAM_API_KEY).as_str())
+    );
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS);
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.http_status, Some(200));
+    assert_eq!(request.cost_msat, PRICE_SATS * 1000);
+    assert_eq!(request.model.as_deref(), Some("mock-model"));
+    assert_eq!(
+        (
+            request.prompt_tokens,
+            request.completion_tokens,
+            request.total_tokens
+        ),
+        (Some(USAGE.0), Some(USAGE.1), Some(USAGE.2))
+    );
+    assert!(request.client_key.is_some());
+    assert!(request.error.is_none());
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 1);
+    let outgoing = &transactions[0];
+    assert!(matches!(outgoing.direction, TransactionDirection::Outgoing));
+    assert_eq!(outgoing.amount_msat, PRICE_SATS * 1000);
+    assert_eq!(outgoing.mint_url.as_deref(), Some(FAKE_MINT_URL));
+    assert_eq!(outgoing.request_id, Some(request.id));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn redeems_change_returned_in_x_change_sats() {
+    let gateway = TestGateway::start(100).await;
+    gateway.upstream.script(Script::Respond(Change::Sats(12)));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS + 12);
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.cost_msat, (PRICE_SATS - 12) * 1000);
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 2);
+    let incoming = transactions
+        .iter()
+        .find(|t| matches!(t.direction, TransactionDirection::Incoming))
+        .expect("change was not recorded");
+    assert_eq!(incoming.amount_msat, 12_000);
+    assert_eq!(incoming.request_id, Some(request.id));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn keeps_change_returned_in_x_change_token_as_credit() {
+    let gateway = TestGateway::start(100).await;
+    gateway.upstream.script(Script::Respond(Change::Token(8)));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    // Credits are kept as tokens, not swapped into the wallet.
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS);
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.cost_msat, (PRICE_SATS - 8) * 1000);
+
+    let credits = gateway.credits().await;
+    assert_eq!(credits.len(), 1);
+    assert_eq!(credits[0].amount_msat, 8_000);
+    assert_eq!(credits[0].mint_url.as_deref(), Some(FAKE_MINT_URL));
+    assert_eq!(credits[0].request_id, Some(request.id));
+    assert!(!credits[0].redeemed);
+    assert!(
+        gateway
+            .mint
+            .is_unspent(&gateway.credit_token(credits[0].id).await)
+    );
+    let listed: Value = gateway
+        .get("/api/credits")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        listed["data"][0]["token_fingerprint"],
+        credits[0].token_fingerprint
+    );
+    assert!(listed["data"][0].get("token").is_none());
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn relays_streamed_completions_and_records_usage() {
+    let gateway = TestGateway::start(100).await;
+    gateway.upstream.script(Script::Respond(Change::Sats(5)));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(true))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+    let body = response.text().await.unwrap();
+
+    let content: String = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]")
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .filter_map(|chunk| {
+            chunk["choices"][0]["delta"]["content"]
+                .as_str()
+                .map(String::from)
+        })
+        .collect();
+    assert_eq!(content, STREAM_CHUNKS.concat());
+    assert!(body.trim_end().ends_with("data: [DONE]"));
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.http_status, Some(200));
+    assert_eq!(request.total_tokens, Some(USAGE.2));
+    assert_eq!(request.cost_msat, (PRICE_SATS - 5) * 1000);
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS + 5);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn passes_upstream_errors_through_and_still_books_the_payment() {
+    for status in [
+        StatusCode::PAYMENT_REQUIRED,
+        StatusCode::SERVICE_UNAVAILABLE,
+    ] {
+        let gateway = TestGateway::start(100).await;
+        gateway.upstream.script(Script::Status(status));
+
+        let response = gateway
+            .post("/v1/chat/completions")
+            .json(&chat(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["type"], "upstream_error");
+
+        let request = gateway.completed_request().await;
+        assert_eq!(request.http_status, Some(status.as_u16() as i32));
+        assert_eq!(request.cost_msat, PRICE_SATS * 1000);
+
+        // The token left the wallet; whether the provider kept it is not
+        // something the gateway can know.
+        let transactions = gateway.transactions().await;
+        assert_eq!(transactions.len(), 1);
+        assert!(matches!(
+            transactions[0].direction,
+            TransactionDirection::Outgoing
+        ));
+        let payment = gateway.upstream.seen()[0].payment.clone().unwrap();
+        assert!(gateway.mint.is_unspent(&payment));
+
+        gateway.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn ignores_change_that_cannot_be_parsed() {
+    let gateway = TestGateway::start(100).await;
+    gateway.upstream.script(Script::BadChange);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.cost_msat, PRICE_SATS * 1000);
+    assert!(request.error.is_none());
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS);
+    assert_eq!(gateway.transactions().await.len(), 1);
+    assert!(gateway.credits().await.is_empty());
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn ignores_change_headers_that_are_not_text() {
+    let gateway = TestGateway::start(100).await;
+    gateway.upstream.script(Script::NonAsciiChange);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.cost_msat, PRICE_SATS * 1000);
+    assert!(gateway.credits().await.is_empty());
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn relays_responses_whose_payment_cannot_be_booked() {
+    let gateway = TestGateway::start(100).await;
+    gateway.fail_bookings(&["Outgoing", "Incoming"]).await;
+    gateway.upstream.script(Script::Respond(Change::Sats(12)));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["usage"]["total_tokens"], USAGE.2);
+
+    assert_eq!(gateway.wallet.current_balance(), 100 - PRICE_SATS + 12);
+    let request = gateway.completed_request().await;
+    assert_eq!(request.cost_msat, (PRICE_SATS - 12) * 1000);
+    assert!(gateway.transactions().await.is_empty());
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn fails_without_calling_upstream_when_the_wallet_cannot_pay() {
+    let gateway = TestGateway::start(100).await;
+    gateway.wallet.fail_sends(true);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "payment_error");
+
+    assert!(gateway.upstream.seen().is_empty());
+    assert!(gateway.transactions().await.is_empty());
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.http_status, None);
+    assert_eq!(request.cost_msat, 0);
+    assert!(
+        request
+            .error
+            .unwrap()
+            .starts_with("Failed to generate payment token")
+    );
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn records_an_error_when_upstream_drops_the_stream() {
+    let gateway = TestGateway::start(100).await;
+    gateway.upstream.script(Script::Disconnect);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(true))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.bytes().await.is_err());
+
+    let request = gateway.completed_request().await;
+    assert_eq!(request.http_status, Some(200));
+    assert!(
+        request
+            .error
+            .unwrap()
+            .starts_with("Error reading from upstream")
+    );
+    assert_eq!(gateway.transactions().await.len(), 1);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn measures_latency_of_slow_upstreams() {
+    let gateway = TestGateway::start(100).await;
+    let delay = Duration::from_millis(400);
+    gateway.upstream.script(Script::Stall(delay));
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    let request = gateway.completed_request().await;
+    assert!(request.latency_ms.unwrap() >= delay.as_millis() as i64);
+    assert_eq!(request.total_tokens, Some(USAGE.2));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn pays_from_a_mint_the_provider_accepts() {
+    let gateway = TestGateway::start(100).await;
+    // The richest mint is one the provider doesn't take tokens from.
+    gateway.wallet.add_mint(FakeMint::at(OTHER_MINT_URL), 500);
+
+    let balance: Value = gateway
+        .get("/api/wallet/balance")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(balance["balance"], "600");
+    assert_eq!(balance["mints"][FAKE_MINT_URL], 100);
+    assert_eq!(balance["mints"][OTHER_MINT_URL], 500);
+
+    let accepted = format!("{}/", FAKE_MINT_URL.to_uppercase());
+    assert_eq!(gateway.accept_mints(&[&accepted]).await, StatusCode::OK);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    assert_eq!(gateway.upstream.seen()[0].redeemed, Some(PRICE_SATS as u64));
+    assert_eq!(gateway.wallet.balance_at(FAKE_MINT_URL), 100 - PRICE_SATS);
+    assert_eq!(gateway.wallet.balance_at(OTHER_MINT_URL), 500);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_to_pay_when_no_accepted_mint_holds_enough() {
+    // Too little anywhere to swap the 20 missing sats in.
+    let gateway = TestGateway::start(20).await;
+    gateway.wallet.add_mint(FakeMint::at(OTHER_MINT_URL), 10);
+    assert_eq!(
+        gateway.accept_mints(&[OTHER_MINT_URL]).await,
+        StatusCode::OK
+    );
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"]["message"],
+        "Failed to generate payment token: no mint accepted by the provider holds 30 sat"
+    );
+
+    assert!(gateway.upstream.seen().is_empty());
+    assert_eq!(gateway.wallet.current_balance(), 30);
+    assert_eq!(
+        gateway.accept_mints(&["not a url"]).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn swaps_into_the_accepted_mint_when_it_cannot_pay() {
+    let gateway = TestGateway::start(10).await;
+    gateway.wallet.add_mint(FakeMint::at(OTHER_MINT_URL), 500);
+    gateway.wallet.set_swap_fee(1);
+    assert_eq!(gateway.accept_mints(&[FAKE_MINT_URL]).await, StatusCode::OK);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+
+    // Only the shortfall is moved.
+    assert_eq!(gateway.upstream.seen()[0].redeemed, Some(PRICE_SATS as u64));
+    assert_eq!(gateway.wallet.balance_at(FAKE_MINT_URL), 0);
+    assert_eq!(gateway.wallet.balance_at(OTHER_MINT_URL), 500 - 20 - 1);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn does_not_swap_into_untrusted_mints() {
+    let gateway = TestGateway::start(500).await;
+    gateway.wallet.add_mint(FakeMint::at(OTHER_MINT_URL), 0);
+    assert_eq!(
+        gateway.accept_mints(&[OTHER_MINT_URL]).await,
+        StatusCode::OK
+    );
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    assert!(gateway.upstream.seen().is_empty());
+    assert_eq!(gateway.wallet.balance_at(FAKE_MINT_URL), 500);
+    assert_eq!(gateway.wallet.balance_at(OTHER_MINT_URL), 0);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn does_not_swap_when_the_source_cannot_cover_the_fees() {
+    let gateway = TestGateway::start(10).await;
+    // 20 sat are missing, which may cost 2 + 1 sat in fees.
+    gateway.wallet.add_mint(FakeMint::at(OTHER_MINT_URL), 22);
+    assert_eq!(gateway.accept_mints(&[FAKE_MINT_URL]).await, StatusCode::OK);
+
+    let response = gateway
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    assert!(gateway.upstream.seen().is_empty());
+    assert_eq!(gateway.wallet.balance_at(FAKE_MINT_URL), 10);
+    assert_eq!(gateway.wallet.balance_at(OTHER_MINT_URL), 22);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,443 @@
+mod support;
+
+use axum::http::StatusCode;
+use cdk::nuts::PublicKey;
+use gateway::{
+    db::transaction::TransactionDirection,
+    pricing::{CHARGE_HEADER, CHARGE_PUBKEY_HEADER, CHARGE_SIGNATURE_HEADER, verify_charge},
+    provider::{CHANGE_EVENT, CHANGE_HEADER, PAYMENT_HEADER, change_from_stream},
+};
+use serde_json::{Value, json};
+use std::str::FromStr;
+use support::{
+    TestGateway, UPSTREAM_API_KEY, chat,
+    upstream::{STREAM_CHUNKS, Script, USAGE},
+};
+
+const PAYMENT_SATS: u64 = 30;
+// 5 prompt tokens at 2 msat and 7 completion tokens at 8 msat, 66 msat
+// rounded up.
+const USAGE_COST_SATS: u64 = 1;
+
+#[tokio::test]
+async fn refuses_requests_without_payment() {
+    let provider = TestGateway::start_provider(0).await;
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "payment_error");
+
+    assert!(provider.upstream.seen().is_empty());
+    assert!(provider.requests().await.is_empty());
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_spent_tokens() {
+    let provider = TestGateway::start_provider(0).await;
+    let token = provider.mint.issue(PAYMENT_SATS);
+    provider.mint.redeem(&token).unwrap();
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, &token)
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+    assert!(provider.upstream.seen().is_empty());
+    assert!(provider.transactions().await.is_empty());
+    let request = provider.completed_request().await;
+    assert_eq!(request.http_status, Some(402));
+    assert!(request.error.is_some());
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn charges_usage_and_returns_change() {
+    let provider = TestGateway::start_provider(100).await;
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let header = |name: &str| response.headers()[name].to_str().unwrap().to_string();
+    let change = header(CHANGE_HEADER);
+    let public_key = PublicKey::from_str(&header(CHARGE_PUBKEY_HEADER)).unwrap();
+    let charge = verify_charge(
+        &header(CHARGE_HEADER),
+        &header(CHARGE_SIGNATURE_HEADER),
+        &public_key,
+    )
+    .unwrap();
+    assert!(charge.usage_reported);
+    assert_eq!(
+        (charge.prompt_tokens, charge.completion_tokens),
+        (USAGE.0, USAGE.1)
+    );
+    assert_eq!(charge.cost_msat, 66);
+    assert_eq!(charge.paid_sats, PAYMENT_SATS as i64);
+    assert_eq!(charge.charged_sats, USAGE_COST_SATS as i64);
+    assert_eq!(charge.change_sats, (PAYMENT_SATS - USAGE_COST_SATS) as i64);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["usage"]["total_tokens"], USAGE.2);
+
+    assert_eq!(
+        provider.mint.redeem(&change).unwrap(),
+        PAYMENT_SATS - USAGE_COST_SATS
+    );
+    assert_eq!(
+        provider.wallet.current_balance(),
+        100 + USAGE_COST_SATS as i64
+    );
+
+    let seen = provider.upstream.seen();
+    assert_eq!(seen.len(), 1);
+    assert!(seen[0].payment.is_none());
+    assert_eq!(
+        seen[0].authorization.as_deref(),
+        Some(format!("Bearer {}", UPSTREAM_API_KEY).as_str())
+    );
+
+    let request = provider.completed_request().await;
+    assert_eq!(request.cost_msat, USAGE_COST_SATS as i64 * 1000);
+    assert_eq!(request.total_tokens, Some(USAGE.2));
+
+    let transactions = provider.transactions().await;
+    assert_eq!(transactions.len(), 2);
+    let incoming = transactions
+        .iter()
+        .find(|t| matches!(t.direction, TransactionDirection::Incoming))
+        .unwrap();
+    assert_eq!(incoming.amount_msat, PAYMENT_SATS as i64 * 1000);
+    let outgoing = transactions
+        .iter()
+        .find(|t| matches!(t.direction, TransactionDirection::Outgoing))
+        .unwrap();
+    assert_eq!(
+        outgoing.amount_msat,
+        (PAYMENT_SATS - USAGE_COST_SATS) as i64 * 1000
+    );
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn charges_only_its_own_payment_when_others_arrive_meanwhile() {
+    let provider = TestGateway::start_provider(100).await;
+    provider.wallet.receive_concurrently(50);
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let change = response.headers()[CHANGE_HEADER].to_str().unwrap();
+    assert_eq!(
+        provider.mint.redeem(change).unwrap(),
+        PAYMENT_SATS - USAGE_COST_SATS
+    );
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn refunds_the_payment_when_upstream_fails() {
+    let provider = TestGateway::start_provider(100).await;
+    provider
+        .upstream
+        .script(Script::Status(StatusCode::SERVICE_UNAVAILABLE));
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let change = response.headers()[CHANGE_HEADER].to_str().unwrap();
+    assert_eq!(provider.mint.redeem(change).unwrap(), PAYMENT_SATS);
+    let charge = verify_charge(
+        response.headers()[CHARGE_HEADER].to_str().unwrap(),
+        response.headers()[CHARGE_SIGNATURE_HEADER]
+            .to_str()
+            .unwrap(),
+        &PublicKey::from_str(response.headers()[CHARGE_PUBKEY_HEADER].to_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    assert!(charge.refunded);
+    assert_eq!(charge.charged_sats, 0);
+    assert_eq!(provider.wallet.current_balance(), 100);
+
+    let request = provider.completed_request().await;
+    assert_eq!(request.cost_msat, 0);
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn refunds_payments_it_cannot_book() {
+    let provider = TestGateway::start_provider(100).await;
+    provider.fail_bookings(&["Incoming"]).await;
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let refund = response.headers()[CHANGE_HEADER].to_str().unwrap();
+    assert_eq!(provider.mint.redeem(refund).unwrap(), PAYMENT_SATS);
+
+    assert!(provider.upstream.seen().is_empty());
+    assert_eq!(provider.wallet.current_balance(), 100);
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn hands_back_change_it_cannot_book() {
+    let provider = TestGateway::start_provider(100).await;
+    provider.fail_bookings(&["Outgoing"]).await;
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(true))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.unwrap();
+    let change = change_from_stream(body.as_bytes()).expect("stream ended without change");
+    assert_eq!(
+        provider.mint.redeem(&change).unwrap(),
+        PAYMENT_SATS - USAGE_COST_SATS
+    );
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn caps_completions_at_what_the_payment_covers() {
+    let provider = TestGateway::start_provider(100).await;
+
+    for requested in [None, Some(50)] {
+        let mut request = chat(false);
+        if let Some(max_tokens) = requested {
+            request["max_tokens"] = json!(max_tokens);
+        }
+        let response = provider
+            .post("/v1/chat/completions")
+            .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let seen = provider.upstream.seen();
+    // 30 sat less the prompt, at 8 msat a completion token.
+    let capped = seen[0].max_tokens.unwrap();
+    assert!(capped > 0 && capped < PAYMENT_SATS * 1000 / 8, "{capped}");
+    assert_eq!(seen[1].max_tokens, Some(50));
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_payments_that_do_not_cover_the_prompt() {
+    let provider = TestGateway::start_provider(100).await;
+    let response = provider
+        .client
+        .put(format!("{}/api/pricing/mock-model", provider.url))
+        .json(&json!({
+            "prompt_msat_per_1k": 1_000_000,
+            "completion_msat_per_1k": 1_000_000,
+            "request_msat": 0,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let token = provider.mint.issue(PAYMENT_SATS);
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, &token)
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+    assert!(provider.upstream.seen().is_empty());
+    assert_eq!(provider.wallet.current_balance(), 100);
+    assert_eq!(provider.mint.redeem(&token).unwrap(), PAYMENT_SATS);
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn sends_change_of_streams_after_the_last_event() {
+    let provider = TestGateway::start_provider(100).await;
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(true))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(CHANGE_HEADER).is_none());
+    let body = response.text().await.unwrap();
+
+    let done = body.find("data: [DONE]").unwrap();
+    let change_event = body.find("event: change").unwrap();
+    assert!(change_event > done);
+    let change = change_from_stream(body.as_bytes()).unwrap();
+    assert_eq!(
+        provider.mint.redeem(&change).unwrap(),
+        PAYMENT_SATS - USAGE_COST_SATS
+    );
+
+    let event: Value = body
+        .lines()
+        .skip_while(|line| *line != "event: change")
+        .find_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .unwrap();
+    let charge = verify_charge(
+        event["charge"].as_str().unwrap(),
+        event["signature"].as_str().unwrap(),
+        &PublicKey::from_str(event["pubkey"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(charge.charged_sats, USAGE_COST_SATS as i64);
+    assert_eq!(event["amount"], charge.change_sats);
+
+    let request = provider.completed_request().await;
+    assert_eq!(request.total_tokens, Some(USAGE.2));
+    assert_eq!(request.cost_msat, USAGE_COST_SATS as i64 * 1000);
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn charges_the_rate_set_for_a_model() {
+    let provider = TestGateway::start_provider(100).await;
+
+    let response = provider
+        .client
+        .put(format!("{}/api/pricing/mock-model", provider.url))
+        .json(&json!({
+            "prompt_msat_per_1k": 100_000,
+            "completion_msat_per_1k": 200_000,
+            "request_msat": 1_000,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let models: Value = provider
+        .client
+        .get(format!("{}/v1/models", provider.url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(models["data"][0]["pricing"]["prompt_msat_per_1k"], 100_000);
+
+    let response = provider
+        .post("/v1/chat/completions")
+        .header(PAYMENT_HEADER, provider.mint.issue(PAYMENT_SATS))
+        .json(&chat(false))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // 5 × 100 + 7 × 200 + 1000 msat, rounded up.
+    let change = response.headers()[CHANGE_HEADER].to_str().unwrap();
+    assert_eq!(provider.mint.redeem(change).unwrap(), PAYMENT_SATS - 3);
+
+    let response = provider
+        .client
+        .delete(format!("{}/api/pricing/mock-model", provider.url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    provider.shutdown().await;
+}
+
+#[tokio::test]
+async fn client_gateway_redeems_change_from_a_provider_gateway() {
+    let provider = TestGateway::start_provider(100).await;
+    let client = TestGateway::start_client_of(&provider, 100).await;
+
+    for stream in [false, true] {
+        let response = client
+            .post("/v1/chat/completions")
+            .json(&chat(stream))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // The change is the client gateway's, not the app's.
+        assert!(response.headers().get(CHANGE_HEADER).is_none());
+        let body = response.text().await.unwrap();
+        if stream {
+            assert!(body.contains(STREAM_CHUNKS[0]));
+            assert!(body.ends_with("data: [DONE]\n\n"), "{body}");
+            assert!(!body.contains(&format!("event: {CHANGE_EVENT}")));
+        }
+    }
+
+    // Each request pays 30 sat and gets all but the usage cost back.
+    let spent = 2 * USAGE_COST_SATS as i64;
+    for _ in 0..50 {
+        if client.wallet.current_balance() == 100 - spent {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(client.wallet.current_balance(), 100 - spent);
+    assert_eq!(provider.wallet.current_balance(), 100 + spent);
+
+    let costs: Vec<i64> = client
+        .requests()
+        .await
+        .iter()
+        .map(|r| r.cost_msat)
+        .collect();
+    assert_eq!(costs, vec![USAGE_COST_SATS as i64 * 1000; 2]);
+
+    client.shutdown().await;
+    provider.shutdown().await;
+}
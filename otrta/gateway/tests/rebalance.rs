@@ -0,0 +1,154 @@
+mod support;
+
+use gateway::{
+    connection::{MintPolicySettings, RebalanceSettings},
+    mint_policy::MintPolicy,
+    rebalance::{Rebalancer, Swap},
+};
+use std::collections::HashMap;
+use support::{
+    TestGateway,
+    fake_wallet::{FAKE_MINT_URL, FakeMint, FakeWallet},
+};
+
+const SPARE_MINT_URL: &str = "https://spare-mint.test";
+const FULL_MINT_URL: &str = "https://full-mint.test";
+
+fn wallet() -> FakeWallet {
+    let wallet = FakeWallet::new(FakeMint::default(), 0);
+    wallet.add_mint(FakeMint::at(SPARE_MINT_URL), 200);
+    wallet.add_mint(FakeMint::at(FULL_MINT_URL), 50);
+    wallet
+}
+
+/// Trusts the mints of `wallet()` besides those in `deny`.
+fn policy(deny: &[&str], max_balance_sats: Option<i64>) -> MintPolicy {
+    MintPolicy::new(
+        MintPolicySettings {
+            deny: deny.iter().map(|mint| mint.to_string()).collect(),
+            max_balance_sats,
+            ..Default::default()
+        },
+        &[FAKE_MINT_URL, SPARE_MINT_URL, FULL_MINT_URL].map(String::from),
+    )
+}
+
+fn rebalancer(targets: &[(&str, i64)], min_reserve_sats: i64) -> Rebalancer {
+    Rebalancer::new(RebalanceSettings {
+        targets: targets
+            .iter()
+            .map(|(mint, sats)| (mint.to_string(), *sats))
+            .collect::<HashMap<_, _>>(),
+        min_reserve_sats,
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn tops_up_mints_below_their_target() {
+    let gateway = TestGateway::start(0).await;
+    let wallet = wallet();
+    wallet.set_swap_fee(1);
+    let rebalancer = rebalancer(&[(FAKE_MINT_URL, 100), (FULL_MINT_URL, 50)], 20);
+
+    let swaps = rebalancer
+        .rebalance(&wallet, &policy(&[], None), &gateway.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        swaps,
+        vec![Swap {
+            from: SPARE_MINT_URL.to_string(),
+            to: FAKE_MINT_URL.to_string(),
+            amount: 100,
+        }]
+    );
+    assert_eq!(wallet.balance_at(FAKE_MINT_URL), 100);
+    assert_eq!(wallet.balance_at(SPARE_MINT_URL), 99);
+    // Mints at their target are not drawn from.
+    assert_eq!(wallet.balance_at(FULL_MINT_URL), 50);
+
+    assert!(
+        rebalancer
+            .rebalance(&wallet, &policy(&[], None), &gateway.pool)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn moves_what_can_be_spared_when_the_target_is_out_of_reach() {
+    let gateway = TestGateway::start(0).await;
+    let wallet = wallet();
+    let rebalancer = rebalancer(&[(FAKE_MINT_URL, 1_000)], 50);
+
+    let swaps = rebalancer
+        .rebalance(&wallet, &policy(&[], None), &gateway.pool)
+        .await
+        .unwrap();
+    // 150 sat above the reserve, of which 2 + 2 sat may go to fees.
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].amount, 146);
+    assert_eq!(swaps[0].amount + rebalancer.fee_allowance(146), 150);
+    assert_eq!(wallet.balance_at(FAKE_MINT_URL), 146);
+    assert_eq!(wallet.balance_at(SPARE_MINT_URL), 54);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn leaves_funds_in_place_when_the_fee_is_over_the_limit() {
+    let gateway = TestGateway::start(0).await;
+    let wallet = wallet();
+    // 2 sat plus 1% of 100 may go to fees.
+    wallet.set_swap_fee(4);
+    let rebalancer = rebalancer(&[(FAKE_MINT_URL, 100)], 20);
+
+    assert!(
+        rebalancer
+            .rebalance(&wallet, &policy(&[], None), &gateway.pool)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(wallet.balance_at(FAKE_MINT_URL), 0);
+    assert_eq!(wallet.balance_at(SPARE_MINT_URL), 200);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn does_not_top_up_untrusted_mints() {
+    let gateway = TestGateway::start(0).await;
+    let wallet = wallet();
+    let rebalancer = rebalancer(&[(FAKE_MINT_URL, 100)], 20);
+
+    let swaps = rebalancer
+        .rebalance(&wallet, &policy(&[FAKE_MINT_URL], None), &gateway.pool)
+        .await
+        .unwrap();
+    assert!(swaps.is_empty());
+    assert_eq!(wallet.balance_at(SPARE_MINT_URL), 200);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn tops_up_no_further_than_the_balance_limit() {
+    let gateway = TestGateway::start(0).await;
+    let wallet = wallet();
+    let rebalancer = rebalancer(&[(FAKE_MINT_URL, 100)], 20);
+
+    let swaps = rebalancer
+        .rebalance(&wallet, &policy(&[], Some(60)), &gateway.pool)
+        .await
+        .unwrap();
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].amount, 60);
+    assert_eq!(wallet.balance_at(FAKE_MINT_URL), 60);
+
+    gateway.shutdown().await;
+}
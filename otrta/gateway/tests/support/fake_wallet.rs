@@ -0,0 +1,750 @@
+use anyhow::{Result, anyhow, bail};
+use cdk::{
+    Amount as CdkAmount,
+    lightning_invoice::Bolt11Invoice,
+    mint_url::MintUrl,
+    nuts::{CurrencyUnit, Id, Proof, ProofDleq, SecretKey, Token},
+    secret::Secret,
+};
+use gateway::token::summarize_token;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+use wallet::api::{CashuWalletApi, models::*};
+
+pub const FAKE_MINT_URL: &str = "https://fake-mint.test";
+const FAKE_KEYSET_ID: &str = "009a1f293253e41e";
+/// P2PK key every fake wallet reports as its own.
+pub const FAKE_P2PK_PUBKEY: &str =
+    "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+/// In-memory stand-in for a mint. Issues real V4 tokens with random proofs
+/// and remembers which of them are still unspent. Proofs carry a DLEQ proof
+/// unless `set_signs_dleq(false)`, which only holds for tokens it issued.
+#[derive(Clone)]
+pub struct FakeMint {
+    url: Arc<str>,
+    unspent: Arc<Mutex<HashMap<String, u64>>>,
+    issued: Arc<Mutex<Vec<String>>>,
+    signs_dleq: Arc<AtomicBool>,
+}
+
+impl Default for FakeMint {
+    fn default() -> Self {
+        Self::at(FAKE_MINT_URL)
+    }
+}
+
+impl FakeMint {
+    pub fn at(url: &str) -> Self {
+        Self {
+            url: Arc::from(url),
+            unspent: Arc::default(),
+            issued: Arc::default(),
+            signs_dleq: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn set_signs_dleq(&self, signs: bool) {
+        self.signs_dleq.store(signs, Ordering::SeqCst);
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn issue(&self, amount: u64) -> String {
+        self.issue_in(amount, CurrencyUnit::Sat)
+    }
+
+    pub fn issue_in(&self, amount: u64, unit: CurrencyUnit) -> String {
+        let keyset_id = Id::from_str(FAKE_KEYSET_ID).unwrap();
+        let signs_dleq = self.signs_dleq.load(Ordering::SeqCst);
+        let proofs = (0..64)
+            .map(|bit| 1u64 << bit)
+            .filter(|value| amount & value != 0)
+            .map(|value| {
+                let mut proof = Proof::new(
+                    CdkAmount::from(value),
+                    keyset_id,
+                    Secret::generate(),
+                    SecretKey::generate().public_key(),
+                );
+                if signs_dleq {
+                    proof.dleq = Some(ProofDleq::new(
+                        SecretKey::generate(),
+                        SecretKey::generate(),
+                        SecretKey::generate(),
+                    ));
+                }
+                proof
+            })
+            .collect();
+        let token =
+            Token::new(MintUrl::from_str(&self.url).unwrap(), proofs, None, unit).to_string();
+
+        let fingerprint = summarize_token(&token).fingerprint;
+        self.issued.lock().unwrap().push(fingerprint.clone());
+        self.unspent.lock().unwrap().insert(fingerprint, amount);
+        token
+    }
+
+    /// Spends `token`, failing for tokens this mint never issued or that
+    /// were spent already.
+    pub fn redeem(&self, token: &str) -> Result<u64> {
+        self.unspent
+            .lock()
+            .unwrap()
+            .remove(&summarize_token(token).fingerprint)
+            .ok_or_else(|| anyhow!("Token is unknown or already spent"))
+    }
+
+    pub fn issued(&self, token: &str) -> bool {
+        self.issued
+            .lock()
+            .unwrap()
+            .contains(&summarize_token(token).fingerprint)
+    }
+
+    pub fn is_unspent(&self, token: &str) -> bool {
+        self.unspent
+            .lock()
+            .unwrap()
+            .contains_key(&summarize_token(token).fingerprint)
+    }
+}
+
+#[derive(Default)]
+struct WalletState {
+    /// Balance held at each mint. Sends without a mint pay from the first,
+    /// like nutshell's default mint.
+    mints: Vec<(FakeMint, i64)>,
+    fail_sends: bool,
+    /// Lightning fee charged to the outgoing mint of a swap.
+    swap_fee: i64,
+    invoices: Vec<FakeInvoice>,
+    /// Fee reserve of melt quotes and fee actually charged when paying.
+    melt_fee_reserve: i64,
+    melt_fee: i64,
+    /// Leave payments in flight until `settle_payments`.
+    hold_payments: bool,
+    /// Let payments through to the mint but fail `pay_invoice`, as if the
+    /// connection dropped before the answer came back.
+    lose_payment_responses: bool,
+    /// Leave the fee out of payment states, like a mint returning no change.
+    hide_payment_fees: bool,
+    /// Fail `payment_state`, as if the mint couldn't be reached.
+    fail_payment_checks: bool,
+    payment_checks: usize,
+    payments: Vec<FakePayment>,
+    sends: Vec<FakeSend>,
+    /// Sats another request receives while `receive` runs.
+    concurrent_receipts: i64,
+}
+
+/// A mint quote: paid once `settle_invoice` is called, issued once the
+/// wallet has minted its ecash.
+struct FakeInvoice {
+    request: String,
+    amount: i64,
+    mint: String,
+    paid: bool,
+    issued: bool,
+}
+
+/// An outgoing Lightning payment, by its melt quote id.
+struct FakePayment {
+    id: String,
+    fee: i64,
+    settled: bool,
+}
+
+/// The options a token was sent with.
+#[derive(Clone, Debug)]
+pub struct FakeSend {
+    pub amount: i64,
+    pub lock: Option<String>,
+    pub locktime: Option<i64>,
+    pub npub: Option<String>,
+}
+
+/// Amount of an invoice made by `fake_invoice` or `create_invoice`, or of a
+/// real one such as the LNURL stand-in hands out.
+fn invoice_amount(bolt11: &str) -> Result<i64> {
+    let Some(fake) = bolt11.strip_prefix("lnbcfake") else {
+        let invoice = Bolt11Invoice::from_str(bolt11).map_err(|_| anyhow!("Invalid invoice"))?;
+        return invoice
+            .amount_milli_satoshis()
+            .map(|msat| (msat / 1000) as i64)
+            .ok_or_else(|| anyhow!("Invoice has no amount"));
+    };
+    let digits: String = fake.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().map_err(|_| anyhow!("Invoice has no amount"))
+}
+
+/// A bolt11 stand-in the fake wallet can pay.
+pub fn fake_invoice(amount: i64) -> String {
+    format!("lnbcfake{}n1payee", amount)
+}
+
+impl WalletState {
+    fn mint(&mut self, url: &str) -> Result<&mut (FakeMint, i64)> {
+        self.mints
+            .iter_mut()
+            .find(|(mint, _)| mint.url() == url)
+            .ok_or_else(|| anyhow!("Unknown mint {}", url))
+    }
+
+    fn total(&self) -> i64 {
+        self.mints.iter().map(|(_, balance)| balance).sum()
+    }
+
+    fn mint_or_first(&mut self, url: Option<&str>) -> Result<&mut (FakeMint, i64)> {
+        match url {
+            Some(url) => self.mint(url),
+            None => self.mints.first_mut().ok_or_else(|| anyhow!("No mint")),
+        }
+    }
+}
+
+/// `CashuWalletApi` over one or more `FakeMint`s. Lightning calls work on
+/// the invoices of `fake_invoice` and `create_invoice`; tokens are never
+/// actually locked, though `lock` reports `FAKE_P2PK_PUBKEY`.
+#[derive(Clone)]
+pub struct FakeWallet {
+    state: Arc<Mutex<WalletState>>,
+}
+
+impl FakeWallet {
+    pub fn new(mint: FakeMint, balance: i64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(WalletState {
+                mints: vec![(mint, balance)],
+                fail_sends: false,
+                swap_fee: 0,
+                invoices: Vec::new(),
+                melt_fee_reserve: 2,
+                melt_fee: 1,
+                hold_payments: false,
+                lose_payment_responses: false,
+                hide_payment_fees: false,
+                fail_payment_checks: false,
+                payment_checks: 0,
+                payments: Vec::new(),
+                sends: Vec::new(),
+                concurrent_receipts: 0,
+            })),
+        }
+    }
+
+    /// Adds a mint holding `balance` sats.
+    pub fn add_mint(&self, mint: FakeMint, balance: i64) {
+        self.state.lock().unwrap().mints.push((mint, balance));
+    }
+
+    pub fn current_balance(&self) -> i64 {
+        self.state.lock().unwrap().total()
+    }
+
+    pub fn balance_at(&self, url: &str) -> i64 {
+        self.state.lock().unwrap().mint(url).map_or(0, |(_, b)| *b)
+    }
+
+    /// Makes every following `send` fail as if the mint were unreachable.
+    pub fn fail_sends(&self, fail: bool) {
+        self.state.lock().unwrap().fail_sends = fail;
+    }
+
+    pub fn set_swap_fee(&self, sats: i64) {
+        self.state.lock().unwrap().swap_fee = sats;
+    }
+
+    /// Sets the fee reserve of melt quotes and the fee payments cost.
+    pub fn set_melt_fees(&self, reserve: i64, fee: i64) {
+        let mut state = self.state.lock().unwrap();
+        state.melt_fee_reserve = reserve;
+        state.melt_fee = fee;
+    }
+
+    /// Makes every following `receive` see `sats` arrive alongside, as if
+    /// another request were paid at the same time.
+    pub fn receive_concurrently(&self, sats: i64) {
+        self.state.lock().unwrap().concurrent_receipts = sats;
+    }
+
+    /// Makes the following payments stay in flight until `settle_payments`.
+    pub fn hold_payments(&self, hold: bool) {
+        self.state.lock().unwrap().hold_payments = hold;
+    }
+
+    /// Makes the following payments go through while `pay_invoice` fails.
+    pub fn lose_payment_responses(&self, lose: bool) {
+        self.state.lock().unwrap().lose_payment_responses = lose;
+    }
+
+    /// Makes payment states leave out the fee that was paid.
+    pub fn hide_payment_fees(&self, hide: bool) {
+        self.state.lock().unwrap().hide_payment_fees = hide;
+    }
+
+    /// Makes asking for the state of a payment fail.
+    pub fn fail_payment_checks(&self, fail: bool) {
+        self.state.lock().unwrap().fail_payment_checks = fail;
+    }
+
+    /// How often the state of a payment was asked for.
+    pub fn payment_checks(&self) -> usize {
+        self.state.lock().unwrap().payment_checks
+    }
+
+    pub fn settle_payments(&self) {
+        let mut state = self.state.lock().unwrap();
+        for payment in &mut state.payments {
+            payment.settled = true;
+        }
+    }
+
+    /// Pays an invoice from `create_invoice`, as the payer's node would.
+    pub fn settle_invoice(&self, payment_request: &str) {
+        let mut state = self.state.lock().unwrap();
+        let invoice = state
+            .invoices
+            .iter_mut()
+            .find(|invoice| invoice.request == payment_request)
+            .expect("Unknown invoice");
+        invoice.paid = true;
+    }
+
+    pub fn sends(&self) -> Vec<FakeSend> {
+        self.state.lock().unwrap().sends.clone()
+    }
+
+    fn mint_urls(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .mints
+            .iter()
+            .map(|(mint, _)| mint.url().to_string())
+            .collect()
+    }
+}
+
+impl CashuWalletApi for FakeWallet {
+    async fn pay_invoice(&self, bolt11: &str, mint: Option<&str>) -> Result<PaymentResponse> {
+        let amount = invoice_amount(bolt11)?;
+        let mut state = self.state.lock().unwrap();
+        let (reserve, fee, settled) =
+            (state.melt_fee_reserve, state.melt_fee, !state.hold_payments);
+        let (_, balance) = state.mint_or_first(mint)?;
+        if *balance < amount + reserve {
+            bail!("Insufficient balance");
+        }
+        *balance -= amount + fee;
+        let id = format!("fake-melt-{}", state.payments.len());
+        state.payments.push(FakePayment {
+            id: id.clone(),
+            fee,
+            settled,
+        });
+        if state.lose_payment_responses {
+            bail!("Connection reset while paying");
+        }
+
+        Ok(PaymentResponse {
+            result: if settled {
+                PaymentResult::Success
+            } else {
+                PaymentResult::Pending
+            },
+            checking_id: Some(id),
+            fee: settled.then_some(Amount {
+                unit: Unit::Sat,
+                amount: fee,
+            }),
+            preimage: settled.then(|| "00".repeat(32)),
+            error_message: None,
+        })
+    }
+
+    async fn melt_quote(&self, bolt11: &str, mint: Option<&str>) -> Result<MeltQuote> {
+        let amount = invoice_amount(bolt11)?;
+        let mut state = self.state.lock().unwrap();
+        let reserve = state.melt_fee_reserve;
+        let mint = state.mint_or_first(mint)?.0.url().to_string();
+        // The id the next payment is made under.
+        let id = format!("fake-melt-{}", state.payments.len());
+
+        Ok(MeltQuote {
+            quote: id.clone(),
+            method: "bolt11".to_string(),
+            request: bolt11.to_string(),
+            checking_id: id,
+            unit: "sat".to_string(),
+            amount,
+            fee_reserve: reserve,
+            state: MeltQuoteState::UNPAID,
+            created_time: 0,
+            paid_time: None,
+            fee_paid: None,
+            payment_preimage: None,
+            expiry: Some(chrono::Utc::now().timestamp() + 600),
+            outputs: None,
+            change: None,
+            mint: Some(mint),
+        })
+    }
+
+    async fn payment_state(
+        &self,
+        payment_hash: Option<&str>,
+        _mint: Option<&str>,
+    ) -> Result<PaymentStatus> {
+        let mut state = self.state.lock().unwrap();
+        state.payment_checks += 1;
+        if state.fail_payment_checks {
+            bail!("Mint unreachable");
+        }
+        let payment = state
+            .payments
+            .iter()
+            .find(|payment| Some(payment.id.as_str()) == payment_hash)
+            .ok_or_else(|| anyhow!("Unknown payment"))?;
+
+        Ok(PaymentStatus {
+            result: if payment.settled {
+                PaymentResult::Success
+            } else {
+                PaymentResult::Pending
+            },
+            fee: (payment.settled && !state.hide_payment_fees).then_some(Amount {
+                unit: Unit::Sat,
+                amount: payment.fee,
+            }),
+            preimage: payment.settled.then(|| "00".repeat(32)),
+            error_message: None,
+        })
+    }
+
+    async fn create_invoice(&self, amount: i64, mint: Option<&str>) -> Result<InvoiceResponse> {
+        let mut state = self.state.lock().unwrap();
+        let mint = match mint {
+            Some(url) => state.mint(url)?.0.url().to_string(),
+            None => state
+                .mints
+                .first()
+                .ok_or_else(|| anyhow!("No mint"))?
+                .0
+                .url()
+                .to_string(),
+        };
+        let id = format!("fake-quote-{}", state.invoices.len());
+        let request = format!("lnbcfake{}n1{}", amount, id);
+        state.invoices.push(FakeInvoice {
+            request: request.clone(),
+            amount,
+            mint,
+            paid: false,
+            issued: false,
+        });
+
+        Ok(InvoiceResponse {
+            ok: true,
+            checking_id: Some(id),
+            payment_request: Some(request),
+            error_message: None,
+        })
+    }
+
+    /// Mints the ecash of a paid invoice, like the CDK wallet.
+    async fn invoice_state(
+        &self,
+        payment_request: Option<&str>,
+        _mint: Option<&str>,
+    ) -> Result<PaymentStatus> {
+        let payment_request =
+            payment_request.ok_or_else(|| anyhow!("A payment request is required"))?;
+        let mut state = self.state.lock().unwrap();
+        let invoice = state
+            .invoices
+            .iter_mut()
+            .find(|invoice| invoice.request == payment_request)
+            .ok_or_else(|| anyhow!("Unknown payment request"))?;
+        let result = match (invoice.paid, invoice.issued) {
+            (false, _) => PaymentResult::Pending,
+            (true, true) => PaymentResult::Success,
+            (true, false) => {
+                invoice.issued = true;
+                let (amount, mint) = (invoice.amount, invoice.mint.clone());
+                state.mint(&mint)?.1 += amount;
+                PaymentResult::Success
+            }
+        };
+
+        Ok(PaymentStatus {
+            result,
+            fee: None,
+            preimage: None,
+            error_message: None,
+        })
+    }
+
+    async fn lightning_balance(&self) -> Result<StatusResponse> {
+        Ok(StatusResponse {
+            balance: self.current_balance() as f64,
+            error_message: None,
+        })
+    }
+
+    /// Moves the balance right away. Unknown incoming mints are added, as
+    /// the CDK wallet does.
+    async fn swap(
+        &self,
+        amount: i64,
+        outgoing_mint: &str,
+        incoming_mint: &str,
+        max_fee: Option<i64>,
+    ) -> Result<SwapResponse> {
+        let mut state = self.state.lock().unwrap();
+        let fee = state.swap_fee;
+        if let Some(max_fee) = max_fee
+            && fee > max_fee
+        {
+            bail!(
+                "Swap fee of {} sat is over the limit of {} sat",
+                fee,
+                max_fee
+            );
+        }
+        let (_, balance) = state.mint(outgoing_mint)?;
+        if *balance < amount + fee {
+            bail!("Insufficient balance");
+        }
+        *balance -= amount + fee;
+        if state.mint(incoming_mint).is_err() {
+            state.mints.push((FakeMint::at(incoming_mint), 0));
+        }
+        state.mint(incoming_mint)?.1 += amount;
+
+        Ok(SwapResponse {
+            outgoing_mint: outgoing_mint.to_string(),
+            incoming_mint: incoming_mint.to_string(),
+            mint_quote: MintQuote {
+                quote: "fake-quote".to_string(),
+                method: "bolt11".to_string(),
+                request: "lnbcfake".to_string(),
+                checking_id: "fake-quote".to_string(),
+                unit: "sat".to_string(),
+                amount,
+                state: MintQuoteState::ISSUED,
+                created_time: 0,
+                paid_time: None,
+                expiry: None,
+                mint: Some(incoming_mint.to_string()),
+                privkey: None,
+                pubkey: None,
+            },
+            balances: HashMap::new(),
+        })
+    }
+
+    async fn balance(&self) -> Result<BalanceResponse> {
+        let state = self.state.lock().unwrap();
+        Ok(BalanceResponse {
+            balance: state.total(),
+            keysets: None,
+            mints: Some(
+                state
+                    .mints
+                    .iter()
+                    .map(|(mint, balance)| {
+                        (
+                            mint.url().to_string(),
+                            serde_json::json!({ "available": balance, "balance": balance }),
+                        )
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Records the lock and recipient; the token itself isn't locked.
+    async fn send(
+        &self,
+        amount: i64,
+        nostr: Option<&str>,
+        lock: Option<&str>,
+        locktime: Option<i64>,
+        mint: Option<&str>,
+        _offline: Option<bool>,
+    ) -> Result<SendResponse> {
+        let mut state = self.state.lock().unwrap();
+        if state.fail_sends {
+            bail!("Mint unreachable");
+        }
+        let (mint, balance) = match mint {
+            Some(url) => state.mint(url)?,
+            None => state.mints.first_mut().ok_or_else(|| anyhow!("No mint"))?,
+        };
+        if *balance < amount {
+            bail!("Insufficient balance");
+        }
+        *balance -= amount;
+        let token = mint.issue(amount as u64);
+        state.sends.push(FakeSend {
+            amount,
+            lock: lock.map(str::to_string),
+            locktime,
+            npub: nostr.map(str::to_string),
+        });
+
+        Ok(SendResponse {
+            balance: state.total(),
+            token,
+            npub: nostr.map(str::to_string),
+        })
+    }
+
+    async fn token_state(&self, token: &str) -> Result<TokenState> {
+        let amount = Token::from_str(token)?.value()?;
+        let url = summarize_token(token)
+            .mint_url
+            .ok_or_else(|| anyhow!("Invalid token"))?;
+        let mut state = self.state.lock().unwrap();
+        let (mint, _) = state.mint(&url)?;
+
+        let amount = u64::from(amount) as i64;
+        Ok(if mint.is_unspent(token) {
+            TokenState {
+                unspent: amount,
+                ..TokenState::default()
+            }
+        } else {
+            TokenState {
+                spent: amount,
+                ..TokenState::default()
+            }
+        })
+    }
+
+    async fn verify_dleq(&self, token: &str) -> Result<DleqStatus> {
+        let proofs = Token::from_str(token)?.proofs();
+        let url = summarize_token(token)
+            .mint_url
+            .ok_or_else(|| anyhow!("Invalid token"))?;
+        let mut state = self.state.lock().unwrap();
+        let (mint, _) = state.mint(&url)?;
+
+        Ok(if proofs.iter().any(|proof| proof.dleq.is_none()) {
+            DleqStatus::Missing
+        } else if mint.issued(token) {
+            DleqStatus::Valid
+        } else {
+            DleqStatus::Invalid
+        })
+    }
+
+    async fn receive(
+        &self,
+        token: Option<&str>,
+        _nostr: Option<bool>,
+        _all: Option<bool>,
+    ) -> Result<ReceiveResponse> {
+        let token = token.ok_or_else(|| anyhow!("A token is required"))?;
+        let url = summarize_token(token)
+            .mint_url
+            .ok_or_else(|| anyhow!("Invalid token"))?;
+
+        let mut state = self.state.lock().unwrap();
+        let initial_balance = state.total();
+        let concurrent = state.concurrent_receipts;
+        let (mint, balance) = state.mint(&url)?;
+        let amount = mint.redeem(token)? as i64;
+        *balance += amount + concurrent;
+
+        Ok(ReceiveResponse {
+            initial_balance,
+            balance: state.total(),
+            amount,
+        })
+    }
+
+    async fn burn(
+        &self,
+        _token: Option<&str>,
+        _all: Option<bool>,
+        _force: Option<bool>,
+        _delete: Option<&str>,
+        _mint: Option<&str>,
+    ) -> Result<BurnResponse> {
+        Ok(BurnResponse {
+            balance: self.current_balance(),
+        })
+    }
+
+    async fn pending(&self, _number: Option<i64>, _offset: Option<i64>) -> Result<PendingResponse> {
+        Ok(PendingResponse {
+            pending_token: HashMap::new(),
+        })
+    }
+
+    async fn lock(&self) -> Result<LockResponse> {
+        Ok(LockResponse {
+            p2pk: Some(format!("P2PK:{FAKE_P2PK_PUBKEY}")),
+        })
+    }
+
+    async fn locks(&self) -> Result<LocksResponse> {
+        Ok(LocksResponse {
+            locks: vec![format!("P2PK:{FAKE_P2PK_PUBKEY}")],
+        })
+    }
+
+    async fn invoices(&self) -> Result<InvoicesResponse> {
+        Ok(InvoicesResponse {
+            mint_quotes: Vec::new(),
+            melt_quotes: Vec::new(),
+        })
+    }
+
+    async fn wallets(&self) -> Result<WalletsResponse> {
+        let state = self.state.lock().unwrap();
+        Ok(WalletsResponse {
+            wallets: state
+                .mints
+                .iter()
+                .map(|(mint, balance)| {
+                    (
+                        mint.url().to_string(),
+                        serde_json::json!({ "balance": balance, "unit": "sat" }),
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    async fn restore(&self, _to: i64) -> Result<RestoreResponse> {
+        Ok(RestoreResponse {
+            balance: self.current_balance(),
+        })
+    }
+
+    async fn info(&self) -> Result<InfoResponse> {
+        Ok(InfoResponse {
+            version: "fake".to_string(),
+            wallet: "fake".to_string(),
+            debug: true,
+            cashu_dir: String::new(),
+            mint_urls: self.mint_urls(),
+            settings: None,
+            tor: false,
+            nostr_public_key: None,
+            nostr_relays: Vec::new(),
+            socks_proxy: None,
+        })
+    }
+}
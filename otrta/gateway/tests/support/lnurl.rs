@@ -0,0 +1,162 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::get,
+};
+use cdk::{
+    SECP256K1,
+    lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret},
+    secp256k1::{
+        SecretKey,
+        hashes::{Hash, sha256},
+    },
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+use tokio::net::TcpListener;
+
+pub const MIN_SENDABLE_MSAT: u64 = 1_000;
+pub const MAX_SENDABLE_MSAT: u64 = 1_000_000;
+pub const COMMENT_ALLOWED: usize = 32;
+
+/// How the service answers the next invoice requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Behaviour {
+    Honest,
+    /// Hands out invoices for one sat more than asked.
+    WrongAmount,
+    /// Hands out invoices that don't commit to the metadata.
+    WrongDescription,
+}
+
+#[derive(Clone, Debug)]
+pub struct InvoiceRequest {
+    pub user: String,
+    pub amount_msat: u64,
+    pub comment: Option<String>,
+}
+
+struct LnurlState {
+    url: String,
+    behaviour: Mutex<Behaviour>,
+    requests: Mutex<Vec<InvoiceRequest>>,
+    invoices: AtomicU64,
+}
+
+/// Lightning Address / LNURL-pay service handing out real, signed bolt11
+/// invoices, which the fake wallet pays by amount.
+pub struct MockLnurl {
+    pub url: String,
+    state: Arc<LnurlState>,
+}
+
+impl MockLnurl {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(LnurlState {
+            url: url.clone(),
+            behaviour: Mutex::new(Behaviour::Honest),
+            requests: Mutex::new(Vec::new()),
+            invoices: AtomicU64::new(0),
+        });
+        let app = Router::new()
+            .route("/.well-known/lnurlp/{user}", get(pay_request))
+            .route("/lnurlp/{user}/callback", get(callback))
+            .with_state(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Self { url, state }
+    }
+
+    /// Lightning Address of `user` at this service.
+    pub fn address(&self, user: &str) -> String {
+        format!("{}@{}", user, self.url.trim_start_matches("http://"))
+    }
+
+    /// bech32 LNURL pointing at the pay request of `user`.
+    pub fn lnurl(&self, user: &str) -> String {
+        let url = format!("{}/.well-known/lnurlp/{}", self.url, user);
+        bech32::encode::<bech32::Bech32>(bech32::Hrp::parse("lnurl").unwrap(), url.as_bytes())
+            .unwrap()
+    }
+
+    pub fn behave(&self, behaviour: Behaviour) {
+        *self.state.behaviour.lock().unwrap() = behaviour;
+    }
+
+    pub fn requests(&self) -> Vec<InvoiceRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+fn metadata(user: &str) -> String {
+    json!([
+        ["text/plain", format!("Paying {}", user)],
+        ["text/identifier", format!("{}@lnurl.test", user)],
+    ])
+    .to_string()
+}
+
+async fn pay_request(
+    State(state): State<Arc<LnurlState>>,
+    Path(user): Path<String>,
+) -> Json<Value> {
+    Json(json!({
+        "tag": "payRequest",
+        "callback": format!("{}/lnurlp/{}/callback", state.url, user),
+        "minSendable": MIN_SENDABLE_MSAT,
+        "maxSendable": MAX_SENDABLE_MSAT,
+        "metadata": metadata(&user),
+        "commentAllowed": COMMENT_ALLOWED,
+    }))
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    amount: u64,
+    comment: Option<String>,
+}
+
+async fn callback(
+    State(state): State<Arc<LnurlState>>,
+    Path(user): Path<String>,
+    Query(query): Query<CallbackQuery>,
+) -> Json<Value> {
+    state.requests.lock().unwrap().push(InvoiceRequest {
+        user: user.clone(),
+        amount_msat: query.amount,
+        comment: query.comment,
+    });
+    if !(MIN_SENDABLE_MSAT..=MAX_SENDABLE_MSAT).contains(&query.amount) {
+        return Json(json!({ "status": "ERROR", "reason": "Amount out of bounds" }));
+    }
+
+    let behaviour = *state.behaviour.lock().unwrap();
+    let amount_msat = match behaviour {
+        Behaviour::WrongAmount => query.amount + 1_000,
+        _ => query.amount,
+    };
+    let description = match behaviour {
+        Behaviour::WrongDescription => "something else".to_string(),
+        _ => metadata(&user),
+    };
+    let n = state.invoices.fetch_add(1, Ordering::SeqCst);
+
+    let node_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+    let invoice = InvoiceBuilder::new(Currency::Bitcoin)
+        .amount_milli_satoshis(amount_msat)
+        .description_hash(sha256::Hash::hash(description.as_bytes()))
+        .payment_hash(sha256::Hash::hash(&n.to_be_bytes()))
+        .payment_secret(PaymentSecret([7; 32]))
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(144)
+        .build_signed(|hash| SECP256K1.sign_ecdsa_recoverable(hash, &node_key))
+        .unwrap();
+
+    Json(json!({ "pr": invoice.to_string(), "routes": [] }))
+}
@@ -0,0 +1,392 @@
+//! Offline harness for end-to-end tests: the gateway router backed by a
+//! `FakeWallet`, paying a `MockUpstream` that shares the same `FakeMint`, or
+//! serving as the paid provider in front of one.
+//!
+//! Needs a Postgres server. `DATABASE_URL` must point at a database the test
+//! user can connect to; every gateway gets its own scratch database created
+//! next to it and dropped with the gateway, whether its test passes or not.
+//! Tests fail when `DATABASE_URL` is not set rather than pass without
+//! running.
+
+#![allow(dead_code)]
+
+pub mod fake_wallet;
+pub mod lnurl;
+pub mod upstream;
+
+use cdk::nuts::SecretKey;
+use fake_wallet::{FAKE_MINT_URL, FAKE_P2PK_PUBKEY, FakeMint, FakeWallet};
+use gateway::{
+    app,
+    connection::{
+        GatewayMode, MintPolicySettings, PaymentSettings, ProviderSettings, RebalanceSettings,
+        TopupSettings, TransferSettings, WithdrawSettings,
+    },
+    crypto::SecretCipher,
+    db::{
+        credit::{Credit, CreditFilter, get_credit_token, get_credits},
+        listing::ListOptions,
+        request::{Request, RequestFilter, get_requests},
+        server_config::create_config,
+        transaction::{Transaction, TransactionFilter, get_transactions},
+    },
+    mint_policy::MintPolicy,
+    models::AppState,
+    pricing::PricingEngine,
+    rebalance::Rebalancer,
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::{
+    Connection, PgConnection, PgPool,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use tokio::{net::TcpListener, sync::RwLock};
+use upstream::MockUpstream;
+use uuid::Uuid;
+use wallet::models::ServerConfig;
+
+pub const UPSTREAM_API_KEY: &str = "sk-upstream-test";
+const ENCRYPTION_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+/// A chat completion request for the mock model.
+pub fn chat(stream: bool) -> serde_json::Value {
+    serde_json::json!({
+        "model": "mock-model",
+        "messages": [{ "role": "user", "content": "Hi" }],
+        "stream": stream,
+    })
+}
+
+pub struct TestGateway {
+    pub url: String,
+    pub client: reqwest::Client,
+    pub pool: PgPool,
+    pub mint: FakeMint,
+    pub wallet: FakeWallet,
+    pub upstream: MockUpstream,
+    _database: ScratchDatabase,
+}
+
+/// The database of one gateway. It is dropped along with the gateway, also
+/// when a test fails before `shutdown`.
+struct ScratchDatabase {
+    admin: PgConnectOptions,
+    name: String,
+}
+
+impl Drop for ScratchDatabase {
+    fn drop(&mut self) {
+        let admin = self.admin.clone();
+        let name = self.name.clone();
+        // `drop` can't await, and may run on the test's own runtime.
+        let dropped = std::thread::spawn(move || -> anyhow::Result<()> {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(async {
+                    let mut connection = PgConnection::connect_with(&admin).await?;
+                    sqlx::query(&format!(r#"DROP DATABASE IF EXISTS "{name}" WITH (FORCE)"#))
+                        .execute(&mut connection)
+                        .await?;
+                    Ok(())
+                })
+        })
+        .join();
+        if !matches!(dropped, Ok(Ok(()))) {
+            eprintln!("Failed to drop test database {}", self.name);
+        }
+    }
+}
+
+impl TestGateway {
+    /// Starts a gateway whose wallet holds `balance` sats, paying a mock
+    /// provider.
+    pub async fn start(balance: i64) -> Self {
+        Self::start_with(balance, PaymentSettings::default()).await
+    }
+
+    /// Like `start`, with `payments` instead of the default payment settings.
+    pub async fn start_with(balance: i64, payments: PaymentSettings) -> Self {
+        let mint = FakeMint::default();
+        let upstream = MockUpstream::start(mint.clone()).await;
+        let endpoint = upstream.url.clone();
+        Self::launch(
+            GatewayMode::Client,
+            mint,
+            balance,
+            upstream,
+            endpoint,
+            payments,
+        )
+        .await
+    }
+
+    /// Starts a gateway in provider mode in front of a mock of OpenAI.
+    pub async fn start_provider(balance: i64) -> Self {
+        let mint = FakeMint::default();
+        let upstream = MockUpstream::start_free(mint.clone()).await;
+        let endpoint = upstream.url.clone();
+        Self::launch(
+            GatewayMode::Provider,
+            mint,
+            balance,
+            upstream,
+            endpoint,
+            PaymentSettings::default(),
+        )
+        .await
+    }
+
+    /// Starts a gateway in client mode paying `provider`, with ecash from the
+    /// same mint.
+    pub async fn start_client_of(provider: &TestGateway, balance: i64) -> Self {
+        Self::launch(
+            GatewayMode::Client,
+            provider.mint.clone(),
+            balance,
+            provider.upstream.clone(),
+            provider.url.clone(),
+            PaymentSettings::default(),
+        )
+        .await
+    }
+
+    async fn launch(
+        mode: GatewayMode,
+        mint: FakeMint,
+        balance: i64,
+        upstream: MockUpstream,
+        endpoint: String,
+        payments: PaymentSettings,
+    ) -> Self {
+        let url = std::env::var("DATABASE_URL")
+            .expect("DATABASE_URL must point at a Postgres database to run the gateway tests");
+        let admin = PgConnectOptions::from_str(&url).expect("Invalid DATABASE_URL");
+        let database = format!("otrta_test_{}", Uuid::new_v4().simple());
+
+        let mut connection = PgConnection::connect_with(&admin)
+            .await
+            .expect("Failed to connect to DATABASE_URL");
+        sqlx::query(&format!(r#"CREATE DATABASE "{}""#, database))
+            .execute(&mut connection)
+            .await
+            .expect("Failed to create test database");
+        connection.close().await.ok();
+
+        let pool = PgPoolOptions::new()
+            .connect_with(admin.clone().database(&database))
+            .await
+            .expect("Failed to connect to test database");
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .expect("Failed to run migrations");
+
+        let cipher = SecretCipher::new(&SecretString::from(ENCRYPTION_KEY)).unwrap();
+        let wallet = FakeWallet::new(mint.clone(), balance);
+
+        create_config(
+            &pool,
+            &cipher,
+            &ServerConfig {
+                endpoint,
+                api_key: UPSTREAM_API_KEY.to_string(),
+                accepted_mints: Vec::new(),
+                p2pk_pubkey: Some(FAKE_P2PK_PUBKEY.to_string()),
+            },
+        )
+        .await
+        .expect("Failed to store provider config");
+
+        // Only providers sign their charges.
+        let signer = matches!(mode, GatewayMode::Provider).then(SecretKey::generate);
+        let state = Arc::new(AppState {
+            db: pool.clone(),
+            users: RwLock::new(HashMap::new()),
+            organizations: RwLock::new(HashMap::new()),
+            api_keys: RwLock::new(HashMap::new()),
+            models: RwLock::new(HashMap::new()),
+            providers: RwLock::new(HashMap::new()),
+            credits: RwLock::new(HashMap::new()),
+            wallet: wallet.clone(),
+            cipher,
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            pricing: PricingEngine::new(ProviderSettings::default(), signer),
+            rebalancer: Rebalancer::new(RebalanceSettings {
+                auto_swap: true,
+                ..RebalanceSettings::default()
+            }),
+            mint_policy: MintPolicy::new(
+                MintPolicySettings::default(),
+                &[FAKE_MINT_URL.to_string()],
+            ),
+            topup: TopupSettings {
+                poll_interval_ms: 50,
+                ..TopupSettings::default()
+            },
+            withdraw: WithdrawSettings {
+                poll_interval_ms: 50,
+                allow_insecure_lnurl: true,
+            },
+            transfer: TransferSettings { lock_secs: 1 },
+            payments,
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let gateway_url = format!("http://{}", listener.local_addr().unwrap());
+        let router = match mode {
+            GatewayMode::Client => app::router(state),
+            GatewayMode::Provider => app::provider_router(state),
+        };
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        Self {
+            url: gateway_url,
+            client: reqwest::Client::new(),
+            pool,
+            mint,
+            wallet,
+            upstream,
+            _database: ScratchDatabase {
+                admin,
+                name: database,
+            },
+        }
+    }
+
+    pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}{}", self.url, path))
+            .bearer_auth("sk-client-test")
+    }
+
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.get(format!("{}{}", self.url, path))
+    }
+
+    /// Restricts the provider to tokens from `mints`, through the admin API.
+    pub async fn accept_mints(&self, mints: &[&str]) -> reqwest::StatusCode {
+        self.client
+            .post(format!("{}/api/server-config", self.url))
+            .json(&serde_json::json!({
+                "endpoint": self.upstream.url,
+                "api_key": UPSTREAM_API_KEY,
+                "accepted_mints": mints,
+                "p2pk_pubkey": FAKE_P2PK_PUBKEY,
+            }))
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    /// `/api/mints/{mint}` followed by `rest`, with the mint URL encoded.
+    pub fn mint_api(&self, mint: &str, rest: &[&str]) -> reqwest::Url {
+        let mut url = reqwest::Url::parse(&self.url).unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .extend(["api", "mints", mint])
+            .extend(rest);
+        url
+    }
+
+    /// Sets the rule of `mint` through the admin API.
+    pub async fn set_mint_rule(&self, mint: &str, rule: serde_json::Value) -> reqwest::Response {
+        self.client
+            .put(self.mint_api(mint, &[]))
+            .json(&rule)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn transactions(&self) -> Vec<Transaction> {
+        get_transactions(
+            &self.pool,
+            &TransactionFilter::default(),
+            &ListOptions::default(),
+        )
+        .await
+        .unwrap()
+        .data
+    }
+
+    /// Makes booking transactions in `directions` (`Incoming`, `Outgoing`)
+    /// fail from now on, as if the database went away right then.
+    pub async fn fail_bookings(&self, directions: &[&str]) {
+        let directions = directions
+            .iter()
+            .map(|direction| format!("'{direction}'"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        sqlx::raw_sql(&format!(
+            r#"
+            CREATE FUNCTION fail_booking() RETURNS trigger AS $$
+            BEGIN
+                RAISE EXCEPTION 'booking refused';
+            END
+            $$ LANGUAGE plpgsql;
+            CREATE TRIGGER fail_booking BEFORE INSERT ON transactions
+                FOR EACH ROW WHEN (NEW.direction::text IN ({directions}))
+                EXECUTE FUNCTION fail_booking();
+            "#
+        ))
+        .execute(&self.pool)
+        .await
+        .unwrap();
+    }
+
+    pub async fn credits(&self) -> Vec<Credit> {
+        get_credits(
+            &self.pool,
+            &CreditFilter::default(),
+            &ListOptions::default(),
+        )
+        .await
+        .unwrap()
+        .data
+    }
+
+    /// The cipher the gateway keeps secrets with.
+    pub fn cipher(&self) -> SecretCipher {
+        SecretCipher::new(&SecretString::from(ENCRYPTION_KEY)).unwrap()
+    }
+
+    /// The token kept for credit `id`, which the API never hands out.
+    pub async fn credit_token(&self, id: Uuid) -> String {
+        get_credit_token(&self.pool, &self.cipher(), id)
+            .await
+            .unwrap()
+            .expect("Unknown credit")
+            .expose_secret()
+            .to_string()
+    }
+
+    pub async fn requests(&self) -> Vec<Request> {
+        get_requests(&self.pool, &RequestFilter::default(), None, None)
+            .await
+            .unwrap()
+            .data
+    }
+
+    /// Waits for the single recorded request to be completed, which happens
+    /// once the response body has been fully relayed.
+    pub async fn completed_request(&self) -> Request {
+        for _ in 0..50 {
+            if let [request] = self.requests().await.as_slice()
+                && request.latency_ms.is_some()
+            {
+                return request.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Request was never completed");
+    }
+
+    /// Closes the connections before the database goes with the gateway.
+    pub async fn shutdown(self) {
+        self.pool.close().await;
+    }
+}
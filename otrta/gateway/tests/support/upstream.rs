@@ -0,0 +1,287 @@
+use super::fake_wallet::FakeMint;
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use futures_util::{StreamExt, stream};
+use serde_json::{Value, json};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::net::TcpListener;
+
+/// Change handed back with a response.
+#[derive(Clone, Copy, Debug)]
+pub enum Change {
+    None,
+    /// A token in `X-CHANGE-SATS`, redeemed by the gateway's wallet.
+    Sats(u64),
+    /// A token in `X-CHANGE-TOKEN` with its value in `X-CHANGE-AMOUNT`,
+    /// kept by the gateway as a credit.
+    Token(u64),
+}
+
+/// What the upstream does with the next request.
+#[derive(Clone, Debug)]
+pub enum Script {
+    Respond(Change),
+    /// Answers with this status and an error body without taking the payment.
+    Status(StatusCode),
+    /// Answers 200 with change headers that are not valid tokens or amounts.
+    BadChange,
+    /// Answers 200 with change headers that are not even ASCII.
+    NonAsciiChange,
+    /// Sends the headers and one chunk, then drops the connection.
+    Disconnect,
+    /// Waits before answering without change.
+    Stall(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct SeenRequest {
+    pub path: String,
+    pub payment: Option<String>,
+    pub redeemed: Option<u64>,
+    pub authorization: Option<String>,
+    pub max_tokens: Option<u64>,
+}
+
+struct UpstreamState {
+    mint: FakeMint,
+    paid: bool,
+    script: Mutex<VecDeque<Script>>,
+    seen: Mutex<Vec<SeenRequest>>,
+}
+
+pub const USAGE: (i64, i64, i64) = (5, 7, 12);
+pub const STREAM_CHUNKS: [&str; 3] = ["Hel", "lo", "!"];
+pub const MINT_NAME: &str = "Mock Mint";
+
+/// OpenAI-compatible upstream. A paid one charges through `X-PAYMENT-SATS`
+/// and always reports usage; a free one behaves like OpenAI itself. Requests
+/// follow the queued scripts in order and get plain answers once the queue
+/// is empty. It also answers `/v1/info` like a mint.
+#[derive(Clone)]
+pub struct MockUpstream {
+    pub url: String,
+    state: Arc<UpstreamState>,
+}
+
+impl MockUpstream {
+    pub async fn start(mint: FakeMint) -> Self {
+        Self::launch(mint, true).await
+    }
+
+    pub async fn start_free(mint: FakeMint) -> Self {
+        Self::launch(mint, false).await
+    }
+
+    async fn launch(mint: FakeMint, paid: bool) -> Self {
+        let state = Arc::new(UpstreamState {
+            mint,
+            paid,
+            script: Mutex::new(VecDeque::new()),
+            seen: Mutex::new(Vec::new()),
+        });
+        let app = Router::new()
+            .route("/v1/chat/completions", post(chat_completions))
+            .route("/v1/models", get(models))
+            .route("/v1/info", get(mint_info))
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        Self { url, state }
+    }
+
+    pub fn script(&self, script: Script) -> &Self {
+        self.state.script.lock().unwrap().push_back(script);
+        self
+    }
+
+    pub fn seen(&self) -> Vec<SeenRequest> {
+        self.state.seen.lock().unwrap().clone()
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+async fn chat_completions(
+    State(state): State<Arc<UpstreamState>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let script = state
+        .script
+        .lock()
+        .unwrap()
+        .pop_front()
+        .unwrap_or(Script::Respond(Change::None));
+    let payment = header_value(&headers, "X-PAYMENT-SATS");
+
+    let redeemed = match (&script, &payment) {
+        (Script::Status(_), _) | (_, None) => None,
+        (_, Some(token)) => state.mint.redeem(token).ok(),
+    };
+    state.seen.lock().unwrap().push(SeenRequest {
+        path: "/v1/chat/completions".to_string(),
+        payment: payment.clone(),
+        redeemed,
+        authorization: header_value(&headers, header::AUTHORIZATION.as_str()),
+        max_tokens: body["max_tokens"].as_u64(),
+    });
+
+    if let Script::Status(status) = script {
+        return (
+            status,
+            Json(json!({ "error": { "message": "scripted failure", "type": "upstream_error" } })),
+        )
+            .into_response();
+    }
+    if state.paid && redeemed.is_none() {
+        return (
+            StatusCode::PAYMENT_REQUIRED,
+            Json(json!({ "error": { "message": "invalid payment", "type": "payment_error" } })),
+        )
+            .into_response();
+    }
+
+    let streaming = body["stream"].as_bool().unwrap_or(false);
+    let include_usage =
+        state.paid || body["stream_options"]["include_usage"].as_bool() == Some(true);
+    let mut response = match script {
+        Script::Disconnect => {
+            // Pause between items so the head and first chunk reach the
+            // gateway before the connection is torn down.
+            let chunks = stream::iter([
+                Ok(chunk_event(STREAM_CHUNKS[0])),
+                Err(io::Error::other("connection reset")),
+            ])
+            .then(|item| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                item
+            });
+            Response::builder()
+                .header(header::CONTENT_TYPE, "text/event-stream")
+                .body(Body::from_stream(chunks))
+                .unwrap()
+        }
+        Script::Stall(delay) => {
+            tokio::time::sleep(delay).await;
+            completion(streaming, include_usage)
+        }
+        _ => completion(streaming, include_usage),
+    };
+
+    let headers = response.headers_mut();
+    match script {
+        Script::Respond(Change::Sats(amount)) => {
+            headers.insert("X-CHANGE-SATS", state.mint.issue(amount).parse().unwrap());
+        }
+        Script::Respond(Change::Token(amount)) => {
+            headers.insert("X-CHANGE-TOKEN", state.mint.issue(amount).parse().unwrap());
+            headers.insert("X-CHANGE-AMOUNT", amount.into());
+        }
+        Script::BadChange => {
+            headers.insert("X-CHANGE-SATS", "cashuBnot-a-token".parse().unwrap());
+            headers.insert("X-CHANGE-TOKEN", "cashuAnot-a-token".parse().unwrap());
+            headers.insert("X-CHANGE-AMOUNT", "lots".parse().unwrap());
+        }
+        Script::NonAsciiChange => {
+            let value = HeaderValue::from_bytes(b"cashuB\xff\xfe").unwrap();
+            headers.insert("X-CHANGE-SATS", value.clone());
+            headers.insert("X-CHANGE-TOKEN", value.clone());
+            headers.insert("X-CHANGE-AMOUNT", value);
+        }
+        _ => {}
+    }
+
+    response
+}
+
+fn usage() -> Value {
+    json!({
+        "prompt_tokens": USAGE.0,
+        "completion_tokens": USAGE.1,
+        "total_tokens": USAGE.2,
+    })
+}
+
+fn chunk_event(content: &str) -> String {
+    let chunk = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "choices": [{ "index": 0, "delta": { "content": content } }],
+    });
+    format!("data: {}\n\n", chunk)
+}
+
+fn completion(streaming: bool, include_usage: bool) -> Response {
+    if !streaming {
+        return Json(json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": STREAM_CHUNKS.concat() },
+                "finish_reason": "stop",
+            }],
+            "usage": usage(),
+        }))
+        .into_response();
+    }
+
+    let mut events: Vec<Result<String, io::Error>> = STREAM_CHUNKS
+        .iter()
+        .map(|content| Ok(chunk_event(content)))
+        .collect();
+    if include_usage {
+        let last = json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "choices": [],
+            "usage": usage(),
+        });
+        events.push(Ok(format!("data: {}\n\n", last)));
+    }
+    events.push(Ok("data: [DONE]\n\n".to_string()));
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .body(Body::from_stream(stream::iter(events)))
+        .unwrap()
+}
+
+async fn models() -> Json<Value> {
+    Json(json!({
+        "object": "list",
+        "data": [{ "id": "mock-model", "object": "model", "created": 0, "owned_by": "mock" }],
+    }))
+}
+
+async fn mint_info() -> Json<Value> {
+    Json(json!({
+        "name": MINT_NAME,
+        "version": "mock/0.1.0",
+        "contact": [{ "method": "email", "info": "mint@example.com" }],
+        "nuts": {
+            "4": { "methods": [{ "method": "bolt11", "unit": "sat" }], "disabled": false },
+            "5": { "methods": [{ "method": "bolt11", "unit": "sat" }], "disabled": false },
+            "12": { "supported": true }
+        }
+    }))
+}
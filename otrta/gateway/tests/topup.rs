@@ -0,0 +1,96 @@
+mod support;
+
+use axum::http::StatusCode;
+use gateway::db::transaction::TransactionDirection;
+use serde_json::{Value, json};
+use std::time::Duration;
+use support::{TestGateway, fake_wallet::FAKE_MINT_URL};
+
+async fn topup_status(gateway: &TestGateway, id: &str) -> Value {
+    gateway
+        .get(&format!("/api/wallet/topup/{}", id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn mints_ecash_once_the_invoice_is_paid() {
+    let gateway = TestGateway::start(100).await;
+
+    let response = gateway
+        .post("/api/wallet/topup")
+        .json(&json!({ "amount": 50, "mint": FAKE_MINT_URL }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let topup: Value = response.json().await.unwrap();
+    let id = topup["id"].as_str().unwrap();
+    let bolt11 = topup["payment_request"].as_str().unwrap();
+    assert!(bolt11.starts_with("lnbc"));
+    assert_eq!(topup["status"], "pending");
+    assert_eq!(topup["amount_sats"], 50);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(topup_status(&gateway, id).await["status"], "pending");
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    gateway.wallet.settle_invoice(bolt11);
+    let mut status = Value::Null;
+    for _ in 0..50 {
+        status = topup_status(&gateway, id).await;
+        if status["status"] != "pending" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(status["status"], "paid");
+    assert!(status["paid_at"].is_string());
+    assert_eq!(gateway.wallet.current_balance(), 150);
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 1);
+    let deposit = &transactions[0];
+    assert!(matches!(deposit.direction, TransactionDirection::Incoming));
+    assert_eq!(deposit.amount_msat, 50_000);
+    assert_eq!(
+        deposit.topup_id.map(|id| id.to_string()).as_deref(),
+        Some(id)
+    );
+    assert_eq!(deposit.mint_url.as_deref(), Some(FAKE_MINT_URL));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_invalid_top_ups() {
+    let gateway = TestGateway::start(100).await;
+
+    for payload in [
+        json!({ "amount": 0 }),
+        json!({ "amount": 10_000_000 }),
+        json!({ "amount": 50, "mint": "https://rogue-mint.test" }),
+        json!({ "amount": 50, "mint": "not a url" }),
+    ] {
+        let response = gateway
+            .post("/api/wallet/topup")
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", payload);
+    }
+
+    let response = gateway
+        .get("/api/wallet/topup/00000000-0000-0000-0000-000000000000")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,186 @@
+mod support;
+
+use axum::http::StatusCode;
+use cdk::nuts::SecretKey;
+use chrono::{DateTime, Utc};
+use gateway::db::transaction::TransactionDirection;
+use serde_json::{Value, json};
+use std::time::Duration;
+use support::TestGateway;
+
+async fn send(gateway: &TestGateway, body: Value) -> reqwest::Response {
+    gateway
+        .post("/api/wallet/send")
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn status(gateway: &TestGateway, id: &str) -> Value {
+    gateway
+        .get(&format!("/api/wallet/send/{}", id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn reclaim(gateway: &TestGateway, id: &str) -> reqwest::Response {
+    gateway
+        .post(&format!("/api/wallet/send/{}/reclaim", id))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn follows_a_sent_token_until_it_is_claimed() {
+    let gateway = TestGateway::start(100).await;
+
+    let response = send(&gateway, json!({ "amount": 30 })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let sent: Value = response.json().await.unwrap();
+    assert_eq!(sent["status"], "pending");
+    assert_eq!(sent["amount_sats"], 30);
+    let token = sent["token"].as_str().unwrap();
+    assert!(token.starts_with("cashuB"));
+    assert_eq!(gateway.wallet.current_balance(), 70);
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 1);
+    assert!(matches!(
+        transactions[0].direction,
+        TransactionDirection::Outgoing
+    ));
+    assert_eq!(transactions[0].amount_msat, 30_000);
+    assert_eq!(transactions[0].token_fingerprint, sent["token_fingerprint"]);
+    let id = sent["id"].as_str().unwrap();
+    assert_eq!(
+        transactions[0]
+            .transfer_id
+            .map(|id| id.to_string())
+            .as_deref(),
+        Some(id)
+    );
+
+    // The token is only handed out once.
+    let transfer = status(&gateway, id).await;
+    assert_eq!(transfer["status"], "pending");
+    assert!(transfer.get("token").is_none());
+
+    gateway.mint.redeem(token).unwrap();
+    let transfer = status(&gateway, id).await;
+    assert_eq!(transfer["status"], "claimed");
+    assert!(transfer["settled_at"].is_string());
+
+    let response = reclaim(&gateway, id).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(gateway.wallet.current_balance(), 70);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn reclaims_tokens_nobody_claimed() {
+    let gateway = TestGateway::start(100).await;
+
+    let sent: Value = send(&gateway, json!({ "amount": 25 }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = sent["id"].as_str().unwrap();
+    assert_eq!(gateway.wallet.current_balance(), 75);
+
+    let response = reclaim(&gateway, id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let transfer: Value = response.json().await.unwrap();
+    assert_eq!(transfer["status"], "reclaimed");
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 2);
+    let incoming = transactions
+        .iter()
+        .find(|tx| matches!(tx.direction, TransactionDirection::Incoming))
+        .unwrap();
+    assert_eq!(incoming.amount_msat, 25_000);
+    assert_eq!(
+        incoming.transfer_id.map(|id| id.to_string()).as_deref(),
+        Some(id)
+    );
+
+    // A token is reclaimed once and can't be claimed afterwards.
+    let response = reclaim(&gateway, id).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(
+        gateway
+            .mint
+            .redeem(sent["token"].as_str().unwrap())
+            .is_err()
+    );
+    assert_eq!(status(&gateway, id).await["status"], "reclaimed");
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn locks_tokens_and_sends_them_over_nostr() {
+    let gateway = TestGateway::start(100).await;
+    let pubkey = SecretKey::generate().public_key().to_hex();
+    let npub =
+        bech32::encode::<bech32::Bech32>(bech32::Hrp::parse("npub").unwrap(), &[7; 32]).unwrap();
+
+    let response = send(
+        &gateway,
+        json!({ "amount": 10, "lock": pubkey, "npub": npub }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let sent: Value = response.json().await.unwrap();
+    assert_eq!(sent["lock_pubkey"], pubkey.as_str());
+    assert_eq!(sent["npub"], npub.as_str());
+    let sends = gateway.wallet.sends();
+    assert_eq!(sends[0].lock.as_deref(), Some(pubkey.as_str()));
+    assert_eq!(sends[0].npub.as_deref(), Some(npub.as_str()));
+    // The gateway under test keeps locks for a second.
+    let locked_until: DateTime<Utc> = sent["locked_until"].as_str().unwrap().parse().unwrap();
+    assert_eq!(sends[0].locktime, Some(locked_until.timestamp()));
+    assert!(locked_until > Utc::now());
+
+    // Only the key's holder can redeem a locked token, until the lock runs
+    // out.
+    let id = sent["id"].as_str().unwrap();
+    let response = reclaim(&gateway, id).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let error = |response: reqwest::Response| async move {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = response.json().await.unwrap();
+        body["error"].as_str().unwrap().to_string()
+    };
+    let response = send(&gateway, json!({ "amount": 10, "lock": "02abc" })).await;
+    assert_eq!(error(response).await, "02abc is not a valid public key");
+    let response = send(&gateway, json!({ "amount": 10, "npub": "npub1nope" })).await;
+    assert_eq!(error(response).await, "npub1nope is not a valid npub");
+    let response = send(&gateway, json!({ "amount": 0 })).await;
+    assert_eq!(error(response).await, "Amount must be positive");
+    let response = send(&gateway, json!({ "amount": 500 })).await;
+    assert_eq!(
+        error(response).await,
+        "Sending needs 500 sat, only 90 sat are available"
+    );
+    assert_eq!(gateway.wallet.sends().len(), 1);
+    assert_eq!(gateway.wallet.current_balance(), 90);
+
+    let wait = (locked_until - Utc::now()).to_std().unwrap_or_default();
+    tokio::time::sleep(wait + Duration::from_millis(100)).await;
+    let response = reclaim(&gateway, id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    gateway.shutdown().await;
+}
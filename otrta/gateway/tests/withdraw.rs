@@ -0,0 +1,215 @@
+mod support;
+
+use axum::http::StatusCode;
+use gateway::db::transaction::TransactionDirection;
+use serde_json::{Value, json};
+use std::time::Duration;
+use support::{
+    TestGateway,
+    fake_wallet::{FAKE_MINT_URL, fake_invoice},
+};
+
+async fn quote(gateway: &TestGateway, bolt11: &str) -> reqwest::Response {
+    gateway
+        .post("/api/wallet/withdraw")
+        .json(&json!({ "bolt11": bolt11 }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn confirm(gateway: &TestGateway, id: &str) -> reqwest::Response {
+    gateway
+        .post(&format!("/api/wallet/withdraw/{}/confirm", id))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn status(gateway: &TestGateway, id: &str) -> Value {
+    gateway
+        .get(&format!("/api/wallet/withdraw/{}", id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Waits for withdrawal `id` to leave `pending`.
+async fn settled(gateway: &TestGateway, id: &str) -> Value {
+    let mut withdrawal = Value::Null;
+    for _ in 0..50 {
+        withdrawal = status(gateway, id).await;
+        if withdrawal["status"] != "pending" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    withdrawal
+}
+
+#[tokio::test]
+async fn pays_an_invoice_once_the_quote_is_confirmed() {
+    let gateway = TestGateway::start(100).await;
+    gateway.wallet.set_melt_fees(3, 1);
+
+    let response = quote(&gateway, &fake_invoice(40)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let withdrawal: Value = response.json().await.unwrap();
+    assert_eq!(withdrawal["status"], "quoted");
+    assert_eq!(withdrawal["amount_sats"], 40);
+    assert_eq!(withdrawal["fee_reserve_sats"], 3);
+    assert_eq!(withdrawal["mint_url"], FAKE_MINT_URL);
+    // Quoting doesn't pay anything.
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    let id = withdrawal["id"].as_str().unwrap();
+    let response = confirm(&gateway, id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let withdrawal: Value = response.json().await.unwrap();
+    assert_eq!(withdrawal["status"], "paid");
+    assert_eq!(withdrawal["fee_paid_sats"], 1);
+    assert!(withdrawal["preimage"].is_string());
+    assert_eq!(gateway.wallet.current_balance(), 59);
+
+    let transactions = gateway.transactions().await;
+    assert_eq!(transactions.len(), 1);
+    let payment = &transactions[0];
+    assert!(matches!(payment.direction, TransactionDirection::Outgoing));
+    assert_eq!(payment.amount_msat, 40_000);
+    assert_eq!(payment.fee_msat, Some(1_000));
+    assert_eq!(
+        payment.withdrawal_id.map(|id| id.to_string()).as_deref(),
+        Some(id)
+    );
+
+    // A quote is paid at most once.
+    assert_eq!(
+        confirm(&gateway, id).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(gateway.wallet.current_balance(), 59);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn follows_payments_still_in_flight() {
+    let gateway = TestGateway::start(100).await;
+    gateway.wallet.hold_payments(true);
+
+    let withdrawal: Value = quote(&gateway, &fake_invoice(40))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = withdrawal["id"].as_str().unwrap();
+    let withdrawal: Value = confirm(&gateway, id).await.json().await.unwrap();
+    assert_eq!(withdrawal["status"], "pending");
+    assert!(gateway.transactions().await.is_empty());
+
+    gateway.wallet.settle_payments();
+    let status = settled(&gateway, id).await;
+    assert_eq!(status["status"], "paid");
+    assert_eq!(status["fee_paid_sats"], 1);
+    assert_eq!(gateway.transactions().await[0].fee_msat, Some(1_000));
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn follows_payments_whose_answer_was_lost() {
+    let gateway = TestGateway::start(100).await;
+    gateway.wallet.lose_payment_responses(true);
+
+    let withdrawal: Value = quote(&gateway, &fake_invoice(40))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = withdrawal["id"].as_str().unwrap();
+    let withdrawal: Value = confirm(&gateway, id).await.json().await.unwrap();
+    // The mint may have paid, so it isn't given up.
+    assert_eq!(withdrawal["status"], "pending");
+
+    let status = settled(&gateway, id).await;
+    assert_eq!(status["status"], "paid");
+    assert_eq!(gateway.wallet.current_balance(), 59);
+    assert_eq!(gateway.transactions().await.len(), 1);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn books_no_fee_when_the_mint_does_not_report_it() {
+    let gateway = TestGateway::start(100).await;
+    gateway.wallet.hold_payments(true);
+    gateway.wallet.hide_payment_fees(true);
+
+    let withdrawal: Value = quote(&gateway, &fake_invoice(40))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = withdrawal["id"].as_str().unwrap();
+    confirm(&gateway, id).await;
+    gateway.wallet.settle_payments();
+
+    let status = settled(&gateway, id).await;
+    assert_eq!(status["status"], "paid");
+    assert_eq!(status["fee_paid_sats"], Value::Null);
+    assert_eq!(gateway.transactions().await[0].fee_msat, None);
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn stops_asking_a_mint_that_keeps_failing() {
+    let gateway = TestGateway::start(100).await;
+    gateway.wallet.hold_payments(true);
+    gateway.wallet.fail_payment_checks(true);
+
+    let withdrawal: Value = quote(&gateway, &fake_invoice(40))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = withdrawal["id"].as_str().unwrap();
+    confirm(&gateway, id).await;
+
+    // Eight checks, backing off up to 16 poll intervals apart.
+    tokio::time::sleep(Duration::from_secs(6)).await;
+    let checks = gateway.wallet.payment_checks();
+    assert_eq!(checks, 8);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(gateway.wallet.payment_checks(), checks);
+    // Nothing says the payment failed, so it is left for the next start.
+    assert_eq!(status(&gateway, id).await["status"], "pending");
+
+    gateway.shutdown().await;
+}
+
+#[tokio::test]
+async fn refuses_withdrawals_the_wallet_cannot_cover() {
+    let gateway = TestGateway::start(100).await;
+
+    // The fee reserve has to be held on top of the amount.
+    let response = quote(&gateway, &fake_invoice(99)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "Paying needs 101 sat including the fee reserve, only 100 sat are available"
+    );
+
+    let response = quote(&gateway, "not an invoice").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = confirm(&gateway, "00000000-0000-0000-0000-000000000000").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(gateway.wallet.current_balance(), 100);
+
+    gateway.shutdown().await;
+}
@@ -0,0 +1,137 @@
+use super::base::CashuWalletApi;
+use super::cdk::CdkWallet;
+use super::client::CashuWalletClient;
+use super::models::*;
+use anyhow::Result;
+
+/// The wallet implementation picked in configuration.
+#[derive(Clone)]
+pub enum WalletBackend {
+    Nutshell(CashuWalletClient),
+    Cdk(CdkWallet),
+}
+
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self {
+            WalletBackend::Nutshell(wallet) => wallet.$method($($arg),*).await,
+            WalletBackend::Cdk(wallet) => wallet.$method($($arg),*).await,
+        }
+    };
+}
+
+impl CashuWalletApi for WalletBackend {
+    async fn pay_invoice(&self, bolt11: &str, mint: Option<&str>) -> Result<PaymentResponse> {
+        dispatch!(self.pay_invoice(bolt11, mint))
+    }
+
+    async fn melt_quote(&self, bolt11: &str, mint: Option<&str>) -> Result<MeltQuote> {
+        dispatch!(self.melt_quote(bolt11, mint))
+    }
+
+    async fn payment_state(
+        &self,
+        payment_hash: Option<&str>,
+        mint: Option<&str>,
+    ) -> Result<PaymentStatus> {
+        dispatch!(self.payment_state(payment_hash, mint))
+    }
+
+    async fn create_invoice(&self, amount: i64, mint: Option<&str>) -> Result<InvoiceResponse> {
+        dispatch!(self.create_invoice(amount, mint))
+    }
+
+    async fn invoice_state(
+        &self,
+        payment_request: Option<&str>,
+        mint: Option<&str>,
+    ) -> Result<PaymentStatus> {
+        dispatch!(self.invoice_state(payment_request, mint))
+    }
+
+    async fn lightning_balance(&self) -> Result<StatusResponse> {
+        dispatch!(self.lightning_balance())
+    }
+
+    async fn swap(
+        &self,
+        amount: i64,
+        outgoing_mint: &str,
+        incoming_mint: &str,
+        max_fee: Option<i64>,
+    ) -> Result<SwapResponse> {
+        dispatch!(self.swap(amount, outgoing_mint, incoming_mint, max_fee))
+    }
+
+    async fn balance(&self) -> Result<BalanceResponse> {
+        dispatch!(self.balance())
+    }
+
+    async fn send(
+        &self,
+        amount: i64,
+        nostr: Option<&str>,
+        lock: Option<&str>,
+        locktime: Option<i64>,
+        mint: Option<&str>,
+        offline: Option<bool>,
+    ) -> Result<SendResponse> {
+        dispatch!(self.send(amount, nostr, lock, locktime, mint, offline))
+    }
+
+    async fn receive(
+        &self,
+        token: Option<&str>,
+        nostr: Option<bool>,
+        all: Option<bool>,
+    ) -> Result<ReceiveResponse> {
+        dispatch!(self.receive(token, nostr, all))
+    }
+
+    async fn token_state(&self, token: &str) -> Result<TokenState> {
+        dispatch!(self.token_state(token))
+    }
+
+    async fn verify_dleq(&self, token: &str) -> Result<DleqStatus> {
+        dispatch!(self.verify_dleq(token))
+    }
+
+    async fn burn(
+        &self,
+        token: Option<&str>,
+        all: Option<bool>,
+        force: Option<bool>,
+        delete: Option<&str>,
+        mint: Option<&str>,
+    ) -> Result<BurnResponse> {
+        dispatch!(self.burn(token, all, force, delete, mint))
+    }
+
+    async fn pending(&self, number: Option<i64>, offset: Option<i64>) -> Result<PendingResponse> {
+        dispatch!(self.pending(number, offset))
+    }
+
+    async fn lock(&self) -> Result<LockResponse> {
+        dispatch!(self.lock())
+    }
+
+    async fn locks(&self) -> Result<LocksResponse> {
+        dispatch!(self.locks())
+    }
+
+    async fn invoices(&self) -> Result<InvoicesResponse> {
+        dispatch!(self.invoices())
+    }
+
+    async fn wallets(&self) -> Result<WalletsResponse> {
+        dispatch!(self.wallets())
+    }
+
+    async fn restore(&self, to: i64) -> Result<RestoreResponse> {
+        dispatch!(self.restore(to))
+    }
+
+    async fn info(&self) -> Result<InfoResponse> {
+        dispatch!(self.info())
+    }
+}
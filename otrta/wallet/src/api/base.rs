@@ -1,57 +1,83 @@
 use super::models::*;
 use anyhow::Result;
 
-pub trait CashuWalletApi {
+/// A Cashu wallet the gateway can pay with. Implemented by the nutshell HTTP
+/// client and the in-process CDK wallet.
+pub trait CashuWalletApi: Send + Sync + 'static {
     fn pay_invoice(
         &self,
         bolt11: &str,
         mint: Option<&str>,
-    ) -> impl Future<Output = Result<PaymentResponse>>;
+    ) -> impl Future<Output = Result<PaymentResponse>> + Send;
+
+    /// Asks the mint what paying `bolt11` costs, without paying it. A
+    /// following `pay_invoice` of the same invoice uses this quote when the
+    /// wallet can.
+    fn melt_quote(
+        &self,
+        bolt11: &str,
+        mint: Option<&str>,
+    ) -> impl Future<Output = Result<MeltQuote>> + Send;
 
     fn payment_state(
         &self,
         payment_hash: Option<&str>,
         mint: Option<&str>,
-    ) -> impl Future<Output = Result<PaymentStatus>>;
+    ) -> impl Future<Output = Result<PaymentStatus>> + Send;
 
     fn create_invoice(
         &self,
         amount: i64,
         mint: Option<&str>,
-    ) -> impl Future<Output = Result<InvoiceResponse>>;
+    ) -> impl Future<Output = Result<InvoiceResponse>> + Send;
 
     fn invoice_state(
         &self,
         payment_request: Option<&str>,
         mint: Option<&str>,
-    ) -> impl Future<Output = Result<PaymentStatus>>;
+    ) -> impl Future<Output = Result<PaymentStatus>> + Send;
 
-    fn lightning_balance(&self) -> impl Future<Output = Result<StatusResponse>>;
+    fn lightning_balance(&self) -> impl Future<Output = Result<StatusResponse>> + Send;
 
+    /// Fails before paying anything when the outgoing mint may take more
+    /// than `max_fee` sats in Lightning fees. `None` lets it take what it
+    /// asks for.
     fn swap(
         &self,
         amount: i64,
         outgoing_mint: &str,
         incoming_mint: &str,
-    ) -> impl Future<Output = Result<SwapResponse>>;
+        max_fee: Option<i64>,
+    ) -> impl Future<Output = Result<SwapResponse>> + Send;
 
-    fn balance(&self) -> impl Future<Output = Result<BalanceResponse>>;
+    fn balance(&self) -> impl Future<Output = Result<BalanceResponse>> + Send;
 
+    /// A token locked to `lock` (NUT-11) can be taken back with the wallet's
+    /// own key from `locktime`, a Unix time, on.
     fn send(
         &self,
         amount: i64,
         nostr: Option<&str>,
         lock: Option<&str>,
+        locktime: Option<i64>,
         mint: Option<&str>,
         offline: Option<bool>,
-    ) -> impl Future<Output = Result<SendResponse>>;
+    ) -> impl Future<Output = Result<SendResponse>> + Send;
+
+    /// Asks the token's mint which of its proofs are spent, e.g. to see
+    /// whether a sent token was claimed.
+    fn token_state(&self, token: &str) -> impl Future<Output = Result<TokenState>> + Send;
+
+    /// Checks that the proofs of a token were signed by its mint (NUT-12),
+    /// without asking the mint about them.
+    fn verify_dleq(&self, token: &str) -> impl Future<Output = Result<DleqStatus>> + Send;
 
     fn receive(
         &self,
         token: Option<&str>,
         nostr: Option<bool>,
         all: Option<bool>,
-    ) -> impl Future<Output = Result<ReceiveResponse>>;
+    ) -> impl Future<Output = Result<ReceiveResponse>> + Send;
 
     fn burn(
         &self,
@@ -60,22 +86,22 @@ pub trait CashuWalletApi {
         force: Option<bool>,
         delete: Option<&str>,
         mint: Option<&str>,
-    ) -> impl Future<Output = Result<BurnResponse>>;
+    ) -> impl Future<Output = Result<BurnResponse>> + Send;
 
     fn pending(
         &self,
         number: Option<i64>,
         offset: Option<i64>,
-    ) -> impl Future<Output = Result<PendingResponse>>;
+    ) -> impl Future<Output = Result<PendingResponse>> + Send;
 
-    fn lock(&self) -> impl Future<Output = Result<LockResponse>>;
-    fn locks(&self) -> impl Future<Output = Result<LocksResponse>>;
+    fn lock(&self) -> impl Future<Output = Result<LockResponse>> + Send;
+    fn locks(&self) -> impl Future<Output = Result<LocksResponse>> + Send;
 
-    fn invoices(&self) -> impl Future<Output = Result<InvoicesResponse>>;
+    fn invoices(&self) -> impl Future<Output = Result<InvoicesResponse>> + Send;
 
-    fn wallets(&self) -> impl Future<Output = Result<WalletsResponse>>;
+    fn wallets(&self) -> impl Future<Output = Result<WalletsResponse>> + Send;
 
-    fn restore(&self, to: i64) -> impl Future<Output = Result<RestoreResponse>>;
+    fn restore(&self, to: i64) -> impl Future<Output = Result<RestoreResponse>> + Send;
 
-    fn info(&self) -> impl Future<Output = Result<InfoResponse>>;
+    fn info(&self) -> impl Future<Output = Result<InfoResponse>> + Send;
 }
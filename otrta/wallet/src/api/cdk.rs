@@ -0,0 +1,724 @@
+use super::base::CashuWalletApi;
+use super::models::*;
+use anyhow::{Context, Result, anyhow, bail};
+use cdk::{
+    Amount as CdkAmount, Wallet,
+    amount::SplitTarget,
+    cdk_database::{self, WalletDatabase},
+    mint_url::MintUrl,
+    nuts::{
+        Conditions, CurrencyUnit, MeltQuoteState, MintQuoteState as CdkMintQuoteState, PublicKey,
+        SecretKey, SpendingConditions, State, Token, nut00::ProofsMethods,
+    },
+    wallet::{
+        MultiMintWallet, ReceiveOptions, SendKind, SendOptions, types::MintQuote as CdkMintQuote,
+        types::WalletKey,
+    },
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+/// `CashuWalletApi` backed by an in-process CDK wallet, so the gateway can
+/// run without a nutshell container. Holds one wallet per mint, all in sats.
+#[derive(Clone)]
+pub struct CdkWallet {
+    wallet: MultiMintWallet,
+    default_mint: MintUrl,
+    /// Melt quotes handed out by `melt_quote`, by invoice, for
+    /// `pay_invoice` to pay with.
+    melt_quotes: Arc<Mutex<HashMap<String, (MintUrl, String)>>>,
+    /// Key tokens locked to this wallet (NUT-11) are redeemed with, derived
+    /// from the seed.
+    p2pk_key: SecretKey,
+}
+
+impl CdkWallet {
+    /// Opens a wallet for every mint in `mint_urls`. The first one is used
+    /// when a call doesn't name a mint.
+    pub async fn new(
+        mint_urls: &[String],
+        localstore: Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync>,
+        seed: &[u8],
+    ) -> Result<Self> {
+        let default_mint = mint_urls
+            .first()
+            .ok_or_else(|| anyhow!("The CDK wallet needs at least one mint URL"))?;
+        let default_mint = MintUrl::from_str(default_mint)?;
+
+        let mut wallets = Vec::with_capacity(mint_urls.len());
+        for mint_url in mint_urls {
+            wallets.push(Wallet::new(
+                mint_url,
+                CurrencyUnit::Sat,
+                localstore.clone(),
+                seed,
+                None,
+            )?);
+        }
+
+        let p2pk_key = SecretKey::from_slice(
+            &Sha256::new()
+                .chain_update(b"p2pk")
+                .chain_update(seed)
+                .finalize(),
+        )?;
+
+        Ok(Self {
+            wallet: MultiMintWallet::new(localstore, Arc::from(seed), wallets),
+            default_mint,
+            melt_quotes: Arc::default(),
+            p2pk_key,
+        })
+    }
+
+    async fn wallet_for(&self, mint: Option<&str>) -> Result<Wallet> {
+        let mint_url = match mint {
+            Some(mint) => MintUrl::from_str(mint)?,
+            None => self.default_mint.clone(),
+        };
+        self.wallet
+            .get_wallet(&WalletKey::new(mint_url.clone(), CurrencyUnit::Sat))
+            .await
+            .ok_or_else(|| anyhow!("Unknown mint {}", mint_url))
+    }
+
+    /// Like `wallet_for`, but adds the mint when the wallet doesn't know it.
+    async fn ensure_wallet(&self, mint_url: &MintUrl) -> Result<Wallet> {
+        let key = WalletKey::new(mint_url.clone(), CurrencyUnit::Sat);
+        if let Some(wallet) = self.wallet.get_wallet(&key).await {
+            return Ok(wallet);
+        }
+        self.wallet
+            .create_and_add_wallet(&mint_url.to_string(), CurrencyUnit::Sat, None)
+            .await
+    }
+
+    /// The wallet holding the most funds, used to pay when no mint is given.
+    async fn richest_wallet(&self) -> Result<Wallet> {
+        let mut richest: Option<(CdkAmount, Wallet)> = None;
+        for wallet in self.wallet.get_wallets().await {
+            let balance = wallet.total_balance().await?;
+            if richest.as_ref().is_none_or(|(best, _)| balance > *best) {
+                richest = Some((balance, wallet));
+            }
+        }
+        richest
+            .map(|(_, wallet)| wallet)
+            .ok_or_else(|| anyhow!("No mint configured"))
+    }
+
+    async fn paying_wallet(&self, mint: Option<&str>) -> Result<Wallet> {
+        match mint {
+            Some(_) => self.wallet_for(mint).await,
+            None => self.richest_wallet().await,
+        }
+    }
+
+    /// The wallet and id of the quote `melt_quote` gave for `bolt11`, if it
+    /// hasn't expired and is at `mint` when one is asked for.
+    async fn quoted_melt(
+        &self,
+        bolt11: &str,
+        mint: Option<&str>,
+    ) -> Result<Option<(Wallet, String)>> {
+        let Some((mint_url, quote_id)) = self.melt_quotes.lock().unwrap().remove(bolt11) else {
+            return Ok(None);
+        };
+        if let Some(mint) = mint
+            && MintUrl::from_str(mint)? != mint_url
+        {
+            return Ok(None);
+        }
+
+        let wallet = self.ensure_wallet(&mint_url).await?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        match wallet.localstore.get_melt_quote(&quote_id).await? {
+            Some(quote) if quote.expiry > now => Ok(Some((wallet, quote_id))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn balances(&self) -> Result<HashMap<String, i64>> {
+        let mut balances = HashMap::new();
+        for wallet in self.wallet.get_wallets().await {
+            let balance = wallet.total_balance().await?;
+            balances.insert(wallet.mint_url.to_string(), sats(balance));
+        }
+        Ok(balances)
+    }
+
+    async fn total_balance(&self) -> Result<i64> {
+        Ok(self.balances().await?.values().sum())
+    }
+
+    /// Signs the proofs of `token` that refund to the wallet's P2PK key, so
+    /// that a token it locked to someone else can be taken back once the
+    /// locktime passed. The CDK only signs for the keys a token is locked to.
+    fn sign_refunds(&self, token: &str) -> Result<String> {
+        let decoded = Token::from_str(token)?;
+        let own_key = self.p2pk_key.public_key();
+
+        let mut proofs = decoded.proofs();
+        let mut signed = false;
+        for proof in &mut proofs {
+            let refunds_to_us = SpendingConditions::try_from(&proof.secret)
+                .ok()
+                .and_then(|conditions| conditions.refund_keys())
+                .is_some_and(|keys| keys.contains(&own_key));
+            if refunds_to_us {
+                proof.sign_p2pk(self.p2pk_key.clone())?;
+                signed = true;
+            }
+        }
+        if !signed {
+            return Ok(token.to_string());
+        }
+
+        let unit = decoded.unit().unwrap_or_default();
+        Ok(Token::new(decoded.mint_url()?, proofs, decoded.memo().clone(), unit).to_string())
+    }
+}
+
+fn sats(amount: CdkAmount) -> i64 {
+    u64::from(amount) as i64
+}
+
+fn cdk_amount(amount: i64) -> Result<CdkAmount> {
+    let amount = u64::try_from(amount).context("Amount must not be negative")?;
+    Ok(CdkAmount::from(amount))
+}
+
+fn payment_result(state: MeltQuoteState) -> PaymentResult {
+    match state {
+        MeltQuoteState::Paid => PaymentResult::Success,
+        MeltQuoteState::Failed => PaymentResult::Failed,
+        MeltQuoteState::Pending => PaymentResult::Pending,
+        MeltQuoteState::Unpaid | MeltQuoteState::Unknown => PaymentResult::Unknown,
+    }
+}
+
+fn mint_quote_state(state: CdkMintQuoteState) -> MintQuoteState {
+    match state {
+        CdkMintQuoteState::Unpaid => MintQuoteState::UNPAID,
+        CdkMintQuoteState::Paid => MintQuoteState::PAID,
+        CdkMintQuoteState::Pending => MintQuoteState::PENDING,
+        CdkMintQuoteState::Issued => MintQuoteState::ISSUED,
+    }
+}
+
+fn mint_quote(quote: CdkMintQuote, created_time: i64) -> MintQuote {
+    MintQuote {
+        checking_id: quote.id.clone(),
+        quote: quote.id,
+        method: "bolt11".to_string(),
+        request: quote.request,
+        unit: quote.unit.to_string(),
+        amount: sats(quote.amount),
+        state: mint_quote_state(quote.state),
+        created_time,
+        paid_time: None,
+        expiry: Some(quote.expiry as i64),
+        mint: Some(quote.mint_url.to_string()),
+        privkey: None,
+        pubkey: None,
+    }
+}
+
+impl CashuWalletApi for CdkWallet {
+    async fn pay_invoice(&self, bolt11: &str, mint: Option<&str>) -> Result<PaymentResponse> {
+        let (wallet, quote_id) = match self.quoted_melt(bolt11, mint).await? {
+            Some(quoted) => quoted,
+            None => {
+                let wallet = self.paying_wallet(mint).await?;
+                let quote = wallet.melt_quote(bolt11.to_string(), None).await?;
+                (wallet, quote.id)
+            }
+        };
+        let melted = wallet.melt(&quote_id).await?;
+
+        Ok(PaymentResponse {
+            result: payment_result(melted.state),
+            checking_id: Some(quote_id),
+            fee: Some(Amount {
+                unit: Unit::Sat,
+                amount: sats(melted.fee_paid),
+            }),
+            preimage: melted.preimage,
+            error_message: None,
+        })
+    }
+
+    async fn melt_quote(&self, bolt11: &str, mint: Option<&str>) -> Result<MeltQuote> {
+        let wallet = self.paying_wallet(mint).await?;
+        let quote = wallet.melt_quote(bolt11.to_string(), None).await?;
+        self.melt_quotes.lock().unwrap().insert(
+            bolt11.to_string(),
+            (wallet.mint_url.clone(), quote.id.clone()),
+        );
+
+        Ok(MeltQuote {
+            checking_id: quote.id.clone(),
+            quote: quote.id,
+            method: "bolt11".to_string(),
+            request: quote.request,
+            unit: quote.unit.to_string(),
+            amount: sats(quote.amount),
+            fee_reserve: sats(quote.fee_reserve),
+            state: super::models::MeltQuoteState::UNPAID,
+            created_time: chrono::Utc::now().timestamp(),
+            paid_time: None,
+            fee_paid: None,
+            payment_preimage: None,
+            expiry: Some(quote.expiry as i64),
+            outputs: None,
+            change: None,
+            mint: Some(wallet.mint_url.to_string()),
+        })
+    }
+
+    /// `payment_hash` is the melt quote id returned as `checking_id` by
+    /// `pay_invoice`. The fee of a paid quote is its reserve less the change
+    /// the mint signed (NUT-08); it is unknown when the mint returns none.
+    async fn payment_state(
+        &self,
+        payment_hash: Option<&str>,
+        mint: Option<&str>,
+    ) -> Result<PaymentStatus> {
+        let quote_id = payment_hash.ok_or_else(|| anyhow!("A melt quote id is required"))?;
+        let wallet = self.wallet_for(mint).await?;
+        let status = wallet.melt_quote_status(quote_id).await?;
+        let fee = match (status.state, &status.change) {
+            (MeltQuoteState::Paid, Some(change)) => {
+                let returned: i64 = change.iter().map(|signature| sats(signature.amount)).sum();
+                Some(Amount {
+                    unit: Unit::Sat,
+                    amount: (sats(status.fee_reserve) - returned).max(0),
+                })
+            }
+            _ => None,
+        };
+
+        Ok(PaymentStatus {
+            result: payment_result(status.state),
+            fee,
+            preimage: status.payment_preimage,
+            error_message: None,
+        })
+    }
+
+    async fn create_invoice(&self, amount: i64, mint: Option<&str>) -> Result<InvoiceResponse> {
+        let wallet = self.wallet_for(mint).await?;
+        let quote = wallet.mint_quote(cdk_amount(amount)?, None).await?;
+
+        Ok(InvoiceResponse {
+            ok: true,
+            checking_id: Some(quote.id),
+            payment_request: Some(quote.request),
+            error_message: None,
+        })
+    }
+
+    /// Mints the ecash for the invoice as soon as the mint reports it paid.
+    async fn invoice_state(
+        &self,
+        payment_request: Option<&str>,
+        mint: Option<&str>,
+    ) -> Result<PaymentStatus> {
+        let payment_request =
+            payment_request.ok_or_else(|| anyhow!("A payment request is required"))?;
+        let quote = self
+            .wallet
+            .localstore
+            .get_mint_quotes()
+            .await?
+            .into_iter()
+            .find(|quote| quote.request == payment_request)
+            .ok_or_else(|| anyhow!("Unknown payment request"))?;
+        let wallet = match mint {
+            Some(_) => self.wallet_for(mint).await?,
+            None => self.ensure_wallet(&quote.mint_url).await?,
+        };
+
+        let result = match wallet.mint_quote_state(&quote.id).await?.state {
+            CdkMintQuoteState::Paid => {
+                wallet.mint(&quote.id, SplitTarget::default(), None).await?;
+                PaymentResult::Success
+            }
+            CdkMintQuoteState::Issued => PaymentResult::Success,
+            CdkMintQuoteState::Unpaid | CdkMintQuoteState::Pending => PaymentResult::Pending,
+        };
+
+        Ok(PaymentStatus {
+            result,
+            fee: None,
+            preimage: None,
+            error_message: None,
+        })
+    }
+
+    async fn lightning_balance(&self) -> Result<StatusResponse> {
+        Ok(StatusResponse {
+            balance: self.total_balance().await? as f64,
+            error_message: None,
+        })
+    }
+
+    /// Moves funds between mints over Lightning: the incoming mint issues an
+    /// invoice that the outgoing mint pays by melting ecash.
+    async fn swap(
+        &self,
+        amount: i64,
+        outgoing_mint: &str,
+        incoming_mint: &str,
+        max_fee: Option<i64>,
+    ) -> Result<SwapResponse> {
+        let from = self.wallet_for(Some(outgoing_mint)).await?;
+        let to = self
+            .ensure_wallet(&MintUrl::from_str(incoming_mint)?)
+            .await?;
+
+        let quote = to.mint_quote(cdk_amount(amount)?, None).await?;
+        let created_time = chrono::Utc::now().timestamp();
+        let melt_quote = from.melt_quote(quote.request.clone(), None).await?;
+        let fee_reserve = sats(melt_quote.fee_reserve);
+        if let Some(max_fee) = max_fee
+            && fee_reserve > max_fee
+        {
+            bail!(
+                "Outgoing mint reserves {} sat in fees for the swap, over the limit of {} sat",
+                fee_reserve,
+                max_fee
+            );
+        }
+        let melted = from.melt(&melt_quote.id).await?;
+        if melted.state != MeltQuoteState::Paid {
+            bail!(
+                "Outgoing mint did not pay the swap invoice (state {})",
+                melted.state
+            );
+        }
+
+        let mut quote = quote;
+        quote.state = to.mint_quote_state(&quote.id).await?.state;
+        if quote.state == CdkMintQuoteState::Paid {
+            to.mint(&quote.id, SplitTarget::default(), None).await?;
+            quote.state = CdkMintQuoteState::Issued;
+        }
+
+        let balances = self
+            .balances()
+            .await?
+            .into_iter()
+            .map(|(mint, balance)| (mint, json!({ "available": balance })))
+            .collect();
+
+        Ok(SwapResponse {
+            outgoing_mint: outgoing_mint.to_string(),
+            incoming_mint: incoming_mint.to_string(),
+            mint_quote: mint_quote(quote, created_time),
+            balances,
+        })
+    }
+
+    async fn balance(&self) -> Result<BalanceResponse> {
+        let balances = self.balances().await?;
+
+        Ok(BalanceResponse {
+            balance: balances.values().sum(),
+            keysets: None,
+            mints: Some(
+                balances
+                    .into_iter()
+                    .map(|(mint, balance)| {
+                        (mint, json!({ "available": balance, "balance": balance }))
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// `lock` is a P2PK public key, with or without nutshell's `P2PK:`
+    /// prefix. The wallet's P2PK key is the refund key past `locktime`.
+    #[tracing::instrument(name = "wallet.send", skip(self), err)]
+    async fn send(
+        &self,
+        amount: i64,
+        nostr: Option<&str>,
+        lock: Option<&str>,
+        locktime: Option<i64>,
+        mint: Option<&str>,
+        offline: Option<bool>,
+    ) -> Result<SendResponse> {
+        if nostr.is_some() {
+            bail!("Sending over nostr is not supported by the CDK wallet");
+        }
+
+        let refund = locktime
+            .map(|locktime| {
+                Conditions::new(
+                    Some(u64::try_from(locktime)?),
+                    None,
+                    Some(vec![self.p2pk_key.public_key()]),
+                    None,
+                    None,
+                )
+                .context("Invalid locktime")
+            })
+            .transpose()?;
+        let conditions = lock
+            .map(|key| PublicKey::from_str(key.trim_start_matches("P2PK:")))
+            .transpose()?
+            .map(|key| SpendingConditions::new_p2pk(key, refund));
+        let options = SendOptions {
+            conditions,
+            send_kind: if offline == Some(true) {
+                SendKind::OfflineExact
+            } else {
+                SendKind::OnlineExact
+            },
+            ..Default::default()
+        };
+
+        let wallet = self.paying_wallet(mint).await?;
+        let prepared = wallet.prepare_send(cdk_amount(amount)?, options).await?;
+        let token = wallet.send(prepared, None).await?;
+
+        Ok(SendResponse {
+            balance: self.total_balance().await?,
+            token: token.to_string(),
+            npub: None,
+        })
+    }
+
+    /// Tokens from mints the wallet doesn't know yet are accepted and the
+    /// mint is added. Tokens locked to the wallet's P2PK key are unlocked.
+    #[tracing::instrument(name = "wallet.receive", skip(self, token), err)]
+    async fn receive(
+        &self,
+        token: Option<&str>,
+        nostr: Option<bool>,
+        _all: Option<bool>,
+    ) -> Result<ReceiveResponse> {
+        if nostr == Some(true) {
+            bail!("Receiving over nostr is not supported by the CDK wallet");
+        }
+        let token = self.sign_refunds(token.ok_or_else(|| anyhow!("A token is required"))?)?;
+        let token = token.as_str();
+        let mint_url = Token::from_str(token)?.mint_url()?;
+
+        let initial_balance = self.total_balance().await?;
+        self.ensure_wallet(&mint_url).await?;
+        let amount = self
+            .wallet
+            .receive(
+                token,
+                ReceiveOptions {
+                    p2pk_signing_keys: vec![self.p2pk_key.clone()],
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok(ReceiveResponse {
+            initial_balance,
+            balance: self.total_balance().await?,
+            amount: sats(amount),
+        })
+    }
+
+    /// Spent proofs the wallet still holds as pending are dropped on the way.
+    async fn token_state(&self, token: &str) -> Result<TokenState> {
+        let token = Token::from_str(token)?;
+        let wallet = self.ensure_wallet(&token.mint_url()?).await?;
+        let proofs = token.proofs();
+        let states = wallet.check_proofs_spent(proofs.clone()).await?;
+
+        let mut summary = TokenState::default();
+        for (proof, state) in proofs.iter().zip(states) {
+            let amount = sats(proof.amount);
+            match state.state {
+                State::Spent => summary.spent += amount,
+                State::Pending => summary.pending += amount,
+                _ => summary.unspent += amount,
+            }
+        }
+        Ok(summary)
+    }
+
+    async fn verify_dleq(&self, token: &str) -> Result<DleqStatus> {
+        let token = Token::from_str(token)?;
+        let wallet = self.ensure_wallet(&token.mint_url()?).await?;
+
+        let mut keysets = HashMap::new();
+        let mut status = DleqStatus::Valid;
+        for proof in token.proofs() {
+            if proof.dleq.is_none() {
+                status = DleqStatus::Missing;
+                continue;
+            }
+            let keys = match keysets.entry(proof.keyset_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(wallet.get_keyset_keys(proof.keyset_id).await?)
+                }
+            };
+            let holds = keys
+                .amount_key(proof.amount)
+                .is_some_and(|key| proof.verify_dleq(key).is_ok());
+            if !holds {
+                return Ok(DleqStatus::Invalid);
+            }
+        }
+        Ok(status)
+    }
+
+    /// Asks the mints which proofs are spent and drops them. Without a token
+    /// only pending proofs are checked, `force` checks unspent ones as well.
+    async fn burn(
+        &self,
+        token: Option<&str>,
+        all: Option<bool>,
+        force: Option<bool>,
+        delete: Option<&str>,
+        mint: Option<&str>,
+    ) -> Result<BurnResponse> {
+        if delete.is_some() {
+            bail!("Deleting pending sends is not supported by the CDK wallet");
+        }
+
+        if let Some(token) = token {
+            let token = Token::from_str(token)?;
+            let wallet = self
+                .wallet_for(Some(&token.mint_url()?.to_string()))
+                .await?;
+            wallet.check_proofs_spent(token.proofs()).await?;
+        } else {
+            let wallets = match mint {
+                Some(_) if all != Some(true) => vec![self.wallet_for(mint).await?],
+                _ => self.wallet.get_wallets().await,
+            };
+            for wallet in wallets {
+                wallet.check_all_pending_proofs().await?;
+                if force == Some(true) {
+                    wallet
+                        .check_proofs_spent(wallet.get_unspent_proofs().await?)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(BurnResponse {
+            balance: self.total_balance().await?,
+        })
+    }
+
+    /// Pending proofs summed up per mint.
+    async fn pending(&self, number: Option<i64>, offset: Option<i64>) -> Result<PendingResponse> {
+        let mut pending = Vec::new();
+        for wallet in self.wallet.get_wallets().await {
+            let proofs = wallet.get_pending_proofs().await?;
+            if proofs.is_empty() {
+                continue;
+            }
+            pending.push((
+                wallet.mint_url.to_string(),
+                json!({
+                    "mint": wallet.mint_url.to_string(),
+                    "amount": sats(proofs.total_amount()?),
+                    "proofs": proofs.len(),
+                }),
+            ));
+        }
+        pending.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let offset = offset.unwrap_or(0).max(0) as usize;
+        let number = number.map_or(usize::MAX, |n| n.max(0) as usize);
+
+        Ok(PendingResponse {
+            pending_token: pending.into_iter().skip(offset).take(number).collect(),
+        })
+    }
+
+    /// The wallet has a single lock, the public key of its P2PK key.
+    async fn lock(&self) -> Result<LockResponse> {
+        Ok(LockResponse {
+            p2pk: Some(format!("P2PK:{}", self.p2pk_key.public_key().to_hex())),
+        })
+    }
+
+    async fn locks(&self) -> Result<LocksResponse> {
+        Ok(LocksResponse {
+            locks: vec![format!("P2PK:{}", self.p2pk_key.public_key().to_hex())],
+        })
+    }
+
+    /// The CDK store can't list melt quotes, so only mint quotes are returned.
+    async fn invoices(&self) -> Result<InvoicesResponse> {
+        let mint_quotes = self
+            .wallet
+            .localstore
+            .get_mint_quotes()
+            .await?
+            .into_iter()
+            // CDK doesn't record when a quote was created.
+            .map(|quote| mint_quote(quote, 0))
+            .collect();
+
+        Ok(InvoicesResponse {
+            mint_quotes,
+            melt_quotes: Vec::new(),
+        })
+    }
+
+    async fn wallets(&self) -> Result<WalletsResponse> {
+        Ok(WalletsResponse {
+            wallets: self
+                .balances()
+                .await?
+                .into_iter()
+                .map(|(mint, balance)| (mint, json!({ "balance": balance, "unit": "sat" })))
+                .collect(),
+        })
+    }
+
+    /// NUT-09 restore on every mint. `to` is a nutshell counter and is
+    /// ignored, CDK scans until it finds an empty batch.
+    async fn restore(&self, _to: i64) -> Result<RestoreResponse> {
+        for wallet in self.wallet.get_wallets().await {
+            let restored = wallet.restore().await?;
+            tracing::info!("Restored {} sat from {}", restored, wallet.mint_url);
+        }
+
+        Ok(RestoreResponse {
+            balance: self.total_balance().await?,
+        })
+    }
+
+    async fn info(&self) -> Result<InfoResponse> {
+        let mint_urls = self
+            .wallet
+            .get_wallets()
+            .await
+            .into_iter()
+            .map(|wallet| wallet.mint_url.to_string())
+            .collect();
+
+        Ok(InfoResponse {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            wallet: "cdk".to_string(),
+            debug: false,
+            cashu_dir: String::new(),
+            mint_urls,
+            settings: None,
+            tor: false,
+            nostr_public_key: None,
+            nostr_relays: Vec::new(),
+            socks_proxy: None,
+        })
+    }
+}
@@ -1,6 +1,6 @@
 use super::base::CashuWalletApi;
 use super::models::*;
-use anyhow::Result;
+use anyhow::{Result, bail};
 use reqwest::Client;
 
 #[derive(Clone)]
@@ -29,6 +29,10 @@ impl CashuWalletApi for CashuWalletClient {
         Ok(response.json().await?)
     }
 
+    async fn melt_quote(&self, _bolt11: &str, _mint: Option<&str>) -> Result<MeltQuote> {
+        bail!("Melt quotes are not available through the nutshell API")
+    }
+
     async fn payment_state(
         &self,
         payment_hash: Option<&str>,
@@ -88,12 +92,18 @@ impl CashuWalletApi for CashuWalletClient {
         Ok(response.json().await?)
     }
 
+    /// Nutshell's `/swap` melts without handing out the quote, so only swaps
+    /// without a fee limit go through.
     async fn swap(
         &self,
         amount: i64,
         outgoing_mint: &str,
         incoming_mint: &str,
+        max_fee: Option<i64>,
     ) -> Result<SwapResponse> {
+        if max_fee.is_some() {
+            bail!("Swaps with a fee limit are not available through the nutshell API");
+        }
         let url = format!(
             "{}/swap?amount={}&outgoing_mint={}&incoming_mint={}",
             self.base_url, amount, outgoing_mint, incoming_mint
@@ -109,11 +119,15 @@ impl CashuWalletApi for CashuWalletClient {
         Ok(response.json().await?)
     }
 
+    /// Nutshell sets the locktime of locked tokens itself, from its
+    /// `LOCKTIME_DELTA_SECONDS`.
+    #[tracing::instrument(name = "wallet.send", skip(self), err)]
     async fn send(
         &self,
         amount: i64,
         nostr: Option<&str>,
         lock: Option<&str>,
+        _locktime: Option<i64>,
         mint: Option<&str>,
         offline: Option<bool>,
     ) -> Result<SendResponse> {
@@ -124,7 +138,9 @@ impl CashuWalletApi for CashuWalletClient {
         }
 
         if let Some(lock_key) = lock {
-            url = format!("{}&lock={}", url, lock_key);
+            // Nutshell only takes keys in its `P2PK:<hex>` form.
+            let lock_key = lock_key.trim_start_matches("P2PK:");
+            url = format!("{}&lock=P2PK:{}", url, lock_key);
         }
 
         if let Some(mint_url) = mint {
@@ -139,6 +155,7 @@ impl CashuWalletApi for CashuWalletClient {
         Ok(response.json().await?)
     }
 
+    #[tracing::instrument(name = "wallet.receive", skip(self, token), err)]
     async fn receive(
         &self,
         token: Option<&str>,
@@ -171,7 +188,18 @@ impl CashuWalletApi for CashuWalletClient {
         }
 
         let response = self.client.post(&url).send().await?;
-        Ok(response.json().await?)
+        let mut received: ReceiveResponse = response.json().await?;
+        // Nutshell only reports balances, taken around its own receive.
+        received.amount = received.balance - received.initial_balance;
+        Ok(received)
+    }
+
+    async fn token_state(&self, _token: &str) -> Result<TokenState> {
+        bail!("Token states are not available through the nutshell API")
+    }
+
+    async fn verify_dleq(&self, _token: &str) -> Result<DleqStatus> {
+        bail!("DLEQ proofs can't be verified through the nutshell API")
     }
 
     async fn burn(
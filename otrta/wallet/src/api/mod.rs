@@ -1,7 +1,11 @@
+pub mod backend;
 pub mod base;
+pub mod cdk;
 pub mod client;
 pub mod models;
 
+pub use backend::WalletBackend;
 pub use base::CashuWalletApi;
+pub use cdk::CdkWallet;
 pub use client::CashuWalletClient;
 pub use models::*;
@@ -90,10 +90,36 @@ pub struct SendResponse {
     pub npub: Option<String>,
 }
 
+/// What the mint says about the proofs of a token (NUT-07), summed up in
+/// sats.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TokenState {
+    pub unspent: i64,
+    pub pending: i64,
+    pub spent: i64,
+}
+
+/// Outcome of checking the DLEQ proofs (NUT-12) of a token against the keys
+/// of its mint.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DleqStatus {
+    /// Every proof carries a DLEQ proof that holds.
+    Valid,
+    /// Some proofs carry none.
+    Missing,
+    /// Some proof carries one that doesn't hold.
+    Invalid,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ReceiveResponse {
     pub initial_balance: i64,
     pub balance: i64,
+    /// Sats the token brought in, after fees. Unlike the balances, it is not
+    /// affected by other calls running at the same time.
+    #[serde(default)]
+    pub amount: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,4 +1,5 @@
 use std::env;
+use std::path::Path;
 use std::time::Duration;
 
 use cdk::Amount;
@@ -12,9 +13,10 @@ use wallet::wallet::wallet;
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let words = env::var("OTRTA_SEED").unwrap();
+    let store_path = env::var("OTRTA_WALLET_PATH").unwrap_or_else(|_| "wallet.redb".into());
 
     let mint_url = "https://testnut.cashu.space";
-    let wallet = wallet(mint_url, &words);
+    let wallet = wallet(mint_url, &words, Path::new(&store_path))?;
 
     let amount = Amount::from(1000);
     let quote = wallet.mint_quote(amount, None).await?;
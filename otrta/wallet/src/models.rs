@@ -83,6 +83,12 @@ pub struct ImageGenerationRequest {
 pub struct ServerConfig {
     pub endpoint: String,
     pub api_key: String,
+    /// Mints the provider takes tokens from. Empty when it takes any.
+    #[serde(default)]
+    pub accepted_mints: Vec<String>,
+    /// Key the provider advertises for payments to be locked to (NUT-11).
+    #[serde(default)]
+    pub p2pk_pubkey: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -99,6 +105,20 @@ pub struct OpenAIModelObject {
     pub features: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<i32>,
+    /// What a provider in provider mode charges for the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<ModelPricing>,
+}
+
+/// Rates of a model. Per-token rates are given per thousand tokens so cheap
+/// models can be priced below a millisat per token.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub prompt_msat_per_1k: i64,
+    pub completion_msat_per_1k: i64,
+    /// Flat fee added to every request.
+    #[serde(default)]
+    pub request_msat: i64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
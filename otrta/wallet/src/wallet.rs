@@ -1,29 +1,54 @@
-use std::{str::FromStr, sync::Arc};
+use std::{fs, path::Path, str::FromStr, sync::Arc};
 
+use anyhow::Result;
 use bip39::Mnemonic;
 use cashu::MintUrl;
 use cdk::wallet::{HttpClient, Wallet, WalletBuilder};
 use cdk_redb::WalletRedbDatabase;
 
-pub fn prepare_seed(seed: &str) -> [u8; 64] {
-    Mnemonic::from_str(&seed).unwrap().to_seed_normalized("")
+use crate::api::CdkWallet;
+
+pub fn prepare_seed(seed: &str) -> Result<[u8; 64]> {
+    Ok(Mnemonic::from_str(seed)?.to_seed_normalized(""))
+}
+
+/// A fresh 12 word BIP39 mnemonic.
+pub fn generate_mnemonic() -> Result<String> {
+    let entropy = rand::random::<[u8; 16]>();
+    Ok(Mnemonic::from_entropy(&entropy)?.to_string())
+}
+
+/// Opens the redb store at `path`, creating the file and its directory on
+/// first use.
+fn open_store(path: &Path) -> Result<Arc<WalletRedbDatabase>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(Arc::new(WalletRedbDatabase::new(path)?))
 }
 
-pub fn wallet(mint_url: &str, _seed: &str) -> Wallet {
-    // let seed = prepare_seed(seed);
-    let rand = rand::random::<[u8; 32]>();
-    let file = tempfile::NamedTempFile::new().unwrap();
-    let redb_store = Arc::new(WalletRedbDatabase::new(file.path()).unwrap());
+pub fn wallet(mint_url: &str, seed: &str, store_path: &Path) -> Result<Wallet> {
+    let seed = prepare_seed(seed)?;
+    let redb_store = open_store(store_path)?;
 
-    let mint_url = MintUrl::from_str(mint_url).unwrap();
+    let mint_url = MintUrl::from_str(mint_url)?;
     let mut builder = WalletBuilder::new()
         .mint_url(mint_url.clone())
         .unit(cdk::nuts::CurrencyUnit::Sat)
         .localstore(redb_store.clone())
-        .seed(&rand);
+        .seed(&seed);
 
     let http_client = HttpClient::new(mint_url, None);
     builder = builder.client(http_client);
 
-    builder.build().unwrap()
+    Ok(builder.build()?)
+}
+
+/// Builds the in-process wallet used by the CDK backend. Secrets are derived
+/// from `seed` (NUT-13), so funds can be restored from the mnemonic alone.
+pub async fn cdk_wallet(mint_urls: &[String], seed: &str, store_path: &Path) -> Result<CdkWallet> {
+    let seed = prepare_seed(seed)?;
+    let redb_store = open_store(store_path)?;
+
+    CdkWallet::new(mint_urls, redb_store, &seed).await
 }
@@ -191,7 +191,9 @@ export function CreditsMonitor({
                 >
                   {credit.token}
                 </TableCell>
-                <TableCell className='font-medium'>{credit.amount}</TableCell>
+                <TableCell className='font-medium'>
+                  {credit.amount_msat / 1000}
+                </TableCell>
                 <TableCell>
                   {credit.redeemed ? (
                     <Badge
@@ -177,7 +177,7 @@ export function TransactionsMonitor({
         <Table>
           <TableHeader>
             <TableRow>
-              <TableHead>Token</TableHead>
+              <TableHead>Token Fingerprint</TableHead>
               <TableHead>Direction</TableHead>
               <TableHead>Amount (sats)</TableHead>
               <TableHead>Created At</TableHead>
@@ -190,12 +190,20 @@ export function TransactionsMonitor({
                   <HoverCard>
                     <HoverCardTrigger asChild>
                       <div className='text-primary cursor-pointer truncate font-mono text-sm'>
-                        {transaction.token}
+                        {transaction.token_fingerprint}
                       </div>
                     </HoverCardTrigger>
                     <HoverCardContent className='bg-card/80 border-primary/20 w-[400px] border p-4 backdrop-blur-sm'>
                       <div className='text-primary bg-muted/30 max-h-[200px] overflow-auto rounded-md p-2 font-mono text-xs'>
-                        {transaction.token}
+                        <div>{transaction.token_fingerprint}</div>
+                        {transaction.mint_url && (
+                          <div className='mt-2'>Mint: {transaction.mint_url}</div>
+                        )}
+                        {transaction.keyset_ids.length > 0 && (
+                          <div className='mt-1'>
+                            Keysets: {transaction.keyset_ids.join(', ')}
+                          </div>
+                        )}
                       </div>
                     </HoverCardContent>
                   </HoverCard>
@@ -209,7 +217,7 @@ export function TransactionsMonitor({
                   </div>
                 </TableCell>
                 <TableCell className='font-semibold'>
-                  {transaction.amount}
+                  {transaction.amount_msat / 1000}
                 </TableCell>
                 <TableCell>
                   {new Date(transaction.created_at).toLocaleString()}
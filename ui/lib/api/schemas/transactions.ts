@@ -3,9 +3,13 @@ import { z } from 'zod';
 export const TransactionSchema = z.object({
   id: z.string(),
   created_at: z.string().datetime(),
-  token: z.string(),
-  amount: z.string(),
+  token_fingerprint: z.string(),
+  mint_url: z.string().nullable(),
+  keyset_ids: z.array(z.string()),
+  amount_msat: z.number().int(),
+  unit: z.string(),
   direction: z.enum(['Incoming', 'Outgoing']),
+  request_id: z.string().nullable(),
 });
 
 export const TransactionListParamsSchema = z.object({
@@ -21,6 +25,7 @@ export const TransactionListResponseSchema = z.object({
     page_size: z.number(),
     total_pages: z.number(),
   }),
+  next_cursor: z.string().nullable(),
 });
 
 export type Transaction = z.infer<typeof TransactionSchema>;
@@ -4,7 +4,9 @@ export interface Credit {
   id: string;
   created_at: string;
   token: string;
-  amount: string;
+  amount_msat: number;
+  unit: string;
+  mint_url: string | null;
   redeemed: boolean;
 }
 